@@ -0,0 +1,93 @@
+//! Asynchronous bulk jobs for providers with a batch API (currently OpenAI).
+//!
+//! Requests are submitted together, run by the provider within a day at a
+//! discount, and collected once the batch completes. See
+//! `Aegis::batch_submit`.
+
+use std::fmt;
+
+use crate::{error::AegisError, models::Message, options::SendOptions};
+
+/// One request in a batch, matched back to its result by `custom_id`.
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    /// Caller-chosen ID, unique within the batch.
+    pub custom_id: String,
+    pub messages: Vec<Message>,
+    pub options: SendOptions,
+}
+
+impl BatchRequest {
+    pub fn new(custom_id: impl Into<String>, messages: Vec<Message>) -> Self {
+        Self {
+            custom_id: custom_id.into(),
+            messages,
+            options: SendOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: SendOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// Provider-assigned ID of a submitted batch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BatchId(pub String);
+
+impl fmt::Display for BatchId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Where a batch is in its lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchStatus {
+    Validating,
+    InProgress,
+    Finalizing,
+    Completed,
+    Failed,
+    Expired,
+    Cancelling,
+    Cancelled,
+    /// A status we don't recognise, as the provider sent it.
+    Other(String),
+}
+
+impl BatchStatus {
+    pub fn from_provider(status: &str) -> Self {
+        match status {
+            "validating" => BatchStatus::Validating,
+            "in_progress" => BatchStatus::InProgress,
+            "finalizing" => BatchStatus::Finalizing,
+            "completed" => BatchStatus::Completed,
+            "failed" => BatchStatus::Failed,
+            "expired" => BatchStatus::Expired,
+            "cancelling" => BatchStatus::Cancelling,
+            "cancelled" => BatchStatus::Cancelled,
+            other => BatchStatus::Other(other.to_string()),
+        }
+    }
+
+    /// Whether the batch has stopped changing. Only `Completed` has results,
+    /// though `Expired` and `Cancelled` batches may have partial ones.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            BatchStatus::Completed
+                | BatchStatus::Failed
+                | BatchStatus::Expired
+                | BatchStatus::Cancelled
+        )
+    }
+}
+
+/// The outcome of one request in a finished batch.
+#[derive(Debug)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: Result<Message, AegisError>,
+}
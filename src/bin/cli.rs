@@ -1,14 +1,16 @@
 use aegis::{
     config::AegisConfig,
-    models::{Message, ProviderType},
+    models::{Content, ContentPart, Message, ProviderType, Role},
+    options::SendOptions,
     Aegis,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Input, Select};
 use futures::StreamExt;
-use std::{fs, process::exit};
+use std::{fs, path::{Path, PathBuf}, process::exit};
 
 #[derive(Parser)]
 #[command(name = "aegis")]
@@ -28,7 +30,7 @@ enum Commands {
     },
     /// Chat with AI models
     Chat {
-        /// Select AI provider (anthropic/openai)
+        /// Select AI provider (anthropic/openai/cohere/mistral/xai/gemini)
         #[arg(short, long)]
         provider: Option<String>,
 
@@ -39,6 +41,10 @@ enum Commands {
         /// Model to use (e.g., claude-3-sonnet, gpt-4)
         #[arg(short, long)]
         model: Option<String>,
+
+        /// Attach a local image file (repeat for multiple images)
+        #[arg(short, long)]
+        image: Vec<PathBuf>,
     },
 }
 
@@ -46,8 +52,12 @@ fn load_config() -> Result<AegisConfig> {
     dotenv::dotenv().ok();
 
     let config = AegisConfig::new()
-        .with_anthropic(std::env::var("ANTHROPIC_API_KEY").unwrap())
-        .with_openai(std::env::var("OPENAI_API_KEY").unwrap());
+        .with_anthropic(std::env::var("ANTHROPIC_API_KEY").unwrap_or_default())
+        .with_openai(std::env::var("OPENAI_API_KEY").unwrap_or_default())
+        .with_cohere(std::env::var("COHERE_API_KEY").unwrap_or_default())
+        .with_mistral(std::env::var("MISTRAL_API_KEY").unwrap_or_default())
+        .with_xai(std::env::var("XAI_API_KEY").unwrap_or_default())
+        .with_gemini(std::env::var("GEMINI_API_KEY").unwrap_or_default());
 
     Ok(config)
 }
@@ -88,8 +98,9 @@ async fn main() -> Result<()> {
         Commands::Chat {
             provider,
             content: message,
-            model
-        } => handle_chat(provider, message, model).await?
+            model,
+            image,
+        } => handle_chat(provider, message, model, image).await?
     }
 
     Ok(())
@@ -104,6 +115,14 @@ fn handle_config(show: bool) -> Result<()> {
                     println!("Anthropic API Key: {}", "[SET]".green());
                 } else if line.starts_with("OPENAI_API_KEY=") {
                     println!("OpenAI API Key: {}", "[SET]".green());
+                } else if line.starts_with("COHERE_API_KEY=") {
+                    println!("Cohere API Key: {}", "[SET]".green());
+                } else if line.starts_with("MISTRAL_API_KEY=") {
+                    println!("Mistral API Key: {}", "[SET]".green());
+                } else if line.starts_with("XAI_API_KEY=") {
+                    println!("xAI API Key: {}", "[SET]".green());
+                } else if line.starts_with("GEMINI_API_KEY=") {
+                    println!("Gemini API Key: {}", "[SET]".green());
                 }
             }
         }
@@ -111,7 +130,7 @@ fn handle_config(show: bool) -> Result<()> {
     }
 
     let theme = ColorfulTheme::default();
-    let providers = vec!["Anthropic API Key", "OpenAI API Key"];
+    let providers = vec!["Anthropic API Key", "OpenAI API Key", "Cohere API Key", "Mistral API Key", "xAI API Key", "Gemini API Key"];
 
     let selection = Select::with_theme(&theme)
         .with_prompt("Select provider to configure")
@@ -126,6 +145,10 @@ fn handle_config(show: bool) -> Result<()> {
     let env_key = match selection {
         0 => "ANTHROPIC_API_KEY",
         1 => "OPENAI_API_KEY",
+        2 => "COHERE_API_KEY",
+        3 => "MISTRAL_API_KEY",
+        4 => "XAI_API_KEY",
+        5 => "GEMINI_API_KEY",
         _ => unreachable!(),
     };
 
@@ -138,6 +161,7 @@ async fn handle_chat(
     provider: Option<String>,
     message: Option<String>,
     model: Option<String>,
+    image_paths: Vec<PathBuf>,
 ) -> Result<()> {
     let config = load_config()?;
     if config.is_empty() {
@@ -149,6 +173,10 @@ async fn handle_chat(
     let provider_type = match provider.as_deref().unwrap_or("anthropic") {
         "anthropic" => ProviderType::Anthropic,
         "openai" => ProviderType::OpenAI,
+        "cohere" => ProviderType::Cohere,
+        "mistral" => ProviderType::Mistral,
+        "xai" => ProviderType::Xai,
+        "gemini" => ProviderType::Gemini,
         _ => {
             println!("{}", "Invalid provider. Using Anthropic as default.".yellow());
             exit(3)
@@ -160,16 +188,72 @@ async fn handle_chat(
         println!("{} {}", "Model:".blue(), model);
     }
 
+    let images = image_paths
+        .iter()
+        .map(|path| load_image(path))
+        .collect::<Result<Vec<_>>>()?;
+    if !images.is_empty() {
+        if message.is_none() {
+            anyhow::bail!("--image requires --content");
+        }
+        let capabilities = aegis.capabilities(provider_type.clone())?;
+        if !capabilities.supported_content_types.iter().any(|t| t == "image") {
+            anyhow::bail!("Provider {:?} does not support image input", provider_type);
+        }
+        println!("{} {}", "Images:".blue(), images.len());
+    }
+
+    // Without --model each provider uses its default
+    let mut options = SendOptions::new();
+    options.model = model;
+
     // Decision point: Use streaming or regular chat
     if needs_streaming(&message) {
-        handle_streaming_chat(&aegis, provider_type, message).await?;
+        handle_streaming_chat(&aegis, provider_type, message, images, &options).await?;
     } else {
-        handle_regular_chat(&aegis, provider_type, message).await?;
+        handle_regular_chat(&aegis, provider_type, message, images, &options).await?;
     }
 
     Ok(())
 }
 
+// Read a local image and encode it as a base64 data URL
+fn load_image(path: &Path) -> Result<ContentPart> {
+    let media_type = match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => anyhow::bail!(
+            "Unsupported image type: {} (expected png, jpg, jpeg, gif or webp)",
+            path.display()
+        ),
+    };
+    let bytes = fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read image {}: {}", path.display(), e))?;
+
+    Ok(ContentPart::image(format!(
+        "data:{};base64,{}",
+        media_type,
+        STANDARD.encode(bytes)
+    )))
+}
+
+fn user_message(text: String, images: Vec<ContentPart>) -> Message {
+    let mut parts = vec![ContentPart::text(text)];
+    parts.extend(images);
+    Message {
+        role: Role::User,
+        content: Content { parts },
+        metadata: None,
+    }
+}
+
 // Helper to determine if streaming is needed
 fn needs_streaming(message: &Option<String>) -> bool {
     match message {
@@ -188,17 +272,15 @@ async fn handle_streaming_chat(
     aegis: &Aegis,
     provider_type: ProviderType,
     message: Option<String>,
+    images: Vec<ContentPart>,
+    options: &SendOptions,
 ) -> Result<()> {
     let mut stream = if let Some(content) = message {
         // One-shot streaming mode
-        let msg = Message {
-            role: aegis::models::Role::User,
-            content: aegis::models::Content {
-                parts: vec![aegis::models::ContentPart::Text { text: content }],
-            },
-            metadata: None
-        };
-        aegis.stream_message(provider_type, vec![msg]).await?
+        let msg = user_message(content, images);
+        aegis
+            .stream_message_with_options(provider_type, vec![msg], options)
+            .await?
     } else {
         // Interactive streaming mode
         println!("{}", "\nStarting interactive chat session (type 'exit' to quit)".yellow());
@@ -208,14 +290,10 @@ async fn handle_streaming_chat(
                 break;
             }
 
-            let msg = Message{
-                role: aegis::models::Role::User,
-                content: aegis::models::Content {
-                    parts: vec![aegis::models::ContentPart::Text { text: input }],
-                },
-                metadata: None
-            };
-            let mut stream = aegis.stream_message(provider_type.clone(), vec![msg]).await?;
+            let msg = user_message(input, Vec::new());
+            let mut stream = aegis
+                .stream_message_with_options(provider_type.clone(), vec![msg], options)
+                .await?;
 
             println!("\n{}", "Assistant:".green());
             while let Some(chunk) = stream.next().await {
@@ -249,17 +327,13 @@ async fn handle_regular_chat(
     aegis: &Aegis,
     provider_type: ProviderType,
     message: Option<String>,
+    images: Vec<ContentPart>,
+    options: &SendOptions,
 ) -> Result<()> {
     let content = message.ok_or_else(|| anyhow::anyhow!("Message content required for regular chat"))?;
-    let msg = Message {
-        role: aegis::models::Role::User,
-        content: aegis::models::Content {
-            parts: vec![aegis::models::ContentPart::Text { text: content }],
-        },
-        metadata: None,
-    };
+    let msg = user_message(content, images);
     
-    match aegis.send_message(provider_type, vec![msg]).await {
+    match aegis.send_message_with_options(provider_type, vec![msg], options).await {
         Ok(response) => println!("\n{}: {}\n", "Assistant".green(), response.content),
         Err(e) => println!("\n{}: {}", "Error".red(), e),
     }
@@ -1,8 +1,172 @@
+use std::{collections::HashMap, time::Duration};
+
+use tracing::warn;
+
+use crate::{
+    cost::UsageTracker,
+    error::AegisError,
+    models::{ContentPart, Message, ProviderType},
+    options::SendOptions,
+    providers::{anthropic::DEFAULT_ANTHROPIC_VERSION, mock::MockProvider},
+    injection::InjectionGuard,
+    redaction::RedactionPolicy,
+    registry::{ModelRegistry, ModelSpec},
+    retry::RetryPolicy,
+};
+
+const IMAGE_PLACEHOLDER: &str = "[image omitted]";
+
+/// What to do with image parts when the provider can't take images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFallback {
+    /// Fail the request with [`AegisError::Unsupported`].
+    #[default]
+    Error,
+    /// Replace each image with its caption, or `[image omitted]`, so the
+    /// text still gets answered.
+    Placeholder,
+}
+
+impl ImageFallback {
+    /// Apply the policy to `messages` bound for a provider without vision.
+    pub fn apply(&self, messages: &mut [Message]) -> Result<(), AegisError> {
+        for part in messages.iter_mut().flat_map(|m| m.content.parts.iter_mut()) {
+            if let ContentPart::Image { caption, .. } = part {
+                match self {
+                    ImageFallback::Error => {
+                        return Err(AegisError::Unsupported(
+                            "provider does not accept image input".to_string(),
+                        ))
+                    }
+                    ImageFallback::Placeholder => {
+                        let text = caption.take().unwrap_or_else(|| IMAGE_PLACEHOLDER.to_string());
+                        *part = ContentPart::text(text);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What to do with sampling parameters the requested model doesn't accept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplingPolicy {
+    /// Drop parameters the model rejects and clamp out-of-range values,
+    /// logging a warning.
+    #[default]
+    Adjust,
+    /// Fail the request with [`AegisError::Unsupported`].
+    Error,
+}
+
+impl SamplingPolicy {
+    /// Fit the sampling parameters in `options` to what `spec` accepts.
+    pub fn apply(
+        &self,
+        model: &str,
+        spec: &ModelSpec,
+        options: &mut SendOptions,
+    ) -> Result<(), AegisError> {
+        let Some(temperature) = options.generation.temperature else {
+            return Ok(());
+        };
+        let fitted = spec
+            .temperature
+            .as_ref()
+            .map(|range| temperature.clamp(*range.start(), *range.end()));
+        if fitted == Some(temperature) {
+            return Ok(());
+        }
+        if *self == SamplingPolicy::Error {
+            return Err(AegisError::Unsupported(match fitted {
+                Some(_) => format!("temperature {} is out of range for {}", temperature, model),
+                None => format!("{} does not accept a temperature", model),
+            }));
+        }
+        match fitted {
+            Some(fitted) => warn!("Clamping temperature {} to {} for {}", temperature, fitted, model),
+            None => warn!("Dropping temperature {} for {}, which rejects it", temperature, model),
+        }
+        options.generation.temperature = fitted;
+        Ok(())
+    }
+}
+
+/// HTTP version the shared client speaks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Let reqwest negotiate: HTTP/2 where the server offers it over TLS,
+    /// HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// Only ever use HTTP/1.1, one stream per connection. Helps when a proxy
+    /// or load balancer stalls or buffers SSE streams multiplexed over
+    /// HTTP/2.
+    Http1,
+    /// Speak HTTP/2 from the start without negotiating, e.g. for an h2c
+    /// gateway. Fails against servers that only speak HTTP/1.1.
+    Http2,
+}
 
 #[derive(Debug, Clone)]
 pub struct AegisConfig {
     pub anthropic_api_key: Option<String>,
     pub openai_api_key: Option<String>,
+    pub cohere_api_key: Option<String>,
+    pub mistral_api_key: Option<String>,
+    pub xai_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    /// Offline providers, each registered as `ProviderType::Custom` under its
+    /// name.
+    pub mocks: Vec<MockProvider>,
+    /// Provider used by `Aegis::send_message_default`. Needed only when more
+    /// than one provider is configured.
+    pub default_provider: Option<ProviderType>,
+    /// Models each provider may be asked for; providers without an entry
+    /// accept any.
+    pub allowed_models: HashMap<ProviderType, Vec<String>>,
+    /// Hosts to send each provider's requests to instead of its official
+    /// endpoint; see `with_base_url`.
+    pub base_urls: HashMap<ProviderType, String>,
+    /// Embedding providers, used by `Aegis::embed`. Voyage wins if both are set.
+    pub voyage_api_key: Option<String>,
+    pub jina_api_key: Option<String>,
+    /// Talk to OpenAI through `/v1/responses` instead of chat completions.
+    pub openai_responses_api: bool,
+    /// Send roles under their own names instead of each provider's mapping.
+    pub verbatim_roles: bool,
+    /// Download remote images and send them inline to Anthropic.
+    pub inline_images: bool,
+    /// Sent as Anthropic's `anthropic-version` header.
+    pub anthropic_version: String,
+    /// Skip TLS certificate verification. Insecure; development only.
+    pub danger_accept_invalid_certs: bool,
+    pub http_version: HttpVersion,
+    /// Idle connections kept per host; reqwest's default (unbounded) when
+    /// `None`.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long an idle connection is kept; reqwest's default (90s) when
+    /// `None`.
+    pub pool_idle_timeout: Option<Duration>,
+    pub redaction: Option<RedactionPolicy>,
+    pub retry: Option<RetryPolicy>,
+    /// Count tokens locally for replies that arrive without usage.
+    pub estimate_missing_usage: bool,
+    /// Spend limit in US dollars; see `with_budget`.
+    pub budget_usd: Option<f64>,
+    /// Also adds each reply's usage here; see `with_usage_tracker`.
+    pub usage_tracker: Option<UsageTracker>,
+    /// Longest a single provider request may take; see `with_timeout`.
+    pub request_timeout: Option<Duration>,
+    /// Longest gap between stream deltas; see `with_stream_idle_timeout`.
+    pub stream_idle_timeout: Option<Duration>,
+    /// Keep per-provider latency histograms for `Aegis::latency_stats`.
+    pub latency_histograms: bool,
+    pub injection: Option<InjectionGuard>,
+    pub image_fallback: ImageFallback,
+    pub sampling: SamplingPolicy,
+    pub models: ModelRegistry,
 }
 
 impl AegisConfig {
@@ -10,6 +174,36 @@ impl AegisConfig {
         Self {
             anthropic_api_key: None,
             openai_api_key: None,
+            cohere_api_key: None,
+            mistral_api_key: None,
+            xai_api_key: None,
+            gemini_api_key: None,
+            mocks: Vec::new(),
+            default_provider: None,
+            allowed_models: HashMap::new(),
+            base_urls: HashMap::new(),
+            voyage_api_key: None,
+            jina_api_key: None,
+            openai_responses_api: false,
+            verbatim_roles: false,
+            inline_images: false,
+            anthropic_version: DEFAULT_ANTHROPIC_VERSION.to_string(),
+            danger_accept_invalid_certs: false,
+            http_version: HttpVersion::Auto,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            redaction: None,
+            retry: None,
+            estimate_missing_usage: false,
+            budget_usd: None,
+            usage_tracker: None,
+            request_timeout: None,
+            stream_idle_timeout: None,
+            latency_histograms: false,
+            injection: None,
+            image_fallback: ImageFallback::Error,
+            sampling: SamplingPolicy::Adjust,
+            models: ModelRegistry::default(),
         }
     }
 
@@ -23,8 +217,239 @@ impl AegisConfig {
         self
     }
 
+    pub fn with_cohere(mut self, key: String) -> Self {
+        self.cohere_api_key = if key.is_empty() { None } else { Some(key) };
+        self
+    }
+
+    pub fn with_mistral(mut self, key: String) -> Self {
+        self.mistral_api_key = if key.is_empty() { None } else { Some(key) };
+        self
+    }
+
+    pub fn with_xai(mut self, key: String) -> Self {
+        self.xai_api_key = if key.is_empty() { None } else { Some(key) };
+        self
+    }
+
+    pub fn with_gemini(mut self, key: String) -> Self {
+        self.gemini_api_key = if key.is_empty() { None } else { Some(key) };
+        self
+    }
+
+    /// Add an offline provider that replies without network access, for
+    /// testing code built on Aegis.
+    pub fn with_mock(mut self, mock: MockProvider) -> Self {
+        self.mocks.push(mock);
+        self
+    }
+
+    /// Send to `provider` when the caller doesn't name one. Without this,
+    /// `Aegis::send_message_default` only works with a single provider.
+    pub fn with_default_provider(mut self, provider: ProviderType) -> Self {
+        self.default_provider = Some(provider);
+        self
+    }
+
+    /// Only let requests to `provider` name one of `models` in
+    /// `SendOptions::model`; others fail with [`AegisError::ModelNotAllowed`]
+    /// before anything is sent. Requests that name no model get the
+    /// provider's default. An empty list lifts the restriction.
+    pub fn with_allowed_models(mut self, provider: ProviderType, models: Vec<String>) -> Self {
+        self.allowed_models.insert(provider, models);
+        self
+    }
+
+    /// Send `provider`'s requests to `base_url`, e.g. an internal gateway or
+    /// a mock server, instead of its official endpoint. Request paths such
+    /// as `/v1/chat/completions` are appended to it, so leave them out.
+    pub fn with_base_url(mut self, provider: ProviderType, base_url: impl Into<String>) -> Self {
+        self.base_urls.insert(provider, base_url.into());
+        self
+    }
+
+    pub fn with_voyage(mut self, key: String) -> Self {
+        self.voyage_api_key = if key.is_empty() { None } else { Some(key) };
+        self
+    }
+
+    pub fn with_jina(mut self, key: String) -> Self {
+        self.jina_api_key = if key.is_empty() { None } else { Some(key) };
+        self
+    }
+
+    /// Use OpenAI's Responses API, which supports `previous_response_id`.
+    pub fn with_openai_responses_api(mut self, enabled: bool) -> Self {
+        self.openai_responses_api = enabled;
+        self
+    }
+
+    /// Pass message roles through verbatim (`tool` stays `tool`, and so on)
+    /// rather than remapping them per provider. A debugging aid; providers may
+    /// reject roles they don't know.
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    /// Fetch `http(s)` image URLs and send them to Anthropic as base64 data,
+    /// for deployments where Anthropic can't reach the image host.
+    pub fn with_inline_images(mut self, enabled: bool) -> Self {
+        self.inline_images = enabled;
+        self
+    }
+
+    /// Accept any TLS certificate, including self-signed and expired ones,
+    /// e.g. for a local HTTPS gateway during development.
+    ///
+    /// **Insecure:** anyone on the network path can read and alter requests,
+    /// API keys included. Never enable this in production.
+    pub fn with_danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// Choose the HTTP version for every provider request. Forcing
+    /// [`HttpVersion::Http1`] is the usual fix when streams stall or arrive
+    /// all at once behind a proxy that mishandles HTTP/2.
+    pub fn with_http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    /// Keep at most `max` idle connections per provider host in the shared
+    /// pool. More lets bursts reuse warm connections instead of paying for
+    /// TLS setup again, at the cost of sockets and memory held while idle;
+    /// `0` disables reuse.
+    pub fn with_pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Close pooled connections after `timeout` unused. Longer keeps
+    /// connections warm across quiet spells (see `Aegis::warmup`) but risks
+    /// reusing one a proxy or the provider has already dropped.
+    pub fn with_pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Pin a different Anthropic API version, e.g. to reach features only
+    /// newer versions expose.
+    pub fn with_anthropic_version(mut self, version: impl Into<String>) -> Self {
+        self.anthropic_version = version.into();
+        self
+    }
+
+    /// Mask personal data in logged (and optionally sent) message text.
+    pub fn with_redaction(mut self, policy: RedactionPolicy) -> Self {
+        self.redaction = Some(policy);
+        self
+    }
+
+    /// Record request durations per provider, queryable through
+    /// `Aegis::latency_stats` and `Aegis::ttft_stats`. Off by default.
+    pub fn with_latency_histograms(mut self, enabled: bool) -> Self {
+        self.latency_histograms = enabled;
+        self
+    }
+
+    /// Fill in `Metadata::usage` when the provider leaves it out, as some
+    /// OpenAI-compatible gateways do, so cost tracking and budgets keep
+    /// working. The counts come from [`crate::tokens::estimate_usage`] and
+    /// are marked `estimated`.
+    pub fn with_usage_estimation(mut self, enabled: bool) -> Self {
+        self.estimate_missing_usage = enabled;
+        self
+    }
+
+    /// Cap the instance's total spend, as priced in `Aegis::cost_report`.
+    /// Requests fail with [`AegisError::BudgetExceeded`] once it is reached,
+    /// and a stream is cut off as soon as its reported usage reaches it.
+    ///
+    /// How soon a stream is stopped depends on how often the provider
+    /// reports usage: Anthropic and OpenAI only do so near the end, so a
+    /// single stream can overshoot by up to its own cost. Models without a
+    /// price in the registry count as free.
+    pub fn with_budget(mut self, usd: f64) -> Self {
+        self.budget_usd = Some(usd);
+        self
+    }
+
+    /// Add the usage of every reply, sent or streamed, to `tracker`. Keep a
+    /// clone to read the totals from.
+    pub fn with_usage_tracker(mut self, tracker: UsageTracker) -> Self {
+        self.usage_tracker = Some(tracker);
+        self
+    }
+
+    /// Fail a provider request with `AegisError::Timeout` when it takes longer
+    /// than `timeout`, e.g. on a hung connection. Each retry attempt gets the
+    /// full timeout. It also bounds batch, model-listing and embedding calls.
+    /// For streams it covers opening the stream, up to the response headers;
+    /// the body is bounded by `with_stream_idle_timeout` instead, since a long
+    /// generation can legitimately take minutes.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// End a stream with `AegisError::StreamIdle` when no delta arrives for
+    /// `idle`, e.g. on a connection a proxy silently stalled. Requests can
+    /// override it with `SendOptions::with_stream_idle_timeout`.
+    pub fn with_stream_idle_timeout(mut self, idle: Duration) -> Self {
+        self.stream_idle_timeout = Some(idle);
+        self
+    }
+
+    /// Retry rate limits, 5xx responses and connection failures. Streams are
+    /// only retried before the first byte arrives.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Screen user input and tool results for prompt injection before they
+    /// are sent.
+    pub fn with_injection_guard(mut self, guard: InjectionGuard) -> Self {
+        self.injection = Some(guard);
+        self
+    }
+
+    /// How to send images to providers without vision support. The default,
+    /// [`ImageFallback::Error`], rejects the request.
+    pub fn with_image_fallback(mut self, policy: ImageFallback) -> Self {
+        self.image_fallback = policy;
+        self
+    }
+
+    /// How to handle a temperature the requested model doesn't accept. The
+    /// default, [`SamplingPolicy::Adjust`], drops or clamps it with a warning.
+    pub fn with_sampling_policy(mut self, policy: SamplingPolicy) -> Self {
+        self.sampling = policy;
+        self
+    }
+
+    /// Replace the model table, e.g. with `ModelRegistry::new()` to drop the
+    /// built-in entries.
+    pub fn with_model_registry(mut self, models: ModelRegistry) -> Self {
+        self.models = models;
+        self
+    }
+
+    /// Add or override a single model on top of the current table.
+    pub fn with_model(mut self, model: impl Into<String>, spec: ModelSpec) -> Self {
+        self.models.insert(model, spec);
+        self
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.anthropic_api_key.is_none() && self.openai_api_key.is_none()
+        self.anthropic_api_key.is_none()
+            && self.openai_api_key.is_none()
+            && self.cohere_api_key.is_none()
+            && self.mistral_api_key.is_none()
+            && self.xai_api_key.is_none()
+            && self.gemini_api_key.is_none()
     }
 }
 
@@ -0,0 +1,79 @@
+//! Agreement between providers' answers to the same prompt, for spotting
+//! disagreement on high-stakes extraction. See `Aegis::consensus`.
+
+use std::collections::HashSet;
+
+use crate::models::{Message, ProviderType};
+
+/// One provider's reply to a consensus request.
+#[derive(Debug, Clone)]
+pub struct ConsensusResponse {
+    pub provider_type: ProviderType,
+    /// The reply's text, as compared by the similarity function.
+    pub text: String,
+    pub message: Message,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsensusResult {
+    /// Mean similarity over every pair of responses, from 0 (no agreement)
+    /// to 1 (identical). A single response scores 1.
+    pub score: f64,
+    pub responses: Vec<ConsensusResponse>,
+}
+
+/// The default similarity: the Jaccard overlap of the two texts'
+/// lowercased word sets. Two empty texts are identical.
+pub fn token_overlap(a: &str, b: &str) -> f64 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// Mean of `similarity` over every unordered pair of `texts`.
+pub(crate) fn mean_pairwise(texts: &[&str], similarity: impl Fn(&str, &str) -> f64) -> f64 {
+    let mut total = 0.0;
+    let mut pairs = 0;
+    for (i, a) in texts.iter().enumerate() {
+        for b in &texts[i + 1..] {
+            total += similarity(a, b);
+            pairs += 1;
+        }
+    }
+    if pairs == 0 {
+        1.0
+    } else {
+        total / pairs as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_overlap_ignores_case_and_punctuation() {
+        assert_eq!(token_overlap("Hanoi.", "hanoi"), 1.0);
+        assert_eq!(token_overlap("The capital is Hanoi", "Hanoi"), 0.25);
+        assert_eq!(token_overlap("Hanoi", "Saigon"), 0.0);
+    }
+
+    #[test]
+    fn score_averages_every_pair() {
+        let texts = ["Hanoi", "Hanoi", "Saigon"];
+
+        let score = mean_pairwise(&texts, token_overlap);
+
+        assert!((score - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(mean_pairwise(&texts[..1], token_overlap), 1.0);
+    }
+}
@@ -0,0 +1,182 @@
+//! Conversations persisted by ID, so a caller can send just the new message
+//! and let the store supply the history, as with OpenAI's stateful
+//! Responses API.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    conversation::Conversation,
+    error::AegisError,
+    models::{Message, ProviderType},
+    options::SendOptions,
+    Aegis,
+};
+
+/// Persistence for conversations. Implementations must be safe to share
+/// between concurrent requests, and `append` must add all of its messages
+/// together so turns on the same conversation never interleave.
+#[async_trait]
+pub trait ConversationStore: Send + Sync {
+    /// Start an empty conversation and return its ID.
+    async fn create(&self) -> Result<String, AegisError>;
+
+    /// The history of conversation `id`.
+    async fn get(&self, id: &str) -> Result<Conversation, AegisError>;
+
+    /// Append `messages` to conversation `id`.
+    async fn append(&self, id: &str, messages: Vec<Message>) -> Result<(), AegisError>;
+}
+
+/// A [`ConversationStore`] held in process memory; conversations are lost on
+/// restart.
+#[derive(Debug, Default)]
+pub struct InMemoryConversationStore {
+    conversations: Mutex<HashMap<String, Vec<Message>>>,
+    next_id: AtomicU64,
+}
+
+impl InMemoryConversationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ConversationStore for InMemoryConversationStore {
+    async fn create(&self) -> Result<String, AegisError> {
+        let id = format!("conv_{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        self.conversations
+            .lock()
+            .unwrap()
+            .insert(id.clone(), Vec::new());
+        Ok(id)
+    }
+
+    async fn get(&self, id: &str) -> Result<Conversation, AegisError> {
+        self.conversations
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|messages| Conversation::from_messages(messages.clone()))
+            .ok_or_else(|| AegisError::ConversationNotFound(id.to_string()))
+    }
+
+    async fn append(&self, id: &str, messages: Vec<Message>) -> Result<(), AegisError> {
+        self.conversations
+            .lock()
+            .unwrap()
+            .get_mut(id)
+            .ok_or_else(|| AegisError::ConversationNotFound(id.to_string()))?
+            .extend(messages);
+        Ok(())
+    }
+}
+
+/// Send `message` on top of the stored history of conversation `id`, then
+/// append the message and the reply to the store as one turn.
+///
+/// Concurrent turns on the same conversation are each answered from the
+/// history as it was when they were sent.
+pub async fn send_turn(
+    store: &dyn ConversationStore,
+    aegis: &Aegis,
+    id: &str,
+    provider_type: ProviderType,
+    message: Message,
+    options: &SendOptions,
+) -> Result<Message, AegisError> {
+    let mut messages = store.get(id).await?.into_messages();
+    messages.push(message.clone());
+    let reply = aegis
+        .send_message_with_options(provider_type, messages, options)
+        .await?;
+    store.append(id, vec![message, reply.clone()]).await?;
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        config::AegisConfig,
+        models::Role,
+        providers::testing::EchoProvider,
+    };
+
+    #[tokio::test]
+    async fn unknown_conversation_is_not_found() {
+        let store = InMemoryConversationStore::new();
+
+        let result = store.append("conv_missing", vec![Message::user("Hi")]).await;
+
+        assert!(
+            matches!(result, Err(AegisError::ConversationNotFound(id)) if id == "conv_missing")
+        );
+    }
+
+    #[tokio::test]
+    async fn turns_append_message_and_reply() {
+        let aegis = Aegis::with_providers(vec![Arc::new(EchoProvider)], AegisConfig::new());
+        let store = InMemoryConversationStore::new();
+        let id = store.create().await.unwrap();
+
+        for text in ["Xin chào", "Tạm biệt"] {
+            send_turn(
+                &store,
+                &aegis,
+                &id,
+                ProviderType::Anthropic,
+                Message::user(text),
+                &SendOptions::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let history = store.get(&id).await.unwrap();
+        let texts: Vec<String> = history
+            .messages()
+            .iter()
+            .map(|m| m.content.to_string())
+            .collect();
+        assert_eq!(texts, ["Xin chào", "Xin chào", "Tạm biệt", "Tạm biệt"]);
+        assert!(matches!(history.messages()[1].role, Role::Assistant));
+    }
+
+    #[tokio::test]
+    async fn concurrent_appends_keep_each_batch_together() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let id = store.create().await.unwrap();
+
+        let tasks: Vec<_> = (0..16)
+            .map(|n| {
+                let (store, id) = (Arc::clone(&store), id.clone());
+                tokio::spawn(async move {
+                    let batch =
+                        vec![Message::user(format!("q{}", n)), Message::user(format!("a{}", n))];
+                    store.append(&id, batch).await.unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let history = store.get(&id).await.unwrap();
+        assert_eq!(history.messages().len(), 32);
+        for pair in history.messages().chunks(2) {
+            let question = pair[0].content.to_string();
+            assert_eq!(pair[1].content.to_string(), question.replacen('q', "a", 1));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Spend accounting, optionally broken down by request tags.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{models::Metadata, registry::ModelRegistry};
+
+/// Requests, tokens and dollars accumulated for one slice of traffic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spend {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// US dollars, from the registry's list prices.
+    pub cost_usd: f64,
+    /// Requests whose model has no known price; their tokens are counted but
+    /// add nothing to `cost_usd`.
+    pub unpriced_requests: u64,
+}
+
+impl Spend {
+    /// Prompt and completion tokens together.
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    fn add(&mut self, prompt_tokens: u32, completion_tokens: u32, cost: Option<f64>) {
+        self.requests += 1;
+        self.prompt_tokens += u64::from(prompt_tokens);
+        self.completion_tokens += u64::from(completion_tokens);
+        match cost {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+}
+
+/// Spend since the `Aegis` instance was created, recorded automatically for
+/// every `send_message` and for each stream once its final usage delta
+/// arrives. To follow one conversation or job, tag its requests with
+/// `SendOptions::with_tag` and read [`CostReport::tag`].
+///
+/// Only requests whose response reported usage are counted. A request with
+/// several tags counts toward each of them, so tag totals can add up to more
+/// than `total`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CostReport {
+    pub total: Spend,
+    /// Spend keyed by `(tag key, tag value)`.
+    pub by_tag: BTreeMap<(String, String), Spend>,
+}
+
+impl CostReport {
+    /// Spend attributed to requests tagged `key=value`.
+    pub fn tag(&self, key: &str, value: &str) -> Option<&Spend> {
+        self.by_tag.get(&(key.to_string(), value.to_string()))
+    }
+
+    /// Add one response's usage, priced by its model.
+    pub(crate) fn record(
+        &mut self,
+        models: &ModelRegistry,
+        metadata: &Metadata,
+        tags: &BTreeMap<String, String>,
+    ) {
+        let Some(usage) = &metadata.usage else {
+            return;
+        };
+        let cost = metadata
+            .model
+            .as_deref()
+            .and_then(|model| models.get(model))
+            .and_then(|spec| spec.pricing)
+            .map(|pricing| pricing.cost(usage.prompt_tokens, usage.completion_tokens));
+
+        self.total
+            .add(usage.prompt_tokens, usage.completion_tokens, cost);
+        for (key, value) in tags {
+            self.by_tag
+                .entry((key.clone(), value.clone()))
+                .or_default()
+                .add(usage.prompt_tokens, usage.completion_tokens, cost);
+        }
+    }
+}
+
+/// Running token totals, e.g. for one conversation or one user's session.
+///
+/// Attach it with `AegisConfig::with_usage_tracker` and every reply with
+/// usage is added, streams included once their final usage delta arrives.
+/// Clones share the same totals, so keep one to read them while `Aegis`
+/// holds another.
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker(Arc<UsageTotals>);
+
+#[derive(Debug, Default)]
+struct UsageTotals {
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the usage a response reported, if any.
+    pub fn record(&self, metadata: &Metadata) {
+        if let Some(usage) = &metadata.usage {
+            let UsageTotals {
+                prompt_tokens,
+                completion_tokens,
+            } = &*self.0;
+            prompt_tokens.fetch_add(u64::from(usage.prompt_tokens), Ordering::Relaxed);
+            completion_tokens.fetch_add(u64::from(usage.completion_tokens), Ordering::Relaxed);
+        }
+    }
+
+    pub fn total_prompt_tokens(&self) -> u64 {
+        self.0.prompt_tokens.load(Ordering::Relaxed)
+    }
+
+    pub fn total_completion_tokens(&self) -> u64 {
+        self.0.completion_tokens.load(Ordering::Relaxed)
+    }
+
+    /// Prompt and completion tokens together.
+    pub fn total_tokens(&self) -> u64 {
+        self.total_prompt_tokens() + self.total_completion_tokens()
+    }
+}
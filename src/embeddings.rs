@@ -0,0 +1,106 @@
+//! Text embeddings, served by dedicated providers independent of the chat
+//! [`Provider`](crate::providers::Provider)s. Like those, each takes
+//! `with_base_url` to send its requests to another host.
+
+pub mod jina;
+pub mod voyage;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{error::AegisError, models::Usage};
+
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Name reported in logs, e.g. `"voyage"`.
+    fn name(&self) -> &str;
+
+    /// Embed each of `inputs`, returning one vector per input in order.
+    async fn embed(&self, inputs: Vec<String>) -> Result<Embeddings, AegisError>;
+}
+
+/// The vectors for one `embed` call.
+#[derive(Debug, Clone)]
+pub struct Embeddings {
+    /// One vector per input, in input order.
+    pub vectors: Vec<Vec<f32>>,
+    /// The model that produced the vectors.
+    pub model: String,
+    /// Length of each vector.
+    pub dimensions: usize,
+    /// Token usage; embeddings only consume prompt tokens.
+    pub usage: Option<Usage>,
+}
+
+/// The OpenAI-style `{"data": [{"index", "embedding"}], "model", "usage"}`
+/// body that both Voyage and Jina return.
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+    model: String,
+    usage: Option<EmbeddingUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingUsage {
+    total_tokens: u32,
+}
+
+impl EmbeddingResponse {
+    pub(crate) fn into_embeddings(mut self) -> Embeddings {
+        self.data.sort_by_key(|d| d.index);
+        let vectors: Vec<Vec<f32>> = self.data.into_iter().map(|d| d.embedding).collect();
+        Embeddings {
+            dimensions: vectors.first().map_or(0, Vec::len),
+            vectors,
+            model: self.model,
+            usage: self.usage.map(|u| Usage {
+                prompt_tokens: u.total_tokens,
+                completion_tokens: 0,
+                total_tokens: u.total_tokens,
+                ..Usage::default()
+            }),
+        }
+    }
+}
+
+/// Post an embedding request and map the response the way the chat providers do.
+pub(crate) async fn post(
+    client: &reqwest::Client,
+    url: String,
+    api_key: &str,
+    request: &impl serde::Serialize,
+) -> Result<Embeddings, AegisError> {
+    let response = client
+        .post(url)
+        .bearer_auth(api_key)
+        .json(request)
+        .send()
+        .await
+        .map_err(AegisError::from)?;
+
+    let status = response.status();
+    let retry_after = crate::retry::retry_after(response.headers());
+    let body = response.text().await.map_err(AegisError::from)?;
+
+    match status {
+        reqwest::StatusCode::OK => {
+            crate::providers::parse_body::<EmbeddingResponse>(&body).map(|r| r.into_embeddings())
+        }
+        reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            Err(AegisError::RateLimitExceeded { retry_after })
+        }
+        reqwest::StatusCode::UNAUTHORIZED => Err(AegisError::InvalidAPIKey),
+        status if status.is_server_error() => Err(AegisError::ServerError(status.as_u16(), body)),
+        _ => Err(AegisError::APIError(format!(
+            "Status: {}, Body: {}",
+            status, body
+        ))),
+    }
+}
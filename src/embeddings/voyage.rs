@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::{
+    embeddings::{EmbeddingProvider, Embeddings},
+    error::AegisError,
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.voyageai.com";
+const DEFAULT_MODEL: &str = "voyage-3";
+
+/// Voyage AI embeddings.
+pub struct VoyageProvider {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VoyageRequest<'a> {
+    input: Vec<String>,
+    model: &'a str,
+}
+
+impl VoyageProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Use another model, e.g. `voyage-code-3`.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for VoyageProvider {
+    fn name(&self) -> &str {
+        "voyage"
+    }
+
+    async fn embed(&self, inputs: Vec<String>) -> Result<Embeddings, AegisError> {
+        let request = VoyageRequest {
+            input: inputs,
+            model: &self.model,
+        };
+        super::post(
+            &self.client,
+            format!("{}/v1/embeddings", self.base_url),
+            &self.api_key,
+            &request,
+        )
+        .await
+    }
+}
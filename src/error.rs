@@ -1,19 +1,195 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
+use crate::models::{Metadata, ProviderType};
+
 #[derive(Error, Debug)]
 pub enum AegisError {
     #[error("Provider not found")]
     ProviderNotFound,
 
+    /// No provider was named and none is set as the default, with several
+    /// configured to choose from.
+    #[error(
+        "No default provider among {configured:?}; set one with AegisConfig::with_default_provider"
+    )]
+    NoDefaultProvider { configured: Vec<ProviderType> },
+
+    /// The requested model is not in the provider's allow-list (see
+    /// `AegisConfig::with_allowed_models`). Nothing was sent.
+    #[error("Model {model} is not allowed for {provider:?}")]
+    ModelNotAllowed { provider: ProviderType, model: String },
+
+    /// `Aegis::validate_model` didn't find the model among those the
+    /// provider lists. `available` is that list, closest names first.
+    #[error("Model {model} not found{}", did_you_mean(.available))]
+    ModelNotFound { model: String, available: Vec<String> },
+
     #[error("API request failed: {0}")]
     APIError(String),
 
+    /// A 429. `retry_after` is the wait the provider asked for in its
+    /// `Retry-After` header, when it sent one.
     #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+    RateLimitExceeded { retry_after: Option<Duration> },
 
     #[error("Invalid API key")]
     InvalidAPIKey,
 
+    #[error("Server error (status {0}): {1}")]
+    ServerError(u16, String),
+
+    #[error("Failed to parse response at `{path}`: {message} (body: {snippet})")]
+    ResponseParseError {
+        path: String,
+        message: String,
+        snippet: String,
+    },
+
+    /// The body ended before the JSON was complete, e.g. a dropped connection.
+    #[error("Incomplete response body: {0}")]
+    IncompleteResponse(String),
+
+    /// A stream ended while a tool call's arguments were still arriving,
+    /// e.g. on a dropped connection. The call is reported instead of being
+    /// emitted with truncated arguments.
+    #[error("Stream ended before the arguments of tool call `{name}` ({id}) were complete")]
+    IncompleteToolCall {
+        id: String,
+        name: String,
+        /// The argument JSON received before the stream ended.
+        partial_arguments: String,
+    },
+
+    /// An image couldn't be inlined: too large, not an image, or unreachable.
+    #[error("Invalid image {0}")]
+    InvalidImage(String),
+
+    /// The configured `InjectionDetector` flagged input or a tool result.
+    #[error("Content flagged as possible prompt injection: {0}")]
+    ContentFlagged(String),
+
+    #[error("Conversation not found: {0}")]
+    ConversationNotFound(String),
+
+    /// The provider's own content filter withheld or cut off the output
+    /// (OpenAI's `content_filter`, Gemini's `SAFETY`, Cohere's `ERROR_TOXIC`
+    /// or Anthropic's `refusal` stop reason), as opposed to the model
+    /// refusing in its reply. `categories` lists the ones Azure reports as
+    /// triggered; the other providers report none.
+    /// Streams end with `FinishReason::ContentFilter` instead, having
+    /// already delivered the partial output.
+    #[error("Output blocked by the provider's content filter (categories: {categories:?})")]
+    ContentFiltered {
+        categories: Vec<String>,
+        /// Whatever text was generated before the filter stepped in.
+        partial: String,
+        /// The reply's metadata, so the tokens it was billed for still
+        /// count toward the cost report and budget.
+        metadata: Option<Box<Metadata>>,
+    },
+
+    /// A successful status with nothing in it, e.g. an empty `choices`
+    /// array. Usually a transient provider glitch, so it is retried.
+    #[error("Provider returned an empty response")]
+    EmptyResponse,
+
+    /// Spend tracked by this `Aegis` instance passed the limit set with
+    /// `AegisConfig::with_budget`. Raised before a request is sent, or as
+    /// the last item of a stream whose reported usage crossed the limit.
+    #[error("Spent ${spent_usd:.4} of a ${budget_usd:.4} budget")]
+    BudgetExceeded { spent_usd: f64, budget_usd: f64 },
+
+    /// The request was cancelled through `SendOptions::with_cancellation`.
+    #[error("Request cancelled")]
+    Cancelled,
+
+    /// The deadline set through `SendOptions::with_deadline` passed,
+    /// counting every retry, a provider request outlasted
+    /// `AegisConfig::with_timeout`, or a provider missed
+    /// `Aegis::broadcast`'s timeout.
+    #[error("Request deadline exceeded")]
+    Timeout,
+
+    /// A stream went this long without a delta, the idle limit set with
+    /// `SendOptions::with_stream_idle_timeout` or its config default. Ends
+    /// the stream.
+    #[error("No stream data for {0:?}")]
+    StreamIdle(Duration),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
     #[error("Network error: {0}")]
-    NetworkError(#[from] reqwest::Error),
-}
\ No newline at end of file
+    NetworkError(#[source] reqwest::Error),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+impl AegisError {
+    /// Short, stable name of the error variant for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AegisError::ProviderNotFound => "provider_not_found",
+            AegisError::NoDefaultProvider { .. } => "no_default_provider",
+            AegisError::ModelNotAllowed { .. } => "model_not_allowed",
+            AegisError::ModelNotFound { .. } => "model_not_found",
+            AegisError::APIError(_) => "api_error",
+            AegisError::RateLimitExceeded { .. } => "rate_limit_exceeded",
+            AegisError::InvalidAPIKey => "invalid_api_key",
+            AegisError::ServerError(..) => "server_error",
+            AegisError::ResponseParseError { .. } => "response_parse_error",
+            AegisError::IncompleteResponse(_) => "incomplete_response",
+            AegisError::IncompleteToolCall { .. } => "incomplete_tool_call",
+            AegisError::InvalidImage(_) => "invalid_image",
+            AegisError::ContentFlagged(_) => "content_flagged",
+            AegisError::ConversationNotFound(_) => "conversation_not_found",
+            AegisError::ContentFiltered { .. } => "content_filtered",
+            AegisError::EmptyResponse => "empty_response",
+            AegisError::BudgetExceeded { .. } => "budget_exceeded",
+            AegisError::Cancelled => "cancelled",
+            AegisError::Timeout => "timeout",
+            AegisError::StreamIdle(_) => "stream_idle",
+            AegisError::Unsupported(_) => "unsupported",
+            AegisError::NetworkError(_) => "network_error",
+            AegisError::IoError(_) => "io_error",
+        }
+    }
+
+    /// Whether the same request may succeed if sent again: rate limits,
+    /// 5xx responses, truncated, stalled or empty bodies and failures to
+    /// connect.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AegisError::RateLimitExceeded { .. }
+            | AegisError::ServerError(..)
+            | AegisError::IncompleteResponse(_)
+            | AegisError::IncompleteToolCall { .. }
+            | AegisError::StreamIdle(_)
+            | AegisError::EmptyResponse => true,
+            AegisError::NetworkError(e) => e.is_connect(),
+            _ => false,
+        }
+    }
+}
+
+/// A request that outlasted the client's timeout is an [`AegisError::Timeout`],
+/// like one cut short by `AegisConfig::with_timeout` anywhere else.
+impl From<reqwest::Error> for AegisError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            AegisError::Timeout
+        } else {
+            AegisError::NetworkError(error)
+        }
+    }
+}
+
+fn did_you_mean(available: &[String]) -> String {
+    match available.first() {
+        Some(closest) => format!("; did you mean {}?", closest),
+        None => String::new(),
+    }
+}
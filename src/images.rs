@@ -0,0 +1,110 @@
+//! Inlining of remote images as base64 data URLs, for providers that need
+//! the image bytes in the request rather than a link to them.
+
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{header::CONTENT_TYPE, Client};
+
+use crate::{
+    error::AegisError,
+    models::{ContentPart, Message},
+};
+
+/// Largest image Anthropic accepts inline.
+pub const MAX_INLINE_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Split a `data:<media type>;base64,<data>` URL into its media type and data.
+pub(crate) fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    url.strip_prefix("data:")?.split_once(";base64,")
+}
+
+/// Replace every `http(s)` image URL in `messages` with a base64 data URL,
+/// downloading each distinct URL once with `client`. Fails on images larger
+/// than `max_bytes` and on responses that aren't images.
+pub(crate) async fn inline_remote_images(
+    client: &Client,
+    messages: &mut [Message],
+    max_bytes: usize,
+) -> Result<(), AegisError> {
+    let mut fetched: HashMap<String, String> = HashMap::new();
+    for part in messages.iter_mut().flat_map(|m| m.content.parts.iter_mut()) {
+        let ContentPart::Image { image_url, .. } = part else {
+            continue;
+        };
+        if !(image_url.starts_with("http://") || image_url.starts_with("https://")) {
+            continue;
+        }
+        if !fetched.contains_key(image_url.as_str()) {
+            let data_url = fetch(client, image_url, max_bytes).await?;
+            fetched.insert(image_url.clone(), data_url);
+        }
+        *image_url = fetched[image_url.as_str()].clone();
+    }
+    Ok(())
+}
+
+async fn fetch(client: &Client, url: &str, max_bytes: usize) -> Result<String, AegisError> {
+    let invalid = |reason: String| AegisError::InvalidImage(format!("{}: {}", url, reason));
+    let too_large = || invalid(format!("larger than the {} byte limit", max_bytes));
+
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(AegisError::from)?;
+    if !response.status().is_success() {
+        return Err(invalid(format!(
+            "fetch failed with status {}",
+            response.status()
+        )));
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    let declared = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_lowercase());
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(AegisError::from)? {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    // Servers often send images as octet-stream, so trust the bytes over the header
+    let media_type = match (sniff(&bytes), declared.as_deref()) {
+        (Some(sniffed), _) => sniffed.to_string(),
+        (None, Some(declared)) if declared.starts_with("image/") => declared.to_string(),
+        (None, declared) => {
+            return Err(invalid(format!(
+                "not an image (content type {})",
+                declared.unwrap_or("unknown")
+            )))
+        }
+    };
+    Ok(format!(
+        "data:{};base64,{}",
+        media_type,
+        STANDARD.encode(bytes)
+    ))
+}
+
+/// The media type of `bytes`, from the signatures of the formats providers accept.
+fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
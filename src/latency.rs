@@ -0,0 +1,114 @@
+//! Per-provider request latency histograms, for percentile dashboards and
+//! SLO checks without an external metrics system.
+
+use std::{collections::HashMap, time::Duration};
+
+use hdrhistogram::Histogram;
+
+use crate::models::ProviderType;
+
+/// Longest duration tracked exactly; slower requests are recorded as this.
+const MAX_TRACKED: Duration = Duration::from_secs(600);
+
+/// Latency percentiles over every request recorded so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub count: u64,
+}
+
+impl LatencyStats {
+    fn from_histogram(histogram: &Histogram<u64>) -> Option<Self> {
+        if histogram.is_empty() {
+            return None;
+        }
+        let at = |quantile| Duration::from_micros(histogram.value_at_quantile(quantile));
+        Some(Self {
+            p50: at(0.50),
+            p95: at(0.95),
+            p99: at(0.99),
+            count: histogram.len(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ProviderLatency {
+    /// Whole requests: until the reply for `send_message`, until the last
+    /// delta for streams.
+    total: Histogram<u64>,
+    /// Streams only: until the first content delta.
+    ttft: Histogram<u64>,
+}
+
+impl ProviderLatency {
+    fn new() -> Self {
+        let histogram = || {
+            Histogram::new_with_bounds(1, MAX_TRACKED.as_micros() as u64, 3)
+                .expect("bounds are valid")
+        };
+        Self {
+            total: histogram(),
+            ttft: histogram(),
+        }
+    }
+}
+
+/// Latency histograms keyed by provider, in microseconds.
+#[derive(Debug, Default)]
+pub(crate) struct LatencyRecorder {
+    providers: HashMap<ProviderType, ProviderLatency>,
+}
+
+impl LatencyRecorder {
+    pub(crate) fn record_total(&mut self, provider_type: &ProviderType, elapsed: Duration) {
+        self.entry(provider_type).total.saturating_record(micros(elapsed));
+    }
+
+    pub(crate) fn record_ttft(&mut self, provider_type: &ProviderType, elapsed: Duration) {
+        self.entry(provider_type).ttft.saturating_record(micros(elapsed));
+    }
+
+    pub(crate) fn total(&self, provider_type: &ProviderType) -> Option<LatencyStats> {
+        LatencyStats::from_histogram(&self.providers.get(provider_type)?.total)
+    }
+
+    pub(crate) fn ttft(&self, provider_type: &ProviderType) -> Option<LatencyStats> {
+        LatencyStats::from_histogram(&self.providers.get(provider_type)?.ttft)
+    }
+
+    fn entry(&mut self, provider_type: &ProviderType) -> &mut ProviderLatency {
+        self.providers
+            .entry(provider_type.clone())
+            .or_insert_with(ProviderLatency::new)
+    }
+}
+
+fn micros(elapsed: Duration) -> u64 {
+    elapsed.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_come_from_recorded_durations() {
+        let mut recorder = LatencyRecorder::default();
+        for ms in 1..=100 {
+            recorder.record_total(&ProviderType::OpenAI, Duration::from_millis(ms));
+        }
+        recorder.record_total(&ProviderType::OpenAI, Duration::from_secs(3600));
+
+        let stats = recorder.total(&ProviderType::OpenAI).unwrap();
+        assert_eq!(stats.count, 101);
+        assert!(stats.p50.abs_diff(Duration::from_millis(51)) < Duration::from_millis(1));
+        assert!(stats.p95.abs_diff(Duration::from_millis(96)) < Duration::from_millis(1));
+        assert!(stats.p99 <= MAX_TRACKED + Duration::from_secs(1));
+
+        assert_eq!(recorder.ttft(&ProviderType::OpenAI), None);
+        assert_eq!(recorder.total(&ProviderType::Anthropic), None);
+    }
+}
@@ -1,37 +1,141 @@
+pub mod batch;
 pub mod config;
+pub mod consensus;
+pub mod conversation;
+pub mod cost;
+pub mod embeddings;
 pub mod error;
+pub mod images;
+pub mod injection;
+pub mod latency;
+pub mod logging;
 pub mod models;
+pub mod options;
+pub mod persistence;
+#[cfg(feature = "otel")]
+pub mod otel;
 pub mod providers;
+pub mod rate_limit;
+pub mod redaction;
+pub mod registry;
+pub mod retry;
+mod sse;
+pub mod stream;
+pub mod tokens;
+pub mod util;
 
-use std::sync::Arc;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
-use crate::models::{Message, ProviderType};
-use config::AegisConfig;
+use crate::models::{Content, ContentPart, FinishReason, Message, Metadata, ProviderType, Role};
+use batch::{BatchId, BatchRequest, BatchResult, BatchStatus};
+use config::{AegisConfig, HttpVersion, ImageFallback, SamplingPolicy};
+use consensus::{ConsensusResponse, ConsensusResult};
+use cost::{CostReport, UsageTracker};
+use embeddings::{EmbeddingProvider, Embeddings};
 use error::AegisError;
-use futures::Stream;
-use providers::Provider;
+use futures::{future, StreamExt};
+use injection::InjectionGuard;
+use latency::{LatencyRecorder, LatencyStats};
+use options::SendOptions;
+use rate_limit::RateLimitStatus;
+use redaction::RedactionPolicy;
+use registry::{ModelRegistry, ModelSpec};
+use retry::RetryPolicy;
+use stream::{CompletionResult, MessageStream, RawStream, SharedStreamItem, StreamAccumulator};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::broadcast,
+};
+use providers::{Provider, ProviderCapabilities};
+use tracing::{warn, Instrument};
+
+/// How long `Aegis::list_models` reuses a provider's model list.
+const MODEL_LIST_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Follow-up requests `continue_response` makes before returning what it has.
+const MAX_CONTINUATIONS: usize = 4;
+const CONTINUE_PROMPT: &str = "Continue exactly where you left off, without repeating anything.";
 
 pub struct Aegis {
-    providers: Vec<Arc<dyn Provider>>,
+    /// Swapped wholesale by `reload_providers`; requests clone the provider
+    /// they use out of it, so they finish on the set they started with.
+    providers: RwLock<Vec<Arc<dyn Provider>>>,
+    default_provider: Option<ProviderType>,
+    allowed_models: HashMap<ProviderType, Vec<String>>,
+    /// Rebuilt by `reload_providers` along with the chat providers.
+    embedding: RwLock<Option<Arc<dyn EmbeddingProvider>>>,
+    redaction: Option<RedactionPolicy>,
+    retry: Option<RetryPolicy>,
+    injection: Option<InjectionGuard>,
+    image_fallback: ImageFallback,
+    sampling: SamplingPolicy,
+    models: Arc<ModelRegistry>,
+    costs: Arc<Mutex<CostReport>>,
+    usage_tracker: Option<UsageTracker>,
+    budget_usd: Option<f64>,
+    request_timeout: Option<Duration>,
+    stream_idle_timeout: Option<Duration>,
+    estimate_missing_usage: bool,
+    /// `None` unless enabled with `AegisConfig::with_latency_histograms`.
+    latency: Option<Arc<Mutex<LatencyRecorder>>>,
+    /// Last `system_fingerprint` seen per model.
+    fingerprints: Arc<Mutex<HashMap<String, String>>>,
+    /// Each provider's model list and when it was fetched.
+    model_lists: Mutex<HashMap<ProviderType, (Instant, Vec<String>)>>,
 }
 
 impl Aegis {
     /// Create a new Aegis instance with the given configuration.
     pub fn new(config: AegisConfig) -> Self {
-        // TODO: choose the best provider if exists multiple
-        let mut providers: Vec<Arc<dyn Provider>> = Vec::new();
+        Self::with_providers(build_providers(&config), config)
+    }
 
-        if let Some(anthropic_key) = config.anthropic_api_key {
-            providers.push(Arc::new(providers::anthropic::AnthropicProvider::new(
-                anthropic_key,
-            )));
-        }
+    /// Rebuild the providers from `config`, e.g. after rotating an API key,
+    /// without restarting. Requests already under way finish on the old
+    /// providers; later ones use the new set.
+    ///
+    /// Only the provider keys and settings, embedding keys included, are
+    /// taken from `config`; retry, budget and the other instance-wide
+    /// settings keep their current values.
+    pub fn reload_providers(&self, config: &AegisConfig) {
+        *self.embedding.write().unwrap() = build_embedding(config);
+        self.replace_providers(build_providers(config));
+    }
 
-        if let Some(openai_key) = config.openai_api_key {
-            providers.push(Arc::new(providers::openai::OpenAIProvider::new(openai_key)));
-        }
+    pub(crate) fn replace_providers(&self, providers: Vec<Arc<dyn Provider>>) {
+        *self.providers.write().unwrap() = providers;
+        self.model_lists.lock().unwrap().clear();
+    }
 
-        Self { providers }
+    /// Build an instance around already-constructed providers, taking the
+    /// remaining settings from `config`.
+    pub(crate) fn with_providers(providers: Vec<Arc<dyn Provider>>, config: AegisConfig) -> Self {
+        Self {
+            providers: RwLock::new(providers),
+            embedding: RwLock::new(build_embedding(&config)),
+            default_provider: config.default_provider,
+            allowed_models: config.allowed_models,
+            redaction: config.redaction,
+            retry: config.retry,
+            injection: config.injection,
+            image_fallback: config.image_fallback,
+            sampling: config.sampling,
+            models: Arc::new(config.models),
+            costs: Arc::default(),
+            usage_tracker: config.usage_tracker,
+            budget_usd: config.budget_usd,
+            request_timeout: config.request_timeout,
+            stream_idle_timeout: config.stream_idle_timeout,
+            estimate_missing_usage: config.estimate_missing_usage,
+            latency: config.latency_histograms.then(Arc::default),
+            fingerprints: Arc::default(),
+            model_lists: Mutex::default(),
+        }
     }
 
     /// Send a message to the specified provider.
@@ -40,23 +144,2064 @@ impl Aegis {
         provider_type: ProviderType,
         messages: Vec<Message>,
     ) -> Result<Message, AegisError> {
-        let provider = self.get_provider(provider_type)?;
-        provider.send_message(messages).await
+        self.send_message_with_options(provider_type, messages, &SendOptions::default())
+            .await
+    }
+
+    /// Send a message to the default provider: the one set with
+    /// `AegisConfig::with_default_provider`, or the only one configured.
+    pub async fn send_message_default(
+        &self,
+        messages: Vec<Message>,
+    ) -> Result<Message, AegisError> {
+        self.send_message(self.default_provider()?, messages).await
+    }
+
+    /// The provider `send_message_default` uses. Fails with
+    /// `NoDefaultProvider` when none is set and several are configured, and
+    /// with `ProviderNotFound` when there are none.
+    pub fn default_provider(&self) -> Result<ProviderType, AegisError> {
+        if let Some(provider_type) = &self.default_provider {
+            return Ok(self.get_provider(provider_type.clone())?.provider_type());
+        }
+        match self.providers.read().unwrap().as_slice() {
+            [] => Err(AegisError::ProviderNotFound),
+            [provider] => Ok(provider.provider_type()),
+            providers => Err(AegisError::NoDefaultProvider {
+                configured: providers.iter().map(|p| p.provider_type()).collect(),
+            }),
+        }
+    }
+
+    /// Send a message to the specified provider with per-call options.
+    pub async fn send_message_with_options(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let provider = self.get_provider(provider_type.clone())?;
+        self.check_budget()?;
+        self.check_model(&provider_type, options)?;
+        let options = &*self.fit_sampling(options)?;
+        let span = logging::request_span(&provider_type);
+        let started = Instant::now();
+        let messages = span.in_scope(|| self.prepare_messages(provider.as_ref(), messages))?;
+        let result = bounded(
+            options,
+            retry::retry(self.retry.as_ref(), || async {
+                let reply = provider.send_message(messages.clone(), options);
+                let reply = timed(self.request_timeout, reply).await?;
+                providers::reject_empty(providers::assistant_turn(reply))
+            })
+            .instrument(span.clone()),
+        )
+        .await
+        .map(|reply| match self.estimate_missing_usage {
+            true => fill_usage(&provider_type, options.model.as_deref(), &messages, reply),
+            false => reply,
+        });
+        match &result {
+            Ok(message) => {
+                logging::record_success(&span, started, message.metadata.as_ref());
+                if let Some(latency) = &self.latency {
+                    latency.lock().unwrap().record_total(&provider_type, started.elapsed());
+                }
+                if let Some(metadata) = &message.metadata {
+                    self.record_usage(metadata, &options.tags);
+                }
+            }
+            Err(e) => {
+                logging::record_failure(&span, started, e);
+                // A filtered reply is still billed for
+                if let AegisError::ContentFiltered { metadata: Some(metadata), .. } = e {
+                    self.record_usage(metadata, &options.tags);
+                }
+            }
+        }
+        result
     }
 
     pub async fn stream_message(
         &self,
         provider_type: ProviderType,
         messages: Vec<Message>,
-    ) -> Result<impl Stream<Item = Result<Message, AegisError>>, AegisError> {
+    ) -> Result<MessageStream, AegisError> {
+        self.stream_message_with_options(provider_type, messages, &SendOptions::default())
+            .await
+    }
+
+    /// Stream a response from the specified provider with per-call options.
+    pub async fn stream_message_with_options(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let provider = self.get_provider(provider_type.clone())?;
+        self.check_budget()?;
+        self.check_model(&provider_type, options)?;
+        let options = &*self.fit_sampling(options)?;
+        let span = logging::request_span(&provider_type);
+        let started = Instant::now();
+        let messages = span.in_scope(|| self.prepare_messages(provider.as_ref(), messages))?;
+        let result = bounded(
+            options,
+            retry::retry(self.retry.as_ref(), || {
+                timed(
+                    self.request_timeout,
+                    provider.stream_message(messages.clone(), options),
+                )
+            })
+            .instrument(span.clone()),
+        )
+        .await;
+        match &result {
+            Ok(_) => logging::record_success(&span, started, None),
+            Err(e) => logging::record_failure(&span, started, e),
+        }
+        result.map(|stream| {
+            let stream = match options.stream_idle_timeout.or(self.stream_idle_timeout) {
+                Some(idle) => stream::idle_timeout(stream, idle),
+                None => stream,
+            };
+            let stream = self.estimate_stream_usage(stream, &provider_type, options, messages);
+            let stream = self.track_costs(stream, &options.tags);
+            let stream = self.enforce_budget(stream);
+            let stream = self.track_latency(stream, provider_type, started);
+            // Dropping the provider stream closes its connection
+            let stream = match &options.cancellation {
+                Some(token) => Box::pin(stream.take_until(token.clone().cancelled_owned())),
+                None => stream,
+            };
+            match options.deadline {
+                Some(deadline) => stream::deadline(stream, deadline),
+                None => stream,
+            }
+        })
+    }
+
+    /// Stream one response to many consumers, e.g. every client watching the
+    /// same generation, over a single upstream request.
+    ///
+    /// Call `resubscribe` on the returned receiver for each extra consumer;
+    /// it receives deltas from the point it joins. The channel closes after
+    /// the last delta or an error. A consumer that falls more than 256 deltas
+    /// behind gets `RecvError::Lagged` and skips ahead.
+    pub async fn stream_shared(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+    ) -> Result<broadcast::Receiver<SharedStreamItem>, AegisError> {
+        let stream = self.stream_message(provider_type, messages).await?;
+        Ok(stream::broadcast(stream))
+    }
+
+    /// Low-level diagnostic: the provider's event-stream bytes exactly as
+    /// they arrive, before any SSE decoding, for debugging a provider whose
+    /// wire format has changed under the parser.
+    ///
+    /// Messages are prepared and the opening request retried as for
+    /// `stream_message`, but nothing is parsed, buffered or cost-tracked.
+    pub async fn stream_message_raw(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+    ) -> Result<RawStream, AegisError> {
+        let provider = self.get_provider(provider_type.clone())?;
+        let options = SendOptions::default();
+        let span = logging::request_span(&provider_type);
+        let messages = span.in_scope(|| self.prepare_messages(provider.as_ref(), messages))?;
+        retry::retry(self.retry.as_ref(), || {
+            timed(
+                self.request_timeout,
+                provider.stream_raw(messages.clone(), &options),
+            )
+        })
+        .instrument(span)
+        .await
+    }
+
+    /// Stream a response to completion and return it together with its
+    /// usage, finish reason, model and timings.
+    pub async fn complete_streaming(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+    ) -> Result<CompletionResult, AegisError> {
+        let started = Instant::now();
+        let mut stream = self.stream_message(provider_type, messages).await?;
+        let mut accumulator = StreamAccumulator::new();
+        while let Some(delta) = stream.next().await {
+            accumulator.push(&delta?);
+        }
+        Ok(accumulator.into_result(started))
+    }
+
+    /// Stream a response straight into `writer`, flushing after every text
+    /// delta, and return the metadata collected from the stream. A failing
+    /// write ends the stream with [`AegisError::IoError`].
+    pub async fn stream_to_writer<W>(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+        mut writer: W,
+    ) -> Result<Option<Metadata>, AegisError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut stream = self.stream_message(provider_type, messages).await?;
+        let mut accumulator = StreamAccumulator::new();
+        while let Some(delta) = stream.next().await {
+            let delta = delta?;
+            let text = delta.content.to_string();
+            if !text.is_empty() {
+                writer.write_all(text.as_bytes()).await?;
+                writer.flush().await?;
+            }
+            accumulator.push(&delta);
+        }
+        Ok(accumulator.into_message().metadata)
+    }
+
+    /// Complete a reply that stopped at the token limit.
+    ///
+    /// While `truncated` (the reply to `messages`) reports
+    /// [`FinishReason::MaxTokens`], the output so far is sent back with a
+    /// request to continue, and the pieces are joined into one message with
+    /// summed usage. Gives up after a few rounds and returns the joined
+    /// output, still marked `MaxTokens`.
+    pub async fn continue_response(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+        truncated: Message,
+    ) -> Result<Message, AegisError> {
+        let mut combined = truncated;
+        for _ in 0..MAX_CONTINUATIONS {
+            let finish_reason = combined.metadata.as_ref().and_then(|m| m.finish_reason.as_ref());
+            if finish_reason != Some(&FinishReason::MaxTokens) {
+                break;
+            }
+
+            let mut history = messages.clone();
+            history.push(combined.clone());
+            history.push(Message {
+                role: Role::User,
+                content: Content {
+                    parts: vec![ContentPart::text(CONTINUE_PROMPT)],
+                },
+                metadata: None,
+            });
+            let next = self.send_message(provider_type.clone(), history).await?;
+
+            let mut usage = combined.metadata.as_ref().and_then(|m| m.usage.clone());
+            if let (Some(total), Some(more)) = (
+                usage.as_mut(),
+                next.metadata.as_ref().and_then(|m| m.usage.as_ref()),
+            ) {
+                *total += more;
+            }
+            let mut accumulator = StreamAccumulator::new();
+            accumulator.push(&combined);
+            accumulator.push(&next);
+            combined = accumulator.into_message();
+            if let Some(metadata) = combined.metadata.as_mut() {
+                metadata.usage = usage;
+            }
+        }
+        Ok(combined)
+    }
+
+    /// Send many independent conversations to one provider, at most
+    /// `concurrency` at a time. Results are returned in input order; a failed
+    /// item does not stop the others. Each item is retried per the configured
+    /// retry policy.
+    pub async fn batch(
+        &self,
+        provider_type: ProviderType,
+        inputs: Vec<Vec<Message>>,
+        concurrency: usize,
+    ) -> Vec<Result<Message, AegisError>> {
+        self.batch_with_progress(provider_type, inputs, concurrency, |_, _| {})
+            .await
+    }
+
+    /// Like [`Aegis::batch`], calling `progress(completed, total)` as each item finishes.
+    pub async fn batch_with_progress<F>(
+        &self,
+        provider_type: ProviderType,
+        inputs: Vec<Vec<Message>>,
+        concurrency: usize,
+        mut progress: F,
+    ) -> Vec<Result<Message, AegisError>>
+    where
+        F: FnMut(usize, usize),
+    {
+        let total = inputs.len();
+        let mut results: Vec<Option<Result<Message, AegisError>>> =
+            std::iter::repeat_with(|| None).take(total).collect();
+        let mut completed = futures::stream::iter(inputs.into_iter().enumerate())
+            .map(|(index, messages)| {
+                let provider_type = provider_type.clone();
+                async move { (index, self.send_message(provider_type, messages).await) }
+            })
+            .buffer_unordered(concurrency.max(1));
+
+        let mut done = 0;
+        while let Some((index, result)) = completed.next().await {
+            results[index] = Some(result);
+            done += 1;
+            progress(done, total);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// Send `messages` to every provider in `provider_types` at once and
+    /// return the first successful reply with the provider that sent it. The
+    /// other requests are dropped as soon as one succeeds, which closes their
+    /// connections. If every provider fails, the last error is returned.
+    pub async fn race(
+        &self,
+        provider_types: Vec<ProviderType>,
+        messages: Vec<Message>,
+    ) -> Result<(ProviderType, Message), AegisError> {
+        if provider_types.is_empty() {
+            return Err(AegisError::ProviderNotFound);
+        }
+        let attempts = provider_types.into_iter().map(|provider_type| {
+            let messages = messages.clone();
+            Box::pin(async move {
+                let reply = self.send_message(provider_type.clone(), messages).await?;
+                Ok::<_, AegisError>((provider_type, reply))
+            })
+        });
+        let (winner, _losers) = future::select_ok(attempts).await?;
+        Ok(winner)
+    }
+
+    /// Send `messages` to every provider in `provider_types` at once and
+    /// return each one's result, in the order given. A provider still
+    /// pending after `timeout` gets `AegisError::Timeout` in its slot and its
+    /// request is dropped, so one slow provider can't hold up the rest.
+    pub async fn broadcast(
+        &self,
+        provider_types: Vec<ProviderType>,
+        messages: Vec<Message>,
+        timeout: Duration,
+    ) -> Vec<(ProviderType, Result<Message, AegisError>)> {
+        future::join_all(provider_types.into_iter().map(|provider_type| {
+            let request = self.send_message(provider_type.clone(), messages.clone());
+            async move {
+                let result = tokio::time::timeout(timeout, request)
+                    .await
+                    .unwrap_or(Err(AegisError::Timeout));
+                (provider_type, result)
+            }
+        }))
+        .await
+    }
+
+    /// Send `messages` to every provider in `provider_types` concurrently and
+    /// score how far their replies agree, as the mean pairwise `similarity`
+    /// (pass [`consensus::token_overlap`] for the default). Fails if any
+    /// provider does, since a missing reply would skew the score.
+    pub async fn consensus(
+        &self,
+        provider_types: Vec<ProviderType>,
+        messages: Vec<Message>,
+        similarity: impl Fn(&str, &str) -> f64,
+    ) -> Result<ConsensusResult, AegisError> {
+        if provider_types.is_empty() {
+            return Err(AegisError::ProviderNotFound);
+        }
+        let replies = future::try_join_all(provider_types.into_iter().map(|provider_type| {
+            let messages = messages.clone();
+            async move {
+                let message = self.send_message(provider_type.clone(), messages).await?;
+                Ok::<_, AegisError>(ConsensusResponse {
+                    provider_type,
+                    text: message.content.to_string(),
+                    message,
+                })
+            }
+        }))
+        .await?;
+        let texts: Vec<&str> = replies.iter().map(|r| r.text.as_str()).collect();
+        Ok(ConsensusResult {
+            score: consensus::mean_pairwise(&texts, similarity),
+            responses: replies,
+        })
+    }
+
+    /// Generate `k` replies from one provider concurrently and return the one
+    /// `scorer` rates highest, the earliest on ties. Candidates that fail are
+    /// skipped; if all do, the last error is returned. `k` of 0 is treated as 1.
+    pub async fn best_of(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+        k: usize,
+        scorer: impl Fn(&Message) -> f64,
+    ) -> Result<Message, AegisError> {
+        let attempts = (0..k.max(1)).map(|_| self.send_message(provider_type.clone(), messages.clone()));
+        let mut last_error = None;
+        let mut candidates = Vec::new();
+        for result in future::join_all(attempts).await {
+            match result {
+                Ok(message) => candidates.push(message),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match pick_best(candidates, scorer) {
+            Some(best) => Ok(best),
+            None => Err(last_error.unwrap_or(AegisError::ProviderNotFound)),
+        }
+    }
+
+    /// `n` candidate replies to one prompt, in one request where the provider
+    /// supports it (OpenAI's `n`) and `n` concurrent requests otherwise.
+    /// Unlike `best_of`, every choice is kept, in order, with usage summed
+    /// across them. `n` of 0 is treated as 1.
+    pub async fn send_message_n(
+        &self,
+        provider_type: ProviderType,
+        messages: Vec<Message>,
+        n: u32,
+        options: &SendOptions,
+    ) -> Result<models::Completions, AegisError> {
+        let provider = self.get_provider(provider_type.clone())?;
+        self.check_budget()?;
+        self.check_model(&provider_type, options)?;
+        let options = &*self.fit_sampling(options)?;
+        let span = logging::request_span(&provider_type);
+        let started = Instant::now();
+        let messages = span.in_scope(|| self.prepare_messages(provider.as_ref(), messages))?;
+        let result = bounded(
+            options,
+            retry::retry(self.retry.as_ref(), || {
+                timed(
+                    self.request_timeout,
+                    provider.send_message_n(messages.clone(), n, options),
+                )
+            })
+            .instrument(span.clone()),
+        )
+        .await;
+        let completions = match result {
+            Ok(completions) => completions,
+            Err(e) => {
+                logging::record_failure(&span, started, &e);
+                return Err(e);
+            }
+        };
+        let metadata = completions
+            .choices
+            .first()
+            .and_then(|c| c.metadata.as_ref())
+            .map(|first| Metadata {
+                usage: Some(completions.usage.clone()),
+                ..first.clone()
+            });
+        logging::record_success(&span, started, metadata.as_ref());
+        if let Some(latency) = &self.latency {
+            latency.lock().unwrap().record_total(&provider_type, started.elapsed());
+        }
+        if let Some(metadata) = &metadata {
+            self.record_usage(metadata, &options.tags);
+        }
+        Ok(completions)
+    }
+
+    /// Submit `requests` to the provider's batch API (currently OpenAI only):
+    /// cheaper than sending them one by one, but results can take up to a
+    /// day. Each request's messages are prepared as for `send_message`.
+    pub async fn batch_submit(
+        &self,
+        provider_type: ProviderType,
+        requests: Vec<BatchRequest>,
+    ) -> Result<BatchId, AegisError> {
         let provider = self.get_provider(provider_type)?;
-        provider.stream_message(messages).await
+        let requests = requests
+            .into_iter()
+            .map(|request| {
+                Ok(BatchRequest {
+                    options: self.fit_sampling(&request.options)?.into_owned(),
+                    messages: self.prepare_messages(provider.as_ref(), request.messages)?,
+                    custom_id: request.custom_id,
+                })
+            })
+            .collect::<Result<Vec<_>, AegisError>>()?;
+        timed(self.request_timeout, provider.batch_submit(requests)).await
+    }
+
+    /// Where a submitted batch is; poll until `BatchStatus::is_finished`.
+    pub async fn batch_poll(
+        &self,
+        provider_type: ProviderType,
+        id: &BatchId,
+    ) -> Result<BatchStatus, AegisError> {
+        let provider = self.get_provider(provider_type)?;
+        timed(self.request_timeout, provider.batch_poll(id)).await
+    }
+
+    /// Per-request results of a finished batch, matched by `custom_id`.
+    pub async fn batch_results(
+        &self,
+        provider_type: ProviderType,
+        id: &BatchId,
+    ) -> Result<Vec<BatchResult>, AegisError> {
+        let provider = self.get_provider(provider_type)?;
+        timed(self.request_timeout, provider.batch_results(id)).await
+    }
+
+    /// Models the provider currently serves, from its models endpoint.
+    /// Fetched lists are reused for ten minutes.
+    pub async fn list_models(&self, provider_type: ProviderType) -> Result<Vec<String>, AegisError> {
+        if let Some((fetched, models)) = self.model_lists.lock().unwrap().get(&provider_type) {
+            if fetched.elapsed() < MODEL_LIST_TTL {
+                return Ok(models.clone());
+            }
+        }
+        let provider = self.get_provider(provider_type.clone())?;
+        let models = timed(self.request_timeout, provider.list_models()).await?;
+        self.model_lists
+            .lock()
+            .unwrap()
+            .insert(provider_type, (Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /// Check that the provider serves `model` before relying on it, e.g. at
+    /// the start of a long session, so a typo fails here with
+    /// `AegisError::ModelNotFound` and the closest real names rather than
+    /// as a 404 partway through. Uses the cached list from `list_models`.
+    pub async fn validate_model(
+        &self,
+        provider_type: ProviderType,
+        model: &str,
+    ) -> Result<(), AegisError> {
+        let mut available = self.list_models(provider_type).await?;
+        if available.iter().any(|m| m == model) {
+            return Ok(());
+        }
+        available.sort_by_cached_key(|m| edit_distance(m, model));
+        Err(AegisError::ModelNotFound {
+            model: model.to_string(),
+            available,
+        })
+    }
+
+    /// Open and pool a connection to the provider so the first real request
+    /// skips DNS, TCP and TLS setup, e.g. right after a cold start. Purely an
+    /// optimisation: it returns within a couple of seconds and ignores
+    /// network errors, failing only if the provider isn't configured.
+    pub async fn warmup(&self, provider_type: ProviderType) -> Result<(), AegisError> {
+        self.get_provider(provider_type)?.warmup().await;
+        Ok(())
+    }
+
+    /// Capabilities of the specified provider, e.g. to check image support before sending.
+    pub fn capabilities(&self, provider_type: ProviderType) -> Result<ProviderCapabilities, AegisError> {
+        Ok(self.get_provider(provider_type)?.capabilities())
+    }
+
+    /// Limits and capabilities for `model`, from the built-in table plus any
+    /// overrides in the config.
+    pub fn model_spec(&self, model: &str) -> Option<&ModelSpec> {
+        self.models.get(model)
+    }
+
+    /// Count the prompt tokens `messages` will use on `model`. See [`tokens::count_tokens`].
+    pub fn count_tokens(
+        &self,
+        provider_type: ProviderType,
+        model: &str,
+        messages: &[Message],
+    ) -> Result<u32, AegisError> {
+        tokens::count_tokens(&provider_type, model, messages)
+    }
+
+    /// Tokens left in `model`'s context window after `messages`; negative
+    /// once the conversation no longer fits. Where tokens can't be counted
+    /// exactly, they are estimated (see [`tokens::estimate_tokens`]). Fails
+    /// with [`AegisError::Unsupported`] if the model is not in the registry.
+    pub fn remaining_context(
+        &self,
+        provider_type: ProviderType,
+        model: &str,
+        messages: &[Message],
+    ) -> Result<i64, AegisError> {
+        let spec = self.model_spec(model).ok_or_else(|| {
+            AegisError::Unsupported(format!("no context window known for model {}", model))
+        })?;
+        let used = match self.count_tokens(provider_type, model, messages) {
+            Ok(used) => i64::from(used),
+            Err(AegisError::Unsupported(_)) => tokens::estimate_tokens(messages) as i64,
+            Err(e) => return Err(e),
+        };
+        Ok(i64::from(spec.context_window) - used)
+    }
+
+    /// Embed `inputs` with the configured embedding provider (see
+    /// `AegisConfig::with_voyage`), independently of the chat providers.
+    pub async fn embed(&self, inputs: Vec<String>) -> Result<Embeddings, AegisError> {
+        let provider = self.embedding.read().unwrap().clone();
+        let provider = provider.ok_or(AegisError::ProviderNotFound)?;
+        let inputs = match self.redaction.as_ref().filter(|p| p.scrubs_before_send()) {
+            Some(policy) => inputs.iter().map(|text| policy.redact(text)).collect(),
+            None => inputs,
+        };
+
+        timed(self.request_timeout, provider.embed(inputs)).await
+    }
+
+    /// Spend so far, in total and per request tag (see `SendOptions::with_tag`).
+    pub fn cost_report(&self) -> CostReport {
+        self.costs.lock().unwrap().clone()
+    }
+
+    /// Percentiles of request durations for `provider_type`: until the reply
+    /// for `send_message`, until the last delta for streams. `None` unless
+    /// latency histograms are enabled and a request has completed.
+    pub fn latency_stats(&self, provider_type: ProviderType) -> Option<LatencyStats> {
+        self.latency.as_ref()?.lock().unwrap().total(&provider_type)
+    }
+
+    /// Percentiles of streams' time to first content from `provider_type`.
+    pub fn ttft_stats(&self, provider_type: ProviderType) -> Option<LatencyStats> {
+        self.latency.as_ref()?.lock().unwrap().ttft(&provider_type)
+    }
+
+    /// The `system_fingerprint` of the latest reply from `model` (as the
+    /// provider reported it, e.g. `gpt-4o-2024-08-06`), for checking that
+    /// seeded runs hit the same backend.
+    pub fn last_system_fingerprint(&self, model: &str) -> Option<String> {
+        self.fingerprints.lock().unwrap().get(model).cloned()
+    }
+
+    /// Rate-limit headers from the provider's most recent response, if it reports them.
+    pub fn last_rate_limit_status(
+        &self,
+        provider_type: ProviderType,
+    ) -> Result<Option<RateLimitStatus>, AegisError> {
+        Ok(self.get_provider(provider_type)?.last_rate_limit_status())
+    }
+
+    /// Check `options` against the requested model's sampling limits. Options
+    /// without a model, or for a model not in the registry, pass unchanged.
+    fn fit_sampling<'a>(&self, options: &'a SendOptions) -> Result<Cow<'a, SendOptions>, AegisError> {
+        let Some((model, spec)) = options
+            .model
+            .as_deref()
+            .filter(|_| options.generation.temperature.is_some())
+            .and_then(|model| Some((model, self.models.get(model)?)))
+        else {
+            return Ok(Cow::Borrowed(options));
+        };
+        let mut fitted = options.clone();
+        self.sampling.apply(model, spec, &mut fitted)?;
+        Ok(Cow::Owned(fitted))
+    }
+
+    /// Apply the image fallback for providers without vision, log the
+    /// outgoing messages and apply send-time redaction if configured.
+    fn prepare_messages(
+        &self,
+        provider: &dyn Provider,
+        mut messages: Vec<Message>,
+    ) -> Result<Vec<Message>, AegisError> {
+        if !provider.capabilities().supported_content_types.iter().any(|t| t == "image") {
+            self.image_fallback.apply(&mut messages)?;
+        }
+        if let Some(guard) = &self.injection {
+            guard.screen_user_input(&mut messages)?;
+        }
+        if let Some(policy) = self.redaction.as_ref().filter(|p| p.scrubs_before_send()) {
+            messages.iter_mut().for_each(|message| policy.scrub(message));
+        }
+        logging::log_messages(self.redaction.as_ref(), &messages);
+        Ok(messages)
     }
 
-    fn get_provider(&self, provider_type: ProviderType) -> Result<&Arc<dyn Provider>, AegisError> {
+    /// Add a completed reply's usage to the cost report and the attached
+    /// tracker, and note its fingerprint.
+    fn record_usage(&self, metadata: &Metadata, tags: &BTreeMap<String, String>) {
+        self.costs.lock().unwrap().record(&self.models, metadata, tags);
+        if let Some(tracker) = &self.usage_tracker {
+            tracker.record(metadata);
+        }
+        note_fingerprint(&self.fingerprints, metadata);
+    }
+
+    /// Record a stream's usage once it arrives. The model is usually reported
+    /// on an earlier delta than the usage, so it is carried forward.
+    fn track_costs(&self, stream: MessageStream, tags: &BTreeMap<String, String>) -> MessageStream {
+        let models = Arc::clone(&self.models);
+        let costs = Arc::clone(&self.costs);
+        let tracker = self.usage_tracker.clone();
+        let fingerprints = Arc::clone(&self.fingerprints);
+        let tags = tags.clone();
+        let mut model = None;
+        Box::pin(stream.inspect(move |delta| {
+            let Ok(Message { metadata: Some(metadata), .. }) = delta else {
+                return;
+            };
+            if metadata.model.is_some() {
+                model = metadata.model.clone();
+            }
+            if metadata.usage.is_some() || metadata.system_fingerprint.is_some() {
+                let metadata = Metadata {
+                    model: model.clone(),
+                    ..metadata.clone()
+                };
+                if metadata.usage.is_some() {
+                    costs.lock().unwrap().record(&models, &metadata, &tags);
+                    if let Some(tracker) = &tracker {
+                        tracker.record(&metadata);
+                    }
+                }
+                note_fingerprint(&fingerprints, &metadata);
+            }
+        }))
+    }
+
+    /// If enabled and `stream` ends without having reported usage, end it
+    /// with one more delta carrying estimated usage for the whole reply.
+    /// Streams that fail are left alone.
+    fn estimate_stream_usage(
+        &self,
+        stream: MessageStream,
+        provider_type: &ProviderType,
+        options: &SendOptions,
+        prompt: Vec<Message>,
+    ) -> MessageStream {
+        if !self.estimate_missing_usage {
+            return stream;
+        }
+        let provider_type = provider_type.clone();
+        let model = options.model.clone();
+        let mut reply = StreamAccumulator::new();
+        let mut done = false;
+        Box::pin(providers::mark_end(stream).filter_map(move |delta| {
+            let delta = match delta {
+                Some(delta) => {
+                    done |= delta.as_ref().map_or(true, |message| {
+                        message.metadata.as_ref().is_some_and(|m| m.usage.is_some())
+                    });
+                    if let Ok(message) = &delta {
+                        reply.push(message);
+                    }
+                    Some(delta)
+                }
+                None if done => None,
+                None => {
+                    let reply = std::mem::take(&mut reply).into_message();
+                    let filled = fill_usage(&provider_type, model.as_deref(), &prompt, reply)
+                        .metadata
+                        .unwrap_or_default();
+                    Some(Ok(Message {
+                        role: Role::Assistant,
+                        content: Content { parts: Vec::new() },
+                        metadata: Some(Metadata {
+                            model: filled.model.or_else(|| model.clone()),
+                            usage: filled.usage,
+                            ..Metadata::default()
+                        }),
+                    }))
+                }
+            };
+            future::ready(delta)
+        }))
+    }
+
+    /// Fail with `ModelNotAllowed` if `options` names a model outside the
+    /// provider's allow-list.
+    fn check_model(
+        &self,
+        provider_type: &ProviderType,
+        options: &SendOptions,
+    ) -> Result<(), AegisError> {
+        let (Some(model), Some(allowed)) = (&options.model, self.allowed_models.get(provider_type))
+        else {
+            return Ok(());
+        };
+        if allowed.is_empty() || allowed.contains(model) {
+            return Ok(());
+        }
+        Err(AegisError::ModelNotAllowed {
+            provider: provider_type.clone(),
+            model: model.clone(),
+        })
+    }
+
+    /// Fail with `BudgetExceeded` if the spend so far has reached the budget.
+    fn check_budget(&self) -> Result<(), AegisError> {
+        let Some(budget_usd) = self.budget_usd else {
+            return Ok(());
+        };
+        let spent_usd = self.costs.lock().unwrap().total.cost_usd;
+        if spent_usd >= budget_usd {
+            return Err(AegisError::BudgetExceeded { spent_usd, budget_usd });
+        }
+        Ok(())
+    }
+
+    /// End `stream` with `BudgetExceeded` after the first delta whose usage
+    /// takes the spend to the budget, the same limit `check_budget` applies.
+    /// Runs after `track_costs`, so that delta is already counted.
+    fn enforce_budget(&self, stream: MessageStream) -> MessageStream {
+        let Some(budget_usd) = self.budget_usd else {
+            return stream;
+        };
+        let costs = Arc::clone(&self.costs);
+        let stream = stream.flat_map(move |delta| {
+            let has_usage = matches!(
+                &delta,
+                Ok(Message { metadata: Some(Metadata { usage: Some(_), .. }), .. })
+            );
+            let exceeded = has_usage
+                .then(|| costs.lock().unwrap().total.cost_usd)
+                .filter(|spent_usd| *spent_usd >= budget_usd)
+                .map(|spent_usd| Err(AegisError::BudgetExceeded { spent_usd, budget_usd }));
+            futures::stream::iter(std::iter::once(delta).chain(exceeded))
+        });
+        let mut exceeded = false;
+        Box::pin(stream.take_while(move |delta| {
+            let keep = !exceeded;
+            exceeded = matches!(delta, Err(AegisError::BudgetExceeded { .. }));
+            future::ready(keep)
+        }))
+    }
+
+    /// Record a stream's time to first content and, if it runs to the end,
+    /// its total duration. Streams cut short are left out of the totals.
+    fn track_latency(
+        &self,
+        stream: MessageStream,
+        provider_type: ProviderType,
+        started: Instant,
+    ) -> MessageStream {
+        let Some(latency) = &self.latency else {
+            return stream;
+        };
+        let (latency, on_end) = (Arc::clone(latency), Arc::clone(latency));
+        let first = provider_type.clone();
+        let mut waiting = true;
+        let stream = stream.inspect(move |delta| {
+            if waiting && delta.as_ref().is_ok_and(|m| !m.content.parts.is_empty()) {
+                waiting = false;
+                latency.lock().unwrap().record_ttft(&first, started.elapsed());
+            }
+        });
+        let end = futures::stream::once(future::lazy(move |_| {
+            on_end.lock().unwrap().record_total(&provider_type, started.elapsed());
+        }))
+        .filter_map(|()| future::ready(None));
+        Box::pin(stream.chain(end))
+    }
+
+    pub(crate) fn injection_guard(&self) -> Option<&InjectionGuard> {
+        self.injection.as_ref()
+    }
+
+    fn get_provider(&self, provider_type: ProviderType) -> Result<Arc<dyn Provider>, AegisError> {
         self.providers
+            .read()
+            .unwrap()
             .iter()
             .find(|p| p.provider_type() == provider_type)
+            .cloned()
             .ok_or(AegisError::ProviderNotFound)
     }
 }
+
+/// Add estimated usage to `reply` if it arrived without any. `model` is the
+/// one requested, used when the reply doesn't name one.
+fn fill_usage(
+    provider_type: &ProviderType,
+    model: Option<&str>,
+    prompt: &[Message],
+    mut reply: Message,
+) -> Message {
+    let metadata = reply.metadata.as_ref();
+    if metadata.is_some_and(|m| m.usage.is_some()) {
+        return reply;
+    }
+    let model = metadata.and_then(|m| m.model.as_deref()).or(model);
+    let usage = tokens::estimate_usage(provider_type, model, prompt, &reply);
+    reply.metadata.get_or_insert_with(Metadata::default).usage = Some(usage);
+    reply
+}
+
+/// The embedding provider for the first embedding key in `config`, if any.
+fn build_embedding(config: &AegisConfig) -> Option<Arc<dyn EmbeddingProvider>> {
+    match (config.voyage_api_key.clone(), config.jina_api_key.clone()) {
+        (Some(key), _) => Some(Arc::new(embeddings::voyage::VoyageProvider::new(key))),
+        (None, Some(key)) => Some(Arc::new(embeddings::jina::JinaProvider::new(key))),
+        (None, None) => None,
+    }
+}
+
+/// One provider for each API key in `config`, all sharing one HTTP client.
+fn build_providers(config: &AegisConfig) -> Vec<Arc<dyn Provider>> {
+    let mut providers: Vec<Arc<dyn Provider>> = Vec::new();
+    let client = http_client(config);
+
+    if let Some(anthropic_key) = config.anthropic_api_key.clone() {
+        providers.push(Arc::new(
+            override_base_url(
+                config,
+                ProviderType::Anthropic,
+                providers::anthropic::AnthropicProvider::new(anthropic_key)
+                    .with_http_client(client.clone())
+                    .with_verbatim_roles(config.verbatim_roles)
+                    .with_inline_images(config.inline_images)
+                    .with_api_version(config.anthropic_version.clone())
+                    .with_model_registry(config.models.clone()),
+                providers::anthropic::AnthropicProvider::with_base_url,
+            ),
+        ));
+    }
+
+    if let Some(openai_key) = config.openai_api_key.clone() {
+        if config.openai_responses_api {
+            providers.push(Arc::new(
+                override_base_url(
+                    config,
+                    ProviderType::OpenAI,
+                    providers::openai_responses::OpenAIResponsesProvider::new(openai_key)
+                        .with_http_client(client.clone())
+                        .with_verbatim_roles(config.verbatim_roles),
+                    providers::openai_responses::OpenAIResponsesProvider::with_base_url,
+                ),
+            ));
+        } else {
+            providers.push(Arc::new(
+                override_base_url(
+                    config,
+                    ProviderType::OpenAI,
+                    providers::openai::OpenAIProvider::new(openai_key)
+                        .with_http_client(client.clone())
+                        .with_verbatim_roles(config.verbatim_roles),
+                    providers::openai::OpenAIProvider::with_base_url,
+                ),
+            ));
+        }
+    }
+
+    if let Some(cohere_key) = config.cohere_api_key.clone() {
+        providers.push(Arc::new(
+            override_base_url(
+                config,
+                ProviderType::Cohere,
+                providers::cohere::CohereProvider::new(cohere_key)
+                    .with_http_client(client.clone())
+                    .with_verbatim_roles(config.verbatim_roles),
+                providers::cohere::CohereProvider::with_base_url,
+            ),
+        ));
+    }
+
+    if let Some(mistral_key) = config.mistral_api_key.clone() {
+        providers.push(Arc::new(
+            override_base_url(
+                config,
+                ProviderType::Mistral,
+                providers::mistral::MistralProvider::new(mistral_key)
+                    .with_http_client(client.clone())
+                    .with_verbatim_roles(config.verbatim_roles),
+                providers::mistral::MistralProvider::with_base_url,
+            ),
+        ));
+    }
+
+    if let Some(xai_key) = config.xai_api_key.clone() {
+        providers.push(Arc::new(
+            override_base_url(
+                config,
+                ProviderType::Xai,
+                providers::xai::XaiProvider::new(xai_key)
+                    .with_http_client(client.clone())
+                    .with_verbatim_roles(config.verbatim_roles),
+                providers::xai::XaiProvider::with_base_url,
+            ),
+        ));
+    }
+
+    if let Some(gemini_key) = config.gemini_api_key.clone() {
+        providers.push(Arc::new(
+            override_base_url(
+                config,
+                ProviderType::Gemini,
+                providers::gemini::GeminiProvider::new(gemini_key)
+                    .with_http_client(client.clone())
+                    .with_verbatim_roles(config.verbatim_roles),
+                providers::gemini::GeminiProvider::with_base_url,
+            ),
+        ));
+    }
+
+    for mock in &config.mocks {
+        providers.push(Arc::new(mock.clone()));
+    }
+
+    providers
+}
+
+/// Apply `with_base_url` to `provider` if the config overrides its host.
+fn override_base_url<P>(
+    config: &AegisConfig,
+    provider_type: ProviderType,
+    provider: P,
+    with_base_url: fn(P, String) -> P,
+) -> P {
+    match config.base_urls.get(&provider_type) {
+        Some(base_url) => with_base_url(provider, base_url.clone()),
+        None => provider,
+    }
+}
+
+/// The HTTP client shared by every provider `Aegis::new` builds, so they
+/// draw on one connection pool.
+fn http_client(config: &AegisConfig) -> reqwest::Client {
+    if config.danger_accept_invalid_certs {
+        warn!("TLS certificate verification is disabled; do not use this outside development");
+    }
+    let builder = reqwest::Client::builder()
+        .danger_accept_invalid_certs(config.danger_accept_invalid_certs);
+    let builder = match config.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+    let builder = match config.pool_max_idle_per_host {
+        Some(max) => builder.pool_max_idle_per_host(max),
+        None => builder,
+    };
+    let builder = match config.pool_idle_timeout {
+        Some(timeout) => builder.pool_idle_timeout(timeout),
+        None => builder,
+    };
+    // Only connecting is bounded here: a client-wide timeout would also cut
+    // off stream bodies, so whole calls are bounded per request by `timed`.
+    let builder = match config.request_timeout {
+        Some(timeout) => builder.connect_timeout(timeout),
+        None => builder,
+    };
+    builder.build().expect("the TLS backend failed to initialise")
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != *cb);
+            current.push(substitute.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Remember the reply's `system_fingerprint` for its model, warning when it
+/// differs from the previous one: OpenAI has changed the serving backend.
+fn note_fingerprint(fingerprints: &Mutex<HashMap<String, String>>, metadata: &Metadata) {
+    let (Some(model), Some(fingerprint)) = (&metadata.model, &metadata.system_fingerprint) else {
+        return;
+    };
+    let previous = fingerprints
+        .lock()
+        .unwrap()
+        .insert(model.clone(), fingerprint.clone());
+    if let Some(previous) = previous.filter(|p| p != fingerprint) {
+        warn!(
+            "system_fingerprint for {} changed from {} to {}; seeded output may not reproduce",
+            model, previous, fingerprint
+        );
+    }
+}
+
+/// The highest-scoring candidate, keeping the earliest on ties.
+fn pick_best(candidates: Vec<Message>, scorer: impl Fn(&Message) -> f64) -> Option<Message> {
+    let mut best: Option<(f64, Message)> = None;
+    for candidate in candidates {
+        let score = scorer(&candidate);
+        if best.as_ref().is_none_or(|(top, _)| score > *top) {
+            best = Some((score, candidate));
+        }
+    }
+    best.map(|(_, message)| message)
+}
+
+/// Run `request`, failing with [`AegisError::Cancelled`] if the options'
+/// cancellation token fires first, or with [`AegisError::Timeout`] if their
+/// deadline passes first. A deadline already past fails without starting it.
+async fn bounded<T>(
+    options: &SendOptions,
+    request: impl std::future::Future<Output = Result<T, AegisError>>,
+) -> Result<T, AegisError> {
+    if options.deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+        return Err(AegisError::Timeout);
+    }
+    let request = async {
+        match options.deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), request)
+                .await
+                .unwrap_or(Err(AegisError::Timeout)),
+            None => request.await,
+        }
+    };
+    match &options.cancellation {
+        Some(token) => token
+            .run_until_cancelled(request)
+            .await
+            .unwrap_or(Err(AegisError::Cancelled)),
+        None => request.await,
+    }
+}
+
+/// Run one provider request, failing with [`AegisError::Timeout`] if it
+/// takes longer than `timeout` (see `AegisConfig::with_timeout`).
+async fn timed<T>(
+    timeout: Option<Duration>,
+    request: impl std::future::Future<Output = Result<T, AegisError>>,
+) -> Result<T, AegisError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, request)
+            .await
+            .unwrap_or(Err(AegisError::Timeout)),
+        None => request.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    use crate::providers::{
+        anthropic::AnthropicProvider,
+        openai::OpenAIProvider,
+        testing::{EchoProvider, GatedProvider, ScriptedProvider, StalledProvider},
+    };
+    use tokio::sync::{Notify, Semaphore};
+
+    fn prompt(text: &str) -> Vec<Message> {
+        vec![Message::user(text)]
+    }
+
+    #[test]
+    fn remaining_context_subtracts_prompt_from_window() {
+        let config = AegisConfig::new().with_model("gpt-4o-tiny", ModelSpec::new(10, 10));
+        let aegis = Aegis::with_providers(Vec::new(), config);
+        let messages = prompt("What is the capital of Vietnam?");
+        let used = aegis.count_tokens(ProviderType::OpenAI, "gpt-4o", &messages).unwrap();
+
+        let remaining = aegis.remaining_context(ProviderType::OpenAI, "gpt-4o", &messages).unwrap();
+        assert_eq!(remaining, 128_000 - i64::from(used));
+
+        let over = aegis.remaining_context(ProviderType::OpenAI, "gpt-4o-tiny", &messages).unwrap();
+        assert_eq!(over, 10 - i64::from(used));
+        assert!(over < 0);
+
+        let unknown = aegis.remaining_context(ProviderType::OpenAI, "llama-3", &messages);
+        assert!(matches!(unknown, Err(AegisError::Unsupported(_))));
+
+        let estimated = aegis
+            .remaining_context(ProviderType::Anthropic, "claude-3-haiku", &messages)
+            .unwrap();
+        assert_eq!(estimated, 200_000 - tokens::estimate_tokens(&messages) as i64);
+    }
+
+    #[tokio::test]
+    async fn images_fall_back_to_captions_for_text_only_providers() {
+        let mut messages = prompt("Compare ");
+        messages[0].content.parts.extend([
+            ContentPart::Image {
+                image_url: "https://example.com/cat.png".to_string(),
+                caption: Some("a cat".to_string()),
+            },
+            ContentPart::text(" with "),
+            ContentPart::image("https://example.com/dog.png"),
+        ]);
+
+        let strict = Aegis::with_providers(vec![Arc::new(EchoProvider)], AegisConfig::new());
+        let result = strict.send_message(ProviderType::Anthropic, messages.clone()).await;
+        assert!(matches!(result, Err(AegisError::Unsupported(_))));
+
+        let lenient = Aegis::with_providers(
+            vec![Arc::new(EchoProvider)],
+            AegisConfig::new().with_image_fallback(ImageFallback::Placeholder),
+        );
+        let reply = lenient.send_message(ProviderType::Anthropic, messages).await.unwrap();
+        assert_eq!(reply.content.to_string(), "Compare a cat with [image omitted]");
+    }
+
+    fn anthropic_reply(text: &str, stop_reason: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "msg_1",
+            "content": [{ "type": "text", "text": text }],
+            "stop_reason": stop_reason,
+            "usage": { "input_tokens": 10, "output_tokens": 4 }
+        }))
+    }
+
+    #[tokio::test]
+    async fn continue_response_joins_truncated_output() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(anthropic_reply("Hanoi is the capital", "max_tokens"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(anthropic_reply(" of Vietnam.", "end_turn"))
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                AnthropicProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+        let messages = prompt("Capital of Vietnam?");
+
+        let truncated = aegis
+            .send_message(ProviderType::Anthropic, messages.clone())
+            .await
+            .unwrap();
+        let complete = aegis
+            .continue_response(ProviderType::Anthropic, messages, truncated)
+            .await
+            .unwrap();
+
+        assert_eq!(complete.content.to_string(), "Hanoi is the capital of Vietnam.");
+        let metadata = complete.metadata.unwrap();
+        assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(metadata.usage.unwrap().completion_tokens, 8);
+    }
+
+    #[tokio::test]
+    async fn validate_model_checks_the_cached_model_list() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": "list",
+                "data": [
+                    { "id": "gpt-4o", "object": "model" },
+                    { "id": "gpt-4-turbo-preview", "object": "model" },
+                    { "id": "gpt-3.5-turbo", "object": "model" }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+
+        aegis.validate_model(ProviderType::OpenAI, "gpt-4o").await.unwrap();
+        let error = aegis
+            .validate_model(ProviderType::OpenAI, "gpt-4-turbo-preveiw")
+            .await
+            .unwrap_err();
+
+        let AegisError::ModelNotFound { model, available } = &error else {
+            panic!("expected ModelNotFound, got {:?}", error);
+        };
+        assert_eq!(model, "gpt-4-turbo-preveiw");
+        assert_eq!(available[0], "gpt-4-turbo-preview");
+        assert_eq!(available.len(), 3);
+        assert!(error.to_string().ends_with("did you mean gpt-4-turbo-preview?"));
+    }
+
+    #[tokio::test]
+    async fn cost_report_groups_spend_by_tag() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(anthropic_reply("Hanoi.", "end_turn"))
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                AnthropicProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+
+        for team in ["search", "search", "ads"] {
+            let options = SendOptions::new().with_tag("team", team).with_tag("env", "prod");
+            aegis
+                .send_message_with_options(ProviderType::Anthropic, prompt("Capital?"), &options)
+                .await
+                .unwrap();
+        }
+
+        let report = aegis.cost_report();
+        assert_eq!(report.total.requests, 3);
+        assert_eq!(report.total.prompt_tokens, 30);
+        // claude-3-sonnet: 10 input tokens at $3/M plus 4 output tokens at $15/M
+        assert!((report.total.cost_usd - 3.0 * 0.00009).abs() < 1e-12);
+        let search = report.tag("team", "search").unwrap();
+        assert_eq!(search.requests, 2);
+        assert_eq!(search.completion_tokens, 8);
+        assert_eq!(report.tag("team", "ads").unwrap().requests, 1);
+        assert_eq!(report.tag("env", "prod").unwrap().requests, 3);
+        assert!(report.tag("team", "infra").is_none());
+    }
+
+    #[tokio::test]
+    async fn cost_report_and_usage_tracker_total_sent_and_streamed_usage() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(anthropic_reply("Hanoi.", "end_turn"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/anthropic/stream_text.json"))
+                .unwrap();
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(fixture["body"].as_str().unwrap(), "text/event-stream"),
+            )
+            .mount(&server)
+            .await;
+        let tracker = UsageTracker::new();
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                AnthropicProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new().with_usage_tracker(tracker.clone()),
+        );
+        let options = SendOptions::new().with_tag("conversation", "c1");
+
+        aegis
+            .send_message_with_options(ProviderType::Anthropic, prompt("Capital?"), &options)
+            .await
+            .unwrap();
+        let stream = aegis
+            .stream_message_with_options(ProviderType::Anthropic, prompt("Capital?"), &options)
+            .await
+            .unwrap();
+        stream.for_each(|_| async {}).await;
+
+        let report = aegis.cost_report();
+        let conversation = report.tag("conversation", "c1").unwrap();
+        assert_eq!(conversation.requests, 2);
+        // 10 + 4 sent, 14 + 10 streamed
+        assert_eq!(conversation.prompt_tokens, 24);
+        assert_eq!(conversation.completion_tokens, 14);
+        assert_eq!(conversation.total_tokens(), 38);
+        assert_eq!(report.total, *conversation);
+        assert_eq!(tracker.total_prompt_tokens(), 24);
+        assert_eq!(tracker.total_completion_tokens(), 14);
+        assert_eq!(tracker.total_tokens(), 38);
+    }
+
+    #[tokio::test]
+    async fn filtered_replies_still_count_toward_the_cost_report() {
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/openai/content_filter.json"))
+                .unwrap();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&fixture["body"]))
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+
+        let result = aegis.send_message(ProviderType::OpenAI, prompt("Describe the siege.")).await;
+
+        assert!(matches!(result, Err(AegisError::ContentFiltered { .. })));
+        let report = aegis.cost_report();
+        assert_eq!(report.total.requests, 1);
+        assert_eq!(report.total.prompt_tokens, 21);
+        assert_eq!(report.total.completion_tokens, 4);
+    }
+
+    fn openai_reply() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "choices": [{
+                "message": { "role": "assistant", "content": "Hanoi." },
+                "finish_reason": "stop"
+            }]
+        }))
+    }
+
+    #[tokio::test]
+    async fn missing_usage_is_estimated_when_enabled() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(openai_reply())
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new().with_usage_estimation(true),
+        );
+        let options = SendOptions::new().with_model("gpt-4o");
+
+        let reply = aegis
+            .send_message_with_options(ProviderType::OpenAI, prompt("Capital?"), &options)
+            .await
+            .unwrap();
+
+        let usage = reply.metadata.unwrap().usage.unwrap();
+        assert!(usage.estimated);
+        let prompt_tokens = aegis.count_tokens(ProviderType::OpenAI, "gpt-4o", &prompt("Capital?"));
+        assert_eq!(usage.prompt_tokens, prompt_tokens.unwrap());
+        assert!(usage.completion_tokens > 0);
+        assert_eq!(usage.total_tokens, usage.prompt_tokens + usage.completion_tokens);
+        assert!(aegis.cost_report().total.cost_usd > 0.0);
+
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(ScriptedProvider::new(ProviderType::OpenAI, &["Ha", "noi"]))],
+            AegisConfig::new().with_usage_estimation(true),
+        );
+        let stream = aegis.stream_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        let deltas: Vec<_> = stream.collect().await;
+
+        assert_eq!(deltas.len(), 3);
+        let last = deltas[2].as_ref().unwrap();
+        assert!(last.content.parts.is_empty());
+        let usage = last.metadata.as_ref().unwrap().usage.as_ref().unwrap();
+        // No model, so four characters per token: "Capital?" and "Hanoi"
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (2, 2));
+        assert!(usage.estimated);
+    }
+
+    #[tokio::test]
+    async fn configured_base_url_replaces_the_official_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(wiremock::matchers::path("/v1/chat/completions"))
+            .respond_with(openai_reply())
+            .expect(1)
+            .mount(&server)
+            .await;
+        let config = AegisConfig::new()
+            .with_openai("test-key".to_string())
+            .with_base_url(ProviderType::OpenAI, server.uri());
+
+        let reply = Aegis::new(config)
+            .send_message(ProviderType::OpenAI, prompt("Capital?"))
+            .await
+            .unwrap();
+
+        assert_eq!(reply.content.to_string(), "Hanoi.");
+    }
+
+    #[tokio::test]
+    async fn temperature_is_dropped_for_reasoning_models() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(openai_reply())
+            .mount(&server)
+            .await;
+        let provider = || -> Vec<Arc<dyn Provider>> {
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )]
+        };
+        let options = SendOptions::new().with_model("o1-2024-12-17").with_temperature(0.2);
+
+        let aegis = Aegis::with_providers(provider(), AegisConfig::new());
+        aegis
+            .send_message_with_options(ProviderType::OpenAI, prompt("Capital?"), &options)
+            .await
+            .unwrap();
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = requests[0].body_json().unwrap();
+        assert_eq!(body["model"], "o1-2024-12-17");
+        assert!(body.get("temperature").is_none());
+
+        let strict = Aegis::with_providers(
+            provider(),
+            AegisConfig::new().with_sampling_policy(SamplingPolicy::Error),
+        );
+        let result = strict
+            .send_message_with_options(ProviderType::OpenAI, prompt("Capital?"), &options)
+            .await;
+        assert!(matches!(result, Err(AegisError::Unsupported(_))));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn last_system_fingerprint_tracks_the_latest_reply() {
+        let server = MockServer::start().await;
+        let reply = |fingerprint: &str| {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "gpt-4o-2024-08-06",
+                "system_fingerprint": fingerprint,
+                "choices": [{
+                    "message": { "role": "assistant", "content": "Hanoi." },
+                    "finish_reason": "stop"
+                }]
+            }))
+        };
+        Mock::given(method("POST"))
+            .respond_with(reply("fp_a"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(reply("fp_b"))
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+
+        aegis.send_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        let first = aegis.last_system_fingerprint("gpt-4o-2024-08-06");
+        aegis.send_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+
+        assert_eq!(first.as_deref(), Some("fp_a"));
+        assert_eq!(
+            aegis.last_system_fingerprint("gpt-4o-2024-08-06").as_deref(),
+            Some("fp_b")
+        );
+    }
+
+    #[test]
+    fn out_of_range_temperature_is_clamped() {
+        let aegis = Aegis::with_providers(Vec::new(), AegisConfig::new());
+        let options = SendOptions::new().with_model("claude-3-haiku-20240307").with_temperature(1.5);
+
+        let fitted = aegis.fit_sampling(&options).unwrap();
+        assert_eq!(fitted.generation.temperature, Some(1.0));
+
+        let options = options.with_temperature(0.5);
+        assert_eq!(aegis.fit_sampling(&options).unwrap().generation.temperature, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn batch_keeps_input_order_and_isolates_failures() {
+        let aegis = Aegis::with_providers(vec![Arc::new(EchoProvider)], AegisConfig::new());
+        let inputs = vec![prompt("one"), prompt(""), prompt("three"), prompt("four")];
+        let mut reported = Vec::new();
+
+        let results = aegis
+            .batch_with_progress(ProviderType::Anthropic, inputs, 2, |done, total| {
+                reported.push((done, total))
+            })
+            .await;
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().content.to_string(), "one");
+        assert!(matches!(results[1], Err(AegisError::APIError(_))));
+        assert_eq!(results[2].as_ref().unwrap().content.to_string(), "three");
+        assert_eq!(results[3].as_ref().unwrap().content.to_string(), "four");
+        assert_eq!(reported, vec![(1, 4), (2, 4), (3, 4), (4, 4)]);
+    }
+
+    #[tokio::test]
+    async fn race_returns_first_success_and_cancels_the_rest() {
+        let stalled = Arc::new(StalledProvider::default());
+        let cancelled = Arc::clone(&stalled.cancelled);
+        let aegis = Aegis::with_providers(vec![stalled, Arc::new(EchoProvider)], AegisConfig::new());
+
+        let (winner, reply) = aegis
+            .race(vec![ProviderType::OpenAI, ProviderType::Anthropic], prompt("Hanoi"))
+            .await
+            .unwrap();
+
+        assert_eq!(winner, ProviderType::Anthropic);
+        assert_eq!(reply.content.to_string(), "Hanoi");
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn broadcast_fills_slow_providers_with_timeouts() {
+        let stalled = Arc::new(StalledProvider::default());
+        let cancelled = Arc::clone(&stalled.cancelled);
+        let aegis = Aegis::with_providers(vec![stalled, Arc::new(EchoProvider)], AegisConfig::new());
+
+        let results = aegis
+            .broadcast(
+                vec![ProviderType::OpenAI, ProviderType::Anthropic],
+                prompt("Hanoi"),
+                Duration::from_secs(5),
+            )
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, ProviderType::OpenAI);
+        assert!(matches!(results[0].1, Err(AegisError::Timeout)));
+        assert_eq!(results[1].0, ProviderType::Anthropic);
+        assert_eq!(results[1].1.as_ref().unwrap().content.to_string(), "Hanoi");
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_requests_fail_after_the_request_timeout() {
+        let stalled = Arc::new(StalledProvider::default());
+        let cancelled = Arc::clone(&stalled.cancelled);
+        let config = AegisConfig::new().with_timeout(Duration::from_secs(30));
+        let aegis = Aegis::with_providers(vec![stalled], config);
+        let started = tokio::time::Instant::now();
+
+        let result = aegis.send_message(ProviderType::OpenAI, prompt("Hanoi")).await;
+
+        assert!(matches!(result, Err(AegisError::Timeout)));
+        assert_eq!(started.elapsed(), Duration::from_secs(30));
+        assert!(cancelled.load(Ordering::SeqCst));
+
+        let result = aegis.stream_message(ProviderType::OpenAI, prompt("Hanoi")).await;
+
+        assert!(matches!(result, Err(AegisError::Timeout)));
+        assert_eq!(started.elapsed(), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn request_timeout_also_bounds_calls_outside_send() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+        let config = AegisConfig::new()
+            .with_openai("test-key".to_string())
+            .with_base_url(ProviderType::OpenAI, server.uri())
+            .with_timeout(Duration::from_millis(50));
+
+        let result = Aegis::new(config).list_models(ProviderType::OpenAI).await;
+
+        assert!(matches!(result, Err(AegisError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn streams_may_outlive_the_request_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/openai/stream_text.json"))
+                .unwrap();
+        let body = fixture["body"].as_str().unwrap().to_string();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // Answers at once, then takes longer than the timeout to finish the body
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            assert!(socket.read(&mut [0; 4096]).await.unwrap() > 0);
+            let (first, rest) = body.split_at(body.find(" is Hanoi").unwrap());
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                        connection: close\r\n\r\n";
+            socket.write_all(format!("{}{}", head, first).as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            socket.write_all(rest.as_bytes()).await.unwrap();
+        });
+        let config = AegisConfig::new()
+            .with_openai("test-key".to_string())
+            .with_base_url(ProviderType::OpenAI, format!("http://{}", address))
+            .with_timeout(Duration::from_millis(100));
+
+        let stream = Aegis::new(config)
+            .stream_message(ProviderType::OpenAI, prompt("Capital?"))
+            .await
+            .unwrap();
+        let deltas: Vec<_> = stream.collect().await;
+
+        let text: String = deltas.iter().map(|d| d.as_ref().unwrap().content.to_string()).collect();
+        assert_eq!(text, "The capital of Vietnam is Hanoi.");
+    }
+
+    #[tokio::test]
+    async fn consensus_scores_agreement_between_providers() {
+        let aegis = Aegis::with_providers(
+            vec![
+                Arc::new(EchoProvider),
+                Arc::new(ScriptedProvider::new(ProviderType::OpenAI, &["Hanoi."])),
+                Arc::new(ScriptedProvider::new(ProviderType::Cohere, &["Saigon"])),
+            ],
+            AegisConfig::new(),
+        );
+        let providers = vec![ProviderType::Anthropic, ProviderType::OpenAI, ProviderType::Cohere];
+
+        let result = aegis
+            .consensus(providers, prompt("Hanoi"), consensus::token_overlap)
+            .await
+            .unwrap();
+
+        assert_eq!(result.responses.len(), 3);
+        assert_eq!(result.responses[1].provider_type, ProviderType::OpenAI);
+        assert_eq!(result.responses[1].text, "Hanoi.");
+        assert!((result.score - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn latency_histograms_record_sends_streams_and_completions() {
+        let provider = || -> Vec<Arc<dyn Provider>> {
+            vec![Arc::new(ScriptedProvider::new(ProviderType::OpenAI, &["Ha", "noi"]))]
+        };
+        let disabled = Aegis::with_providers(provider(), AegisConfig::new());
+        disabled.send_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        assert_eq!(disabled.latency_stats(ProviderType::OpenAI), None);
+
+        let aegis =
+            Aegis::with_providers(provider(), AegisConfig::new().with_latency_histograms(true));
+        aegis.send_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        assert_eq!(aegis.ttft_stats(ProviderType::OpenAI), None);
+
+        let stream = aegis.stream_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        let deltas: Vec<_> = stream.collect().await;
+        assert_eq!(deltas.len(), 2);
+        let options = SendOptions::default();
+        aegis.send_message_n(ProviderType::OpenAI, prompt("Capital?"), 2, &options).await.unwrap();
+
+        assert_eq!(aegis.latency_stats(ProviderType::OpenAI).unwrap().count, 3);
+        let ttft = aegis.ttft_stats(ProviderType::OpenAI).unwrap();
+        assert_eq!(ttft.count, 1);
+        assert!(ttft.p50 <= aegis.latency_stats(ProviderType::OpenAI).unwrap().p99);
+        assert_eq!(aegis.latency_stats(ProviderType::Anthropic), None);
+    }
+
+    #[tokio::test]
+    async fn shared_stream_reaches_every_subscriber() {
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(ScriptedProvider::new(ProviderType::OpenAI, &["Ha", "noi"]))],
+            AegisConfig::new(),
+        );
+        let mut first =
+            aegis.stream_shared(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        let mut second = first.resubscribe();
+
+        for receiver in [&mut first, &mut second] {
+            let mut text = String::new();
+            loop {
+                match receiver.recv().await {
+                    Ok(delta) => text.push_str(&delta.unwrap().content.to_string()),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                    Err(e) => panic!("unexpected {:?}", e),
+                }
+            }
+            assert_eq!(text, "Hanoi");
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_stops_once_usage_crosses_the_budget() {
+        // gpt-4o output is $10/M, so each delta costs $0.0006
+        let usage = Metadata {
+            model: Some("gpt-4o".to_string()),
+            usage: Some(models::Usage {
+                completion_tokens: 60,
+                total_tokens: 60,
+                ..models::Usage::default()
+            }),
+            ..Metadata::default()
+        };
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                ScriptedProvider::new(ProviderType::OpenAI, &["Ha", "noi", " is", " the"])
+                    .with_metadata(usage),
+            )],
+            AegisConfig::new().with_budget(0.001),
+        );
+
+        let stream = aegis.stream_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        let deltas: Vec<_> = stream.collect().await;
+
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[1].as_ref().unwrap().content.to_string(), "noi");
+        match &deltas[2] {
+            Err(AegisError::BudgetExceeded { spent_usd, budget_usd }) => {
+                assert!((spent_usd - 0.0012).abs() < 1e-12);
+                assert_eq!(*budget_usd, 0.001);
+            }
+            other => panic!("expected BudgetExceeded, got {:?}", other),
+        }
+        let next = aegis.send_message(ProviderType::OpenAI, prompt("Capital?")).await;
+        assert!(matches!(next, Err(AegisError::BudgetExceeded { .. })));
+    }
+
+    #[tokio::test]
+    async fn stream_stops_when_usage_reaches_the_budget_exactly() {
+        let usage = Metadata {
+            model: Some("gpt-4o".to_string()),
+            usage: Some(models::Usage {
+                completion_tokens: 60,
+                total_tokens: 60,
+                ..models::Usage::default()
+            }),
+            ..Metadata::default()
+        };
+        let scripted = |chunks| {
+            ScriptedProvider::new(ProviderType::OpenAI, chunks).with_metadata(usage.clone())
+        };
+        let unlimited =
+            Aegis::with_providers(vec![Arc::new(scripted(&["Ha"]))], AegisConfig::new());
+        let stream = unlimited.stream_message(ProviderType::OpenAI, prompt("Capital?")).await;
+        stream.unwrap().collect::<Vec<_>>().await;
+        let one_delta_usd = unlimited.cost_report().total.cost_usd;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(scripted(&["Ha", "noi"]))],
+            AegisConfig::new().with_budget(one_delta_usd),
+        );
+
+        let stream = aegis.stream_message(ProviderType::OpenAI, prompt("Capital?")).await.unwrap();
+        let deltas: Vec<_> = stream.collect().await;
+
+        assert_eq!(deltas.len(), 2);
+        assert!(matches!(deltas[1], Err(AegisError::BudgetExceeded { .. })));
+        let next = aegis.send_message(ProviderType::OpenAI, prompt("Capital?")).await;
+        assert!(matches!(next, Err(AegisError::BudgetExceeded { .. })));
+    }
+
+    #[tokio::test]
+    async fn reload_lets_in_flight_requests_finish_on_the_old_providers() {
+        let gate = Arc::new(Semaphore::new(0));
+        let started = Arc::new(Notify::new());
+        let old = GatedProvider {
+            text: "old",
+            gate: Arc::clone(&gate),
+            started: Arc::clone(&started),
+        };
+        let aegis = Arc::new(Aegis::with_providers(vec![Arc::new(old)], AegisConfig::new()));
+        let in_flight = tokio::spawn({
+            let aegis = Arc::clone(&aegis);
+            async move { aegis.send_message(ProviderType::OpenAI, prompt("Hi")).await }
+        });
+        started.notified().await;
+
+        aegis.replace_providers(vec![Arc::new(ScriptedProvider::new(
+            ProviderType::OpenAI,
+            &["new"],
+        ))]);
+        let reply = aegis.send_message(ProviderType::OpenAI, prompt("Hi")).await.unwrap();
+        assert_eq!(reply.content.to_string(), "new");
+        gate.add_permits(1);
+        let reply = in_flight.await.unwrap().unwrap();
+        assert_eq!(reply.content.to_string(), "old");
+
+        aegis.reload_providers(&AegisConfig::new().with_anthropic("rotated-key".to_string()));
+        assert!(aegis.get_provider(ProviderType::Anthropic).is_ok());
+        assert!(matches!(
+            aegis.get_provider(ProviderType::OpenAI),
+            Err(AegisError::ProviderNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reload_rebuilds_the_embedding_provider() {
+        let aegis = Aegis::with_providers(Vec::new(), AegisConfig::new());
+        let result = aegis.embed(vec!["Hanoi".to_string()]).await;
+        assert!(matches!(result, Err(AegisError::ProviderNotFound)));
+
+        aegis.reload_providers(&AegisConfig::new().with_voyage("rotated-key".to_string()));
+        assert!(aegis.embedding.read().unwrap().is_some());
+
+        aegis.reload_providers(&AegisConfig::new());
+        assert!(aegis.embedding.read().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn models_outside_the_allow_list_are_rejected() {
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(EchoProvider)],
+            AegisConfig::new().with_allowed_models(
+                ProviderType::Anthropic,
+                vec!["claude-3-haiku-20240307".to_string()],
+            ),
+        );
+        let send = |model: &str| {
+            let options = SendOptions::new().with_model(model);
+            let aegis = &aegis;
+            async move {
+                aegis
+                    .send_message_with_options(ProviderType::Anthropic, prompt("Hi"), &options)
+                    .await
+            }
+        };
+
+        assert!(send("claude-3-haiku-20240307").await.is_ok());
+        match send("claude-3-opus-20240229").await {
+            Err(AegisError::ModelNotAllowed { provider, model }) => {
+                assert_eq!(provider, ProviderType::Anthropic);
+                assert_eq!(model, "claude-3-opus-20240229");
+            }
+            other => panic!("expected ModelNotAllowed, got {:?}", other),
+        }
+        let options = SendOptions::new().with_model("claude-3-opus-20240229");
+        let stream = aegis
+            .stream_message_with_options(ProviderType::Anthropic, prompt("Hi"), &options)
+            .await;
+        assert!(matches!(stream, Err(AegisError::ModelNotAllowed { .. })));
+        assert!(aegis.send_message(ProviderType::Anthropic, prompt("Hi")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_message_default_uses_the_configured_default() {
+        let providers = || -> Vec<Arc<dyn Provider>> {
+            vec![
+                Arc::new(EchoProvider),
+                Arc::new(ScriptedProvider::new(ProviderType::OpenAI, &["Hanoi."])),
+            ]
+        };
+
+        let ambiguous = Aegis::with_providers(providers(), AegisConfig::new());
+        match ambiguous.send_message_default(prompt("Capital of Vietnam?")).await {
+            Err(AegisError::NoDefaultProvider { configured }) => {
+                assert_eq!(configured, [ProviderType::Anthropic, ProviderType::OpenAI]);
+            }
+            other => panic!("expected NoDefaultProvider, got {:?}", other),
+        }
+
+        let config = AegisConfig::new().with_default_provider(ProviderType::OpenAI);
+        let aegis = Aegis::with_providers(providers(), config);
+        let reply = aegis.send_message_default(prompt("Capital of Vietnam?")).await.unwrap();
+        assert_eq!(reply.content.to_string(), "Hanoi.");
+
+        let single = Aegis::with_providers(vec![Arc::new(EchoProvider)], AegisConfig::new());
+        assert_eq!(single.default_provider().unwrap(), ProviderType::Anthropic);
+
+        let missing = AegisConfig::new().with_default_provider(ProviderType::Cohere);
+        let missing = Aegis::with_providers(providers(), missing);
+        assert!(matches!(missing.default_provider(), Err(AegisError::ProviderNotFound)));
+    }
+
+    #[test]
+    fn pick_best_keeps_the_earliest_top_score() {
+        let candidates: Vec<Message> = ["Hanoi", "It is Hanoi.", "Hanoi, VN"]
+            .into_iter()
+            .flat_map(prompt)
+            .collect();
+
+        let longest = pick_best(candidates.clone(), |m| m.content.to_string().len() as f64);
+        assert_eq!(longest.unwrap().content.to_string(), "It is Hanoi.");
+
+        let first = pick_best(candidates, |_| 0.0);
+        assert_eq!(first.unwrap().content.to_string(), "Hanoi");
+        assert!(pick_best(Vec::new(), |_| 0.0).is_none());
+    }
+
+    #[tokio::test]
+    async fn best_of_returns_the_top_candidate() {
+        let aegis = Aegis::with_providers(vec![Arc::new(EchoProvider)], AegisConfig::new());
+
+        let reply = aegis
+            .best_of(ProviderType::Anthropic, prompt("Hanoi"), 3, |_| 1.0)
+            .await
+            .unwrap();
+
+        assert_eq!(reply.content.to_string(), "Hanoi");
+    }
+
+    #[tokio::test]
+    async fn configured_http_version_is_used() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        for (version, expected) in [
+            (HttpVersion::Http1, reqwest::Version::HTTP_11),
+            (HttpVersion::Http2, reqwest::Version::HTTP_2),
+        ] {
+            let client = http_client(&AegisConfig::new().with_http_version(version));
+            let response = client.get(server.uri()).send().await.unwrap();
+            assert_eq!(response.version(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn warmup_primes_the_provider_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+
+        aegis.warmup(ProviderType::OpenAI).await.unwrap();
+
+        assert!(matches!(
+            aegis.warmup(ProviderType::Cohere).await,
+            Err(AegisError::ProviderNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancellation_aborts_a_pending_call() {
+        let stalled = Arc::new(StalledProvider::default());
+        let dropped = Arc::clone(&stalled.cancelled);
+        let aegis = Aegis::with_providers(vec![stalled], AegisConfig::new());
+        let token = tokio_util::sync::CancellationToken::new();
+        let options = SendOptions::new().with_cancellation(token.clone());
+
+        let (result, _) = tokio::join!(
+            aegis.send_message_with_options(ProviderType::OpenAI, prompt("Hanoi"), &options),
+            async { token.cancel() },
+        );
+
+        assert!(matches!(result, Err(AegisError::Cancelled)));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn past_deadline_fails_without_a_request() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let aegis = Aegis::with_providers(
+            vec![Arc::new(
+                OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            AegisConfig::new(),
+        );
+        let options = SendOptions::new().with_deadline(Instant::now());
+
+        let result = aegis
+            .send_message_with_options(ProviderType::OpenAI, prompt("Hanoi"), &options)
+            .await;
+
+        assert!(matches!(result, Err(AegisError::Timeout)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deadline_aborts_a_stalled_call() {
+        let stalled = Arc::new(StalledProvider::default());
+        let dropped = Arc::clone(&stalled.cancelled);
+        let aegis = Aegis::with_providers(vec![stalled], AegisConfig::new());
+        let options =
+            SendOptions::new().with_deadline(Instant::now() + std::time::Duration::from_secs(60));
+
+        let result = aegis
+            .send_message_with_options(ProviderType::OpenAI, prompt("Hanoi"), &options)
+            .await;
+
+        assert!(matches!(result, Err(AegisError::Timeout)));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+}
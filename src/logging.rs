@@ -0,0 +1,77 @@
+//! Structured request logging.
+//!
+//! Every call made through [`crate::Aegis`] runs inside an `aegis_request` span
+//! carrying `provider`, `model`, `latency_ms`, `prompt_tokens`,
+//! `completion_tokens`, `status` and `error_kind`, and emits one event when the
+//! request finishes. Only these fields are recorded; API keys and message
+//! content never are. Outgoing message text is logged separately at `debug`
+//! level, masked by the configured [`RedactionPolicy`].
+
+use std::time::Instant;
+
+use tracing::{debug, field::Empty, info, info_span, warn, Level, Span};
+
+use crate::{
+    error::AegisError,
+    models::{Message, Metadata, ProviderType},
+    redaction::RedactionPolicy,
+};
+
+/// Install a global subscriber that writes one JSON object per log line,
+/// including the fields of the current request span.
+///
+/// Panics if a global subscriber has already been set.
+pub fn init_json() {
+    tracing_subscriber::fmt()
+        .json()
+        .with_current_span(true)
+        .with_span_list(false)
+        .init();
+}
+
+pub(crate) fn request_span(provider_type: &ProviderType) -> Span {
+    info_span!(
+        "aegis_request",
+        provider = ?provider_type,
+        model = Empty,
+        latency_ms = Empty,
+        prompt_tokens = Empty,
+        completion_tokens = Empty,
+        status = Empty,
+        error_kind = Empty,
+    )
+}
+
+pub(crate) fn record_success(span: &Span, started: Instant, metadata: Option<&Metadata>) {
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    if let Some(metadata) = metadata {
+        if let Some(model) = &metadata.model {
+            span.record("model", model.as_str());
+        }
+        if let Some(usage) = &metadata.usage {
+            span.record("prompt_tokens", usage.prompt_tokens);
+            span.record("completion_tokens", usage.completion_tokens);
+        }
+    }
+    span.in_scope(|| info!("request completed"));
+}
+
+pub(crate) fn record_failure(span: &Span, started: Instant, error: &AegisError) {
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    span.record("error_kind", error.kind());
+    span.in_scope(|| warn!("request failed"));
+}
+
+pub(crate) fn log_messages(policy: Option<&RedactionPolicy>, messages: &[Message]) {
+    if !tracing::enabled!(Level::DEBUG) {
+        return;
+    }
+    for message in messages {
+        let text = message.content.to_string();
+        let text = match policy {
+            Some(policy) => policy.redact(&text),
+            None => text,
+        };
+        debug!(role = ?message.role, "outgoing message: {}", text);
+    }
+}
@@ -17,9 +17,9 @@ async fn main() {
     let messages = vec![Message {
         role: aegis::models::Role::User,
         content: aegis::models::Content {
-            parts: vec![aegis::models::ContentPart::Text {
-                text: "What is the capital of Vietnam?".to_string()
-            }]
+            parts: vec![aegis::models::ContentPart::text(
+                "What is the capital of Vietnam?"
+            )]
         },
         metadata: None,
     }];
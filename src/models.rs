@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{error::AegisError, providers::BODY_SNIPPET_LEN};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -8,18 +11,77 @@ pub struct Message {
     pub metadata: Option<Metadata>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl Message {
+    /// A message made of `parts`, with no metadata.
+    pub fn new(role: Role, parts: Vec<ContentPart>) -> Self {
+        Self {
+            role,
+            content: Content { parts },
+            metadata: None,
+        }
+    }
+
+    /// A message holding just `text`.
+    pub fn text(role: Role, text: impl Into<String>) -> Self {
+        Self::new(role, vec![ContentPart::text(text)])
+    }
+
+    /// A user message holding just `text`.
+    pub fn user(text: impl Into<String>) -> Self {
+        Self::text(Role::User, text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ProviderType {
     Anthropic,
     OpenAI,
+    Cohere,
+    Mistral,
+    Xai,
+    Gemini,
+    /// A provider built outside this crate, such as
+    /// [`MockProvider`](crate::providers::mock::MockProvider), told apart by
+    /// the name it was registered under.
+    Custom(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ProviderType {
+    /// Lowercase name, as reported in `Metadata::provider`. Every custom
+    /// provider is `"custom"` here; they report their own name through
+    /// `Provider::name`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderType::Anthropic => "anthropic",
+            ProviderType::OpenAI => "openai",
+            ProviderType::Cohere => "cohere",
+            ProviderType::Mistral => "mistral",
+            ProviderType::Xai => "xai",
+            ProviderType::Gemini => "gemini",
+            ProviderType::Custom(_) => "custom",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     User,
     Assistant,
     System,
+    Tool,
+}
+
+impl Role {
+    /// The role's own name, as it serializes.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::System => "system",
+            Role::Tool => "tool",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,7 +93,7 @@ impl std::fmt::Display for Content {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let text = self.parts.iter()
             .filter_map(|part| match part {
-                ContentPart::Text { text } => Some(text.as_str()),
+                ContentPart::Text { text, .. } => Some(text.as_str()),
                 _ => None
             })
             .collect::<Vec<&str>>()
@@ -44,27 +106,320 @@ impl std::fmt::Display for Content {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ContentPart {
-    Text { text: String },
-    Image { image_url: String },
+    Text {
+        text: String,
+        /// Sources the text cites, when the provider reports them.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        annotations: Vec<Citation>,
+        /// Cache the prompt up to and including this part, for providers
+        /// with explicit prompt caching (Anthropic's `cache_control`, at
+        /// most 4 per request). Ignored elsewhere.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        cache: bool,
+    },
+    Image {
+        image_url: String,
+        /// Short description of the image, used in its place when the
+        /// provider can't take images (see `ImageFallback`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        caption: Option<String>,
+    },
+    ToolCall(ToolCall),
+    ToolResult { tool_call_id: String, content: String },
+    /// The model's visible thinking, for models that expose it (DeepSeek's
+    /// `reasoning_content`, Anthropic's extended thinking). Kept apart from
+    /// the answer: `Content`'s `Display` skips it, and it is not sent back.
+    Reasoning { text: String },
     // Future: add more content types
 }
 
+impl ContentPart {
+    /// A plain text part with no annotations.
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text {
+            text: text.into(),
+            annotations: Vec::new(),
+            cache: false,
+        }
+    }
+
+    /// A text part marked as a prompt cache breakpoint, e.g. the end of a
+    /// long document that later requests repeat.
+    pub fn cached_text(text: impl Into<String>) -> Self {
+        ContentPart::Text {
+            text: text.into(),
+            annotations: Vec::new(),
+            cache: true,
+        }
+    }
+
+    /// An image part without a caption.
+    pub fn image(image_url: impl Into<String>) -> Self {
+        ContentPart::Image {
+            image_url: image_url.into(),
+            caption: None,
+        }
+    }
+}
+
+/// A source cited by a span of model output, e.g. from web search or
+/// document retrieval.
+///
+/// The character range is where the provider puts it: OpenAI reports the
+/// cited span of the output text, Anthropic the quoted span of a source
+/// document.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    pub url: Option<String>,
+    pub title: Option<String>,
+    /// The quoted source text, when the provider includes it.
+    pub cited_text: Option<String>,
+    pub start_index: Option<usize>,
+    pub end_index: Option<usize>,
+}
+
+/// A function the model may call, described by a JSON Schema for its arguments.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl ToolDefinition {
+    /// A tool whose parameters are the JSON Schema derived from `T`, so the
+    /// schema can't drift from the type its calls are parsed into with
+    /// [`ToolCall::parse_arguments`].
+    ///
+    /// ```
+    /// use aegis::models::ToolDefinition;
+    ///
+    /// #[derive(serde::Deserialize, schemars::JsonSchema)]
+    /// struct GetWeather {
+    ///     /// City and country, e.g. "Hanoi, Vietnam".
+    ///     location: String,
+    /// }
+    ///
+    /// let tool = ToolDefinition::from_type::<GetWeather>("get_weather", "Current weather");
+    /// assert_eq!(tool.parameters["required"][0], "location");
+    /// ```
+    pub fn from_type<T: JsonSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        let mut parameters = serde_json::Value::from(schemars::schema_for!(T));
+        // Providers want the bare object schema, without the meta-schema URI
+        if let Some(schema) = parameters.as_object_mut() {
+            schema.remove("$schema");
+            schema.remove("title");
+        }
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+        }
+    }
+}
+
+/// Whether, and which, tool the model must call. Only sent alongside tools.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool.
+    Auto,
+    /// The model must not call a tool.
+    None,
+    /// The model must call at least one tool.
+    Required,
+    /// The model must call the named tool.
+    Specific(String),
+}
+
+/// A tool invocation requested by the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    /// Deserialize the arguments into the type the tool was defined from
+    /// (see [`ToolDefinition::from_type`]). Arguments that don't match fail
+    /// with [`AegisError::ResponseParseError`] naming the offending field.
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> Result<T, AegisError> {
+        serde_path_to_error::deserialize(&self.arguments).map_err(|e| {
+            AegisError::ResponseParseError {
+                path: e.path().to_string(),
+                message: e.inner().to_string(),
+                snippet: self.arguments.to_string().chars().take(BODY_SNIPPET_LEN).collect(),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Metadata {
     pub model: Option<String>,
     pub provider: Option<String>,
     pub usage: Option<Usage>,
+    /// Provider-assigned ID of the response, for APIs that can chain on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_id: Option<String>,
+    /// Why the model stopped generating.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+    /// The stop sequence that ended generation, for providers that report
+    /// which one matched (currently Anthropic).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequence: Option<String>,
+    /// OpenAI's identifier for the backend configuration that served the
+    /// request. A change between runs means seeded output may differ.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+    /// Deprecation and other notices the provider attached to the response,
+    /// e.g. that the model is being retired. Each is also logged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Why a response ended, normalised across providers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished its turn or hit a stop sequence.
+    Stop,
+    /// The output was cut off by the token limit.
+    MaxTokens,
+    /// The model stopped to call tools.
+    ToolCalls,
+    /// The provider withheld or cut off output on policy grounds.
+    ContentFilter,
+    /// A reason we don't recognise, as the provider sent it.
+    Other(String),
+}
+
+impl FinishReason {
+    /// Map a provider's finish/stop reason string onto the common reasons.
+    pub fn from_provider(reason: &str) -> Self {
+        match reason {
+            "stop" | "end_turn" | "stop_sequence" | "COMPLETE" | "STOP_SEQUENCE" | "STOP" => {
+                FinishReason::Stop
+            }
+            "length" | "max_tokens" | "max_output_tokens" | "MAX_TOKENS" => FinishReason::MaxTokens,
+            "tool_calls" | "tool_use" | "function_call" | "TOOL_CALL" => FinishReason::ToolCalls,
+            "content_filter" | "refusal" | "ERROR_TOXIC" | "SAFETY" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Completion tokens that matched OpenAI's predicted output (see
+    /// `OpenAIOptions::with_prediction`); reported only for predictions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_prediction_tokens: Option<u32>,
+    /// Predicted tokens that went unused. They are still billed as
+    /// completion tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected_prediction_tokens: Option<u32>,
+    /// Counted locally because the provider reported no usage (see
+    /// `AegisConfig::with_usage_estimation`), so possibly off by a few
+    /// tokens.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+impl std::ops::AddAssign<&Usage> for Usage {
+    fn add_assign(&mut self, other: &Usage) {
+        fn add(total: &mut Option<u32>, other: Option<u32>) {
+            if let Some(other) = other {
+                *total = Some(total.unwrap_or(0) + other);
+            }
+        }
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        add(&mut self.accepted_prediction_tokens, other.accepted_prediction_tokens);
+        add(&mut self.rejected_prediction_tokens, other.rejected_prediction_tokens);
+        self.estimated |= other.estimated;
+    }
+}
+
+/// Several candidate replies to one prompt, from `Aegis::send_message_n`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completions {
+    /// In the provider's order, so a choice's index is its position. Each
+    /// keeps its own finish reason in its metadata.
+    pub choices: Vec<Message>,
+    /// Total across all choices.
+    pub usage: Usage,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StreamChunk {
     pub content: String,
     pub stop_reason: Option<String>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example tool arguments, as an agent would define them.
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct GetForecast {
+        /// City and country, e.g. "Hanoi, Vietnam".
+        location: String,
+        unit: TemperatureUnit,
+        /// Days ahead, defaulting to today only.
+        #[serde(default)]
+        days: Option<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    enum TemperatureUnit {
+        Celsius,
+        Fahrenheit,
+    }
+
+    #[test]
+    fn typed_tools_round_trip_their_arguments() {
+        let tool = ToolDefinition::from_type::<GetForecast>("get_forecast", "Weather forecast");
+        assert_eq!(tool.parameters["type"], "object");
+        assert!(tool.parameters.get("$schema").is_none());
+        assert_eq!(tool.parameters["required"], serde_json::json!(["location", "unit"]));
+        assert_eq!(
+            tool.parameters["properties"]["location"]["description"],
+            "City and country, e.g. \"Hanoi, Vietnam\"."
+        );
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: tool.name,
+            arguments: serde_json::json!({ "location": "Hanoi, Vietnam", "unit": "celsius" }),
+        };
+        assert_eq!(
+            call.parse_arguments::<GetForecast>().unwrap(),
+            GetForecast {
+                location: "Hanoi, Vietnam".to_string(),
+                unit: TemperatureUnit::Celsius,
+                days: None,
+            }
+        );
+
+        let bad = ToolCall {
+            arguments: serde_json::json!({ "location": "Hanoi", "unit": "kelvin" }),
+            ..call
+        };
+        match bad.parse_arguments::<GetForecast>() {
+            Err(AegisError::ResponseParseError { path, .. }) => assert_eq!(path, "unit"),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::models::{ToolChoice, ToolDefinition};
+
+/// Per-call options for `send_message`/`stream_message`.
+///
+/// This is the main way to configure a request: the `*_with_options`
+/// methods take one, and the plain methods are shorthands passing
+/// `SendOptions::default()`. New knobs are added here as fields, so existing
+/// calls keep compiling. Build one fluently:
+///
+/// ```
+/// use std::time::Duration;
+/// use aegis::options::SendOptions;
+///
+/// let options = SendOptions::new()
+///     .with_model("gpt-4o")
+///     .with_max_tokens(256)
+///     .with_stream_idle_timeout(Duration::from_secs(20));
+/// assert_eq!(options.generation.max_tokens, Some(256));
+/// ```
+///
+/// Every field is optional; `SendOptions::default()` reproduces the plain
+/// `Aegis::send_message` behaviour. Options a provider has no concept of are
+/// ignored by that provider.
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    /// Model to use instead of the provider's default.
+    pub model: Option<String>,
+    /// Sampling and length controls.
+    pub generation: GenerationParams,
+    /// Tools the model may call. Cohere and Gemini don't take tools yet, and
+    /// fail with `AegisError::Unsupported` when any are set.
+    pub tools: Vec<ToolDefinition>,
+    /// Whether the model must call a tool, and which. Only sent when `tools`
+    /// is non-empty.
+    pub tool_choice: Option<ToolChoice>,
+    /// Whether OpenAI may return several tool calls in one turn. Only sent
+    /// when `tools` is non-empty.
+    pub parallel_tool_calls: Option<bool>,
+    /// Parameters only Anthropic understands.
+    pub anthropic: AnthropicOptions,
+    /// Parameters only OpenAI understands.
+    pub openai: OpenAIOptions,
+    /// Parameters only Mistral understands.
+    pub mistral: MistralOptions,
+    /// Cancels the request when triggered: a pending call fails with
+    /// `AegisError::Cancelled` and an open stream ends early. The tool loops
+    /// in `Conversation` also stop, returning what they collected as a
+    /// cancelled `ToolRun` rather than an error.
+    pub cancellation: Option<CancellationToken>,
+    /// When the call must be done by, e.g. propagated from an incoming
+    /// request's timeout. Bounds the whole call including retries and
+    /// backoff; past it a pending call fails with `AegisError::Timeout` and
+    /// an open stream ends with it.
+    pub deadline: Option<Instant>,
+    /// Longest wait for the next stream delta before the stream ends with
+    /// `AegisError::StreamIdle`. Overrides
+    /// `AegisConfig::with_stream_idle_timeout`; ignored by non-streaming
+    /// calls.
+    pub stream_idle_timeout: Option<Duration>,
+    /// Local bookkeeping labels (team, feature, ...) that the request's usage
+    /// is attributed to in `Aegis::cost_report`. Never sent to the provider.
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Anthropic-only sampling parameters, kept apart so the common options stay portable.
+#[derive(Debug, Clone, Default)]
+pub struct AnthropicOptions {
+    /// Sample only from the `k` most likely tokens.
+    pub top_k: Option<u32>,
+}
+
+impl AnthropicOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+}
+
+/// Sampling and length controls, mapped by each provider onto its request
+/// body. Fields left `None` are not sent, so the provider's default applies.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParams {
+    /// Sampling temperature, e.g. `0.0` for deterministic completions.
+    /// Checked against the model's allowed range (see `SamplingPolicy`).
+    pub temperature: Option<f32>,
+    /// Upper bound on generated tokens. Anthropic requires one, so there it
+    /// defaults to the model's maximum output from the model registry; other
+    /// providers use their own defaults.
+    pub max_tokens: Option<u32>,
+    /// Nucleus sampling: draw only from the most likely tokens whose
+    /// probabilities add up to `top_p`.
+    pub top_p: Option<f32>,
+    /// Strings that end generation when produced. Not sent when empty.
+    pub stop_sequences: Vec<String>,
+}
+
+impl GenerationParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
+}
+
+/// OpenAI-only parameters.
+#[derive(Debug, Clone, Default)]
+pub struct OpenAIOptions {
+    /// Continue from a stored response (Responses API only), so earlier turns
+    /// need not be re-sent. Take the ID from `Metadata::response_id`.
+    pub previous_response_id: Option<String>,
+    /// Expected output, e.g. the file being edited, so that responses which
+    /// mostly repeat it come back faster (chat completions only).
+    pub prediction: Option<String>,
+}
+
+impl OpenAIOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_previous_response_id(mut self, id: String) -> Self {
+        self.previous_response_id = Some(id);
+        self
+    }
+
+    pub fn with_prediction(mut self, content: impl Into<String>) -> Self {
+        self.prediction = Some(content.into());
+        self
+    }
+}
+
+/// Mistral-only parameters.
+#[derive(Debug, Clone, Default)]
+pub struct MistralOptions {
+    /// Have Mistral prepend its safety system prompt.
+    pub safe_prompt: Option<bool>,
+}
+
+impl MistralOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_safe_prompt(mut self, safe_prompt: bool) -> Self {
+        self.safe_prompt = Some(safe_prompt);
+        self
+    }
+}
+
+impl SendOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn with_generation(mut self, generation: GenerationParams) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    // Shorthands for setting a single `generation` field.
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.generation.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.generation.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.generation.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.generation.stop_sequences = stop_sequences;
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn with_tool_choice(mut self, choice: ToolChoice) -> Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+
+    pub fn with_parallel_tool_calls(mut self, parallel: bool) -> Self {
+        self.parallel_tool_calls = Some(parallel);
+        self
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_stream_idle_timeout(mut self, idle: Duration) -> Self {
+        self.stream_idle_timeout = Some(idle);
+        self
+    }
+
+    pub fn with_anthropic(mut self, anthropic: AnthropicOptions) -> Self {
+        self.anthropic = anthropic;
+        self
+    }
+
+    pub fn with_openai(mut self, openai: OpenAIOptions) -> Self {
+        self.openai = openai;
+        self
+    }
+
+    pub fn with_mistral(mut self, mistral: MistralOptions) -> Self {
+        self.mistral = mistral;
+        self
+    }
+
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+}
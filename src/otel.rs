@@ -0,0 +1,84 @@
+//! OpenTelemetry export of request spans, behind the `otel` feature.
+//!
+//! [`init_otlp`] layers `tracing-opentelemetry` on the existing
+//! instrumentation, so each `aegis_request` span (see [`crate::logging`])
+//! reaches an OTLP collector with its `provider`, `model`, token counts,
+//! `status` and `error_kind`. Only span fields and info-level or higher events
+//! are exported; the debug-level events carrying outgoing message text never
+//! are.
+//!
+//! The exporter is configured from the standard environment variables, e.g.
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` (default `http://localhost:4318`) and
+//! `OTEL_SERVICE_NAME`. Keep the returned provider alive and call its
+//! `shutdown` before exit so buffered spans are flushed.
+
+use std::collections::HashMap;
+
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing::{Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{filter::filter_fn, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+/// Install a global subscriber that logs to stdout and exports spans over
+/// OTLP/HTTP, and register the W3C trace-context propagator.
+///
+/// Panics if a global subscriber has already been set.
+pub fn init_otlp() -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder().with_http().build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .build();
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let otel = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("aegis"))
+        .with_filter(filter_fn(|meta| {
+            meta.is_span() || *meta.level() <= Level::INFO
+        }));
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .init();
+
+    Ok(provider)
+}
+
+/// Make `span` a child of the trace described by incoming request headers
+/// (`traceparent`/`tracestate`), so the Aegis calls made inside it join the
+/// caller's distributed trace. Header names must be lowercase. Does nothing
+/// when the headers carry no trace context.
+pub fn continue_trace(span: &Span, headers: &HashMap<String, String>) {
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(headers));
+    if let Err(e) = span.set_parent(parent) {
+        tracing::debug!("Could not attach incoming trace context: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn incoming_trace_context_becomes_the_parent() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let headers = HashMap::from([(
+            "traceparent".to_string(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string(),
+        )]);
+
+        let trace_id = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("handler");
+            continue_trace(&span, &headers);
+            span.context().span().span_context().trace_id()
+        });
+
+        assert_eq!(trace_id.to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+    }
+}
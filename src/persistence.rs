@@ -0,0 +1,335 @@
+//! Saving and loading message histories as files.
+//!
+//! [`SaveFormat::JsonLines`] writes one message per line, so long-running
+//! sessions can [`append_messages`] as they go instead of rewriting the
+//! whole file. Malformed files fail with [`AegisError::IoError`] of kind
+//! `InvalidData`.
+//!
+//! Files record the [`SCHEMA_VERSION`] they were written with, and
+//! [`load_conversation`] upgrades older ones with [`migrate`], so histories
+//! saved by earlier releases keep loading.
+
+use std::{io, path::Path};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{error::AegisError, models::Message};
+
+/// Version of the file layout this release writes.
+///
+/// 1. A bare array of messages (JSON, MessagePack) or bare message lines
+///    (JSON Lines). Files without a version are read as this.
+/// 2. `{"schema_version": 2, "messages": [...]}`; in JSON Lines, a
+///    `{"schema_version": 2}` line followed by the messages.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// File format for [`save_conversation`] and [`load_conversation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// One pretty-printed JSON object.
+    Json,
+    /// A version line, then one compact JSON message per line.
+    JsonLines,
+    /// The JSON layout as MessagePack; the most compact, but not
+    /// human-readable.
+    MessagePack,
+}
+
+#[derive(Serialize)]
+struct ConversationFile<'a> {
+    schema_version: u32,
+    messages: &'a [Message],
+}
+
+#[derive(Serialize)]
+struct Header {
+    schema_version: u32,
+}
+
+/// Write `messages` to `path` in `format`, replacing the file if it exists.
+pub async fn save_conversation(
+    messages: &[Message],
+    path: impl AsRef<Path>,
+    format: SaveFormat,
+) -> Result<(), AegisError> {
+    let file = ConversationFile {
+        schema_version: SCHEMA_VERSION,
+        messages,
+    };
+    let bytes = match format {
+        SaveFormat::Json => serde_json::to_vec_pretty(&file).map_err(io::Error::from)?,
+        SaveFormat::JsonLines => {
+            let mut bytes = header()?;
+            bytes.extend(json_lines(messages)?);
+            bytes
+        }
+        SaveFormat::MessagePack => rmp_serde::to_vec_named(&file).map_err(invalid_data)?,
+    };
+    fs::write(path, bytes).await?;
+    Ok(())
+}
+
+/// Read the messages saved at `path` in `format`, migrating files written
+/// with an older [`SCHEMA_VERSION`]. Blank lines in JSON Lines files are
+/// skipped.
+pub async fn load_conversation(
+    path: impl AsRef<Path>,
+    format: SaveFormat,
+) -> Result<Vec<Message>, AegisError> {
+    let bytes = fs::read(path).await?;
+    let (version, messages) = match format {
+        SaveFormat::Json => {
+            split_version(serde_json::from_slice(&bytes).map_err(io::Error::from)?)?
+        }
+        SaveFormat::JsonLines => {
+            let mut lines = bytes
+                .split(|&b| b == b'\n')
+                .enumerate()
+                .filter(|(_, line)| !line.trim_ascii().is_empty())
+                .map(|(i, line)| {
+                    serde_json::from_slice::<Value>(line)
+                        .map_err(|e| invalid_data(format!("line {}: {}", i + 1, e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let header = lines.first().filter(|line| line.get("role").is_none());
+            match header.and_then(|line| line.get("schema_version")).cloned() {
+                Some(version) => {
+                    lines.remove(0);
+                    (parse_version(&version)?, lines)
+                }
+                None => (1, lines),
+            }
+        }
+        SaveFormat::MessagePack => {
+            split_version(rmp_serde::from_slice(&bytes).map_err(invalid_data)?)?
+        }
+    };
+    migrate(version, messages)
+}
+
+/// Upgrade messages saved under schema `version` to the current [`Message`]
+/// shape. Fails with `InvalidData` for versions newer than this release
+/// understands.
+pub fn migrate(version: u32, messages: Vec<Value>) -> Result<Vec<Message>, AegisError> {
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported schema version {} (this release reads up to {})",
+            version, SCHEMA_VERSION
+        ))
+        .into());
+    }
+    // Versions 1 and 2 differ only in the file layout, which the caller has
+    // already unwrapped; later changes to the message shape go here, one
+    // step per version.
+    messages
+        .into_iter()
+        .enumerate()
+        .map(|(i, message)| {
+            serde_json::from_value(message)
+                .map_err(|e| invalid_data(format!("message {}: {}", i + 1, e)).into())
+        })
+        .collect()
+}
+
+/// Append `messages` to the JSON Lines file at `path`, creating it if needed.
+/// An existing file keeps the schema version it was started with.
+pub async fn append_messages(
+    messages: &[Message],
+    path: impl AsRef<Path>,
+) -> Result<(), AegisError> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    if file.metadata().await?.len() == 0 {
+        file.write_all(&header()?).await?;
+    }
+    file.write_all(&json_lines(messages)?).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// The version and messages of a whole-file (JSON or MessagePack) save.
+fn split_version(file: Value) -> Result<(u32, Vec<Value>), AegisError> {
+    match file {
+        Value::Array(messages) => Ok((1, messages)),
+        Value::Object(mut file) => {
+            let version = parse_version(file.get("schema_version").unwrap_or(&Value::Null))?;
+            match file.remove("messages") {
+                Some(Value::Array(messages)) => Ok((version, messages)),
+                _ => Err(invalid_data("missing \"messages\" array").into()),
+            }
+        }
+        _ => Err(invalid_data("expected a conversation object or message array").into()),
+    }
+}
+
+fn parse_version(version: &Value) -> Result<u32, AegisError> {
+    version
+        .as_u64()
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| invalid_data(format!("invalid schema_version {}", version)).into())
+}
+
+fn header() -> Result<Vec<u8>, AegisError> {
+    let mut bytes = serde_json::to_vec(&Header {
+        schema_version: SCHEMA_VERSION,
+    })
+    .map_err(io::Error::from)?;
+    bytes.push(b'\n');
+    Ok(bytes)
+}
+
+fn json_lines(messages: &[Message]) -> Result<Vec<u8>, AegisError> {
+    let mut bytes = Vec::new();
+    for message in messages {
+        serde_json::to_writer(&mut bytes, message).map_err(io::Error::from)?;
+        bytes.push(b'\n');
+    }
+    Ok(bytes)
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ContentPart, Metadata, Role, ToolCall, Usage};
+
+    fn history() -> Vec<Message> {
+        vec![
+            Message::user("Weather in Hanoi?"),
+            Message {
+                metadata: Some(Metadata {
+                    model: Some("gpt-4o".to_string()),
+                    usage: Some(Usage {
+                        prompt_tokens: 12,
+                        completion_tokens: 8,
+                        total_tokens: 20,
+                        ..Usage::default()
+                    }),
+                    ..Metadata::default()
+                }),
+                ..Message::new(
+                    Role::Assistant,
+                    vec![ContentPart::ToolCall(ToolCall {
+                        id: "call_1".to_string(),
+                        name: "get_weather".to_string(),
+                        arguments: serde_json::json!({ "location": "Hanoi" }),
+                    })],
+                )
+            },
+            Message::new(
+                Role::Tool,
+                vec![ContentPart::ToolResult {
+                    tool_call_id: "call_1".to_string(),
+                    content: "31°C".to_string(),
+                }],
+            ),
+        ]
+    }
+
+    /// Messages serialize to the same JSON after the trip, since `Message`
+    /// has no `PartialEq`.
+    fn as_json(messages: &[Message]) -> serde_json::Value {
+        serde_json::to_value(messages).unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_format_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        for format in [
+            SaveFormat::Json,
+            SaveFormat::JsonLines,
+            SaveFormat::MessagePack,
+        ] {
+            let path = dir.path().join(format!("{:?}", format));
+
+            save_conversation(&history(), &path, format).await.unwrap();
+            let loaded = load_conversation(&path, format).await.unwrap();
+
+            assert_eq!(as_json(&loaded), as_json(&history()), "{:?}", format);
+        }
+    }
+
+    #[tokio::test]
+    async fn json_lines_appends_without_rewriting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let messages = history();
+
+        append_messages(&messages[..1], &path).await.unwrap();
+        append_messages(&messages[1..], &path).await.unwrap();
+
+        let text = fs::read_to_string(&path).await.unwrap();
+        assert_eq!(text.lines().count(), 4);
+        let loaded = load_conversation(&path, SaveFormat::JsonLines)
+            .await
+            .unwrap();
+        assert_eq!(as_json(&loaded), as_json(&messages));
+
+        fs::write(&path, "{\"role\":\"user\"}\n").await.unwrap();
+        match load_conversation(&path, SaveFormat::JsonLines).await {
+            Err(AegisError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            other => panic!("expected invalid data, got {:?}", other),
+        }
+    }
+
+    /// A history as saved before files were versioned, in the message shape
+    /// of the earliest releases.
+    const V1_HISTORY: &str = r#"[
+        {"role": "user", "content": {"parts": [{"type": "Text", "text": "Weather in Hanoi?"}]}},
+        {
+            "role": "assistant",
+            "content": {"parts": [{"type": "Text", "text": "31°C and sunny."}]},
+            "metadata": {
+                "model": "gpt-4",
+                "provider": "openai",
+                "usage": {"prompt_tokens": 12, "completion_tokens": 6, "total_tokens": 18}
+            }
+        }
+    ]"#;
+
+    #[tokio::test]
+    async fn unversioned_files_are_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("v1.json");
+        let lines = dir.path().join("v1.jsonl");
+        fs::write(&json, V1_HISTORY).await.unwrap();
+        let messages: Vec<Value> = serde_json::from_str(V1_HISTORY).unwrap();
+        let text: Vec<_> = messages.iter().map(|m| m.to_string() + "\n").collect();
+        fs::write(&lines, text.concat()).await.unwrap();
+
+        for (path, format) in [(&json, SaveFormat::Json), (&lines, SaveFormat::JsonLines)] {
+            let loaded = load_conversation(path, format).await.unwrap();
+
+            assert_eq!(loaded.len(), 2);
+            assert_eq!(loaded[1].content.to_string(), "31°C and sunny.");
+            let metadata = loaded[1].metadata.as_ref().unwrap();
+            assert_eq!(metadata.usage.as_ref().unwrap().total_tokens, 18);
+            assert_eq!(metadata.finish_reason, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn newer_schema_versions_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("future.json");
+        fs::write(&path, r#"{"schema_version": 99, "messages": []}"#)
+            .await
+            .unwrap();
+
+        match load_conversation(&path, SaveFormat::Json).await {
+            Err(AegisError::IoError(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                assert!(e.to_string().contains("99"));
+            }
+            other => panic!("expected invalid data, got {:?}", other),
+        }
+    }
+}
@@ -1,33 +1,502 @@
+//! Chat providers. Besides their own settings, each has the same builders:
+//!
+//! - `with_base_url` sends its requests to another host, e.g. a gateway or
+//!   a mock server (see `AegisConfig::with_base_url`).
+//! - `with_http_client` sends them through the given client, so that
+//!   providers can share one connection pool; `Aegis::new` does this.
+//! - `with_verbatim_roles` sends each role under its own name instead of
+//!   the provider's mapping, a debugging aid for how roles serialize.
+//!
+//! Internally, each provider's `open_stream` sends a streaming request and
+//! checks its status, leaving the body unread for `stream_message` or
+//! `stream_raw` to consume.
+
 pub mod anthropic;
+pub mod cohere;
+pub mod gemini;
+pub mod mistral;
+pub mod mock;
 pub mod openai;
+pub mod openai_compatible;
+pub mod openai_responses;
+pub mod xai;
+#[cfg(test)]
+pub(crate) mod testing;
+
+use std::time::Duration;
 
 use async_trait::async_trait;
-use futures::Stream;
-use std::pin::Pin;
+use futures::{future, stream, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, warn};
 
 use crate::{
+    batch::{BatchId, BatchRequest, BatchResult, BatchStatus},
     error::AegisError,
-    models::{Message, ProviderType},
+    models::{
+        Completions, Content, ContentPart, FinishReason, Message, Metadata, ProviderType, Role,
+        ToolCall, Usage,
+    },
+    options::SendOptions,
+    rate_limit::RateLimitStatus,
+    stream::{MessageStream, RawStream},
 };
 
 #[async_trait]
 pub trait Provider: Send + Sync {
     fn provider_type(&self) -> ProviderType;
 
-    async fn send_message(&self, messages: Vec<Message>) -> Result<Message, AegisError>;
+    /// Name reported in `Metadata::provider`. Defaults to the provider type's
+    /// name; custom providers can override it.
+    fn name(&self) -> &str {
+        self.provider_type().as_str()
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError>;
 
     async fn stream_message(
         &self,
         messages: Vec<Message>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<Message, AegisError>> + Send>>, AegisError>;
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError>;
+
+    /// `n` candidate replies to the same prompt. Without native support
+    /// this sends `n` requests at once, failing if any of them fails. See
+    /// `Aegis::send_message_n`.
+    async fn send_message_n(
+        &self,
+        messages: Vec<Message>,
+        n: u32,
+        options: &SendOptions,
+    ) -> Result<Completions, AegisError> {
+        let requests = (0..n.max(1)).map(|_| self.send_message(messages.clone(), options));
+        let choices = future::try_join_all(requests).await?;
+        let mut usage = Usage::default();
+        for each in choices.iter().filter_map(|c| c.metadata.as_ref()?.usage.as_ref()) {
+            usage += each;
+        }
+        Ok(Completions { choices, usage })
+    }
+
+    /// The undecoded event-stream bytes of a streaming request, for
+    /// debugging a provider's wire format. See `Aegis::stream_message_raw`.
+    async fn stream_raw(
+        &self,
+        _messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        Err(AegisError::Unsupported(format!(
+            "{} does not expose raw streams",
+            self.name()
+        )))
+    }
 
     fn capabilities(&self) -> ProviderCapabilities;
+
+    /// IDs of the models the provider currently serves, from its models
+    /// endpoint. See `Aegis::validate_model`.
+    async fn list_models(&self) -> Result<Vec<String>, AegisError> {
+        Err(AegisError::Unsupported(format!("{} does not list its models", self.name())))
+    }
+
+    /// Open a connection to the provider ahead of the first request. Best
+    /// effort: failures are ignored.
+    async fn warmup(&self) {}
+
+    /// Rate-limit state from the most recent response, for providers that report it.
+    fn last_rate_limit_status(&self) -> Option<RateLimitStatus> {
+        None
+    }
+
+    /// Submit `requests` as one asynchronous batch. See `Aegis::batch_submit`.
+    async fn batch_submit(&self, _requests: Vec<BatchRequest>) -> Result<BatchId, AegisError> {
+        Err(AegisError::Unsupported(format!("{} has no batch API", self.name())))
+    }
+
+    async fn batch_poll(&self, _id: &BatchId) -> Result<BatchStatus, AegisError> {
+        Err(AegisError::Unsupported(format!("{} has no batch API", self.name())))
+    }
+
+    async fn batch_results(&self, _id: &BatchId) -> Result<Vec<BatchResult>, AegisError> {
+        Err(AegisError::Unsupported(format!("{} has no batch API", self.name())))
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ProviderCapabilities {
     pub streaming: bool,
     pub max_tokens: usize,
     pub supported_content_types: Vec<String>,
     pub models: Vec<String>,
 }
+
+impl ProviderCapabilities {
+    /// What changes when moving from these capabilities to `other`.
+    pub fn diff(&self, other: &ProviderCapabilities) -> CapabilitiesDiff {
+        let only_in = |a: &[String], b: &[String]| -> Vec<String> {
+            a.iter().filter(|item| !b.contains(item)).cloned().collect()
+        };
+        CapabilitiesDiff {
+            streaming: (self.streaming != other.streaming)
+                .then_some((self.streaming, other.streaming)),
+            max_tokens: (self.max_tokens != other.max_tokens)
+                .then_some((self.max_tokens, other.max_tokens)),
+            added_content_types: only_in(
+                &other.supported_content_types,
+                &self.supported_content_types,
+            ),
+            removed_content_types: only_in(
+                &self.supported_content_types,
+                &other.supported_content_types,
+            ),
+            added_models: only_in(&other.models, &self.models),
+            removed_models: only_in(&self.models, &other.models),
+        }
+    }
+}
+
+/// Differences between two providers' capabilities, from the first to the
+/// second; see [`ProviderCapabilities::diff`]. Unchanged scalars are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CapabilitiesDiff {
+    /// `(before, after)` streaming support.
+    pub streaming: Option<(bool, bool)>,
+    /// `(before, after)` output token limit.
+    pub max_tokens: Option<(usize, usize)>,
+    pub added_content_types: Vec<String>,
+    pub removed_content_types: Vec<String>,
+    pub added_models: Vec<String>,
+    pub removed_models: Vec<String>,
+}
+
+impl CapabilitiesDiff {
+    /// Whether the two capability sets are the same.
+    pub fn is_empty(&self) -> bool {
+        *self == CapabilitiesDiff::default()
+    }
+}
+
+/// Give up on a warmup request after this long; it only primes the pool.
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Send a `HEAD` to `base_url` so `client` pools a live connection (DNS, TCP
+/// and TLS done) for the next real request. The response itself is ignored.
+pub(crate) async fn warmup(client: &reqwest::Client, base_url: &str) {
+    let request = client.head(base_url).timeout(WARMUP_TIMEOUT).send();
+    if let Err(e) = request.await {
+        debug!("Warmup request to {} failed: {}", base_url, e);
+    }
+}
+
+/// Map the status of a failed streaming request onto the same errors the
+/// non-streaming paths return.
+pub(crate) async fn stream_error(response: reqwest::Response) -> AegisError {
+    let status = response.status();
+    let retry_after = crate::retry::retry_after(response.headers());
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        reqwest::StatusCode::TOO_MANY_REQUESTS => AegisError::RateLimitExceeded { retry_after },
+        reqwest::StatusCode::UNAUTHORIZED => AegisError::InvalidAPIKey,
+        status if status.is_server_error() => AegisError::ServerError(status.as_u16(), body),
+        _ => AegisError::APIError(format!("Stream request failed: Status: {}, Body: {}", status, body)),
+    }
+}
+
+/// The body of a successful response to a non-chat request, e.g. a file
+/// download, with failures mapped as for chat requests.
+pub(crate) async fn read_text(response: reqwest::Response) -> Result<String, AegisError> {
+    let status = response.status();
+    let retry_after = crate::retry::retry_after(response.headers());
+    let body = response.text().await.map_err(AegisError::from)?;
+    match status {
+        status if status.is_success() => Ok(body),
+        reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            Err(AegisError::RateLimitExceeded { retry_after })
+        }
+        reqwest::StatusCode::UNAUTHORIZED => Err(AegisError::InvalidAPIKey),
+        status if status.is_server_error() => Err(AegisError::ServerError(status.as_u16(), body)),
+        _ => Err(AegisError::APIError(format!(
+            "Status: {}, Body: {}",
+            status, body
+        ))),
+    }
+}
+
+pub(crate) async fn read_json<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, AegisError> {
+    parse_body(&read_text(response).await?)
+}
+
+/// A `GET /v1/models` listing, as OpenAI and Anthropic both shape it.
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+/// Send a prepared models-listing request and return the model IDs.
+pub(crate) async fn list_model_ids(
+    request: reqwest::RequestBuilder,
+) -> Result<Vec<String>, AegisError> {
+    let response = request.send().await.map_err(AegisError::from)?;
+    let list: ModelList = read_json(response).await?;
+    Ok(list.data.into_iter().map(|model| model.id).collect())
+}
+
+/// Longest prefix of a response body quoted in parse errors.
+pub(crate) const BODY_SNIPPET_LEN: usize = 200;
+
+/// Deserialize a response body, reporting the JSON path and the start of the
+/// body on failure. A body that ends mid-document is reported as
+/// [`AegisError::IncompleteResponse`] so it can be retried; anything else is a
+/// schema mismatch.
+pub(crate) fn parse_body<T: DeserializeOwned>(body: &str) -> Result<T, AegisError> {
+    let deserializer = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        if e.inner().classify() == serde_json::error::Category::Eof {
+            return AegisError::IncompleteResponse(format!(
+                "{} after {} bytes",
+                e.inner(),
+                body.len()
+            ));
+        }
+        AegisError::ResponseParseError {
+            path: e.path().to_string(),
+            message: e.inner().to_string(),
+            snippet: body.chars().take(BODY_SNIPPET_LEN).collect(),
+        }
+    })
+}
+
+/// Recover at least the assistant text from a body that no longer matches our
+/// response types, so a provider-side schema tweak doesn't break callers.
+pub(crate) fn lenient_message(
+    body: &str,
+    provider: &str,
+    extract_text: impl Fn(&serde_json::Value) -> Option<String>,
+) -> Option<Message> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let text = extract_text(&value).filter(|text| !text.is_empty())?;
+    Some(Message {
+        role: Role::Assistant,
+        content: Content {
+            parts: vec![ContentPart::text(text)],
+        },
+        metadata: Some(Metadata {
+            model: value.get("model").and_then(|m| m.as_str()).map(str::to_string),
+            provider: Some(provider.to_string()),
+            usage: None,
+            response_id: None,
+            finish_reason: None,
+            stop_sequence: None,
+            system_fingerprint: None,
+            warnings: Vec::new(),
+        }),
+    })
+}
+
+/// Response headers that announce a deprecation: OpenAI's own, plus the
+/// standard `Deprecation`, `Sunset` and `Warning`.
+const WARNING_HEADERS: [&str; 4] = ["openai-deprecation", "deprecation", "sunset", "warning"];
+
+/// Deprecation notices in the headers of a response, as `name: value`. Read
+/// before the body consumes the response; see [`with_warnings`].
+pub(crate) fn header_warnings(headers: &reqwest::header::HeaderMap) -> Vec<String> {
+    WARNING_HEADERS
+        .iter()
+        .flat_map(|name| {
+            headers
+                .get_all(*name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .map(move |value| format!("{}: {}", name, value))
+        })
+        .collect()
+}
+
+/// Attach `warnings` from the headers, plus any the body carries in a
+/// top-level `warning` or `warnings` field or Cohere's `meta.warnings`, to
+/// the reply's metadata, logging each one.
+pub(crate) fn with_warnings(mut message: Message, mut warnings: Vec<String>, body: &str) -> Message {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(body) {
+        let fields = [
+            value.get("warning"),
+            value.get("warnings"),
+            value.pointer("/meta/warnings"),
+        ];
+        for field in fields.into_iter().flatten() {
+            match field {
+                serde_json::Value::String(text) => warnings.push(text.clone()),
+                serde_json::Value::Array(items) => warnings
+                    .extend(items.iter().filter_map(|i| i.as_str()).map(str::to_string)),
+                _ => {}
+            }
+        }
+    }
+    if warnings.is_empty() {
+        return message;
+    }
+    let metadata = message.metadata.get_or_insert_with(Metadata::default);
+    for warning in &warnings {
+        warn!(
+            "{} warned about {}: {}",
+            metadata.provider.as_deref().unwrap_or("provider"),
+            metadata.model.as_deref().unwrap_or("the request"),
+            warning
+        );
+    }
+    metadata.warnings.extend(warnings);
+    message
+}
+
+/// A reply is always the assistant's turn; any other role is corrected (and
+/// logged) so it can't be mistaken for history when appended.
+pub(crate) fn assistant_turn(mut message: Message) -> Message {
+    if !matches!(message.role, Role::Assistant) {
+        warn!("Reply had role {:?}; treating it as the assistant's", message.role);
+        message.role = Role::Assistant;
+    }
+    message
+}
+
+/// Turn a reply with no content into [`AegisError::EmptyResponse`] so it can
+/// be retried. Replies that are empty because they were filtered or cut off
+/// by the token limit are kept, since sending again won't change them.
+pub(crate) fn reject_empty(message: Message) -> Result<Message, AegisError> {
+    let finish_reason = message.metadata.as_ref().and_then(|m| m.finish_reason.as_ref());
+    let is_empty = message.content.parts.is_empty()
+        || message.content.parts.iter().all(|part| match part {
+            ContentPart::Text {
+                text, annotations, ..
+            } => text.is_empty() && annotations.is_empty(),
+            _ => false,
+        });
+    if is_empty && matches!(finish_reason, None | Some(FinishReason::Stop)) {
+        return Err(AegisError::EmptyResponse);
+    }
+    Ok(message)
+}
+
+/// Turn a reply the provider withheld or cut off on policy grounds into
+/// [`AegisError::ContentFiltered`], with the `categories` it reported.
+pub(crate) fn reject_filtered(
+    message: Message,
+    categories: Vec<String>,
+) -> Result<Message, AegisError> {
+    let finish_reason = message.metadata.as_ref().and_then(|m| m.finish_reason.as_ref());
+    if finish_reason != Some(&FinishReason::ContentFilter) {
+        return Ok(message);
+    }
+    Err(AegisError::ContentFiltered {
+        categories,
+        partial: message.content.to_string(),
+        metadata: message.metadata.map(Box::new),
+    })
+}
+
+/// Concatenate the `text` of every `{"type": "text"}` block in a JSON array.
+pub(crate) fn text_blocks(blocks: Option<&serde_json::Value>) -> Option<String> {
+    let text = blocks?
+        .as_array()?
+        .iter()
+        .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("text"))
+        .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+        .collect::<String>();
+    Some(text)
+}
+
+/// A streamed tool call whose arguments are still arriving in fragments.
+#[derive(Debug, Default)]
+pub(crate) struct PartialToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+impl PartialToolCall {
+    /// The assembled call; no arguments at all become `{}`. Arguments that
+    /// don't parse as JSON were cut short, so they are reported as
+    /// [`AegisError::IncompleteToolCall`] rather than dispatched.
+    pub(crate) fn finish(self) -> Result<ContentPart, AegisError> {
+        let arguments = if self.arguments.trim().is_empty() {
+            serde_json::Value::Object(Default::default())
+        } else {
+            match serde_json::from_str(&self.arguments) {
+                Ok(arguments) => arguments,
+                Err(_) => return Err(self.incomplete()),
+            }
+        };
+        Ok(ContentPart::ToolCall(ToolCall {
+            id: self.id,
+            name: self.name,
+            arguments,
+        }))
+    }
+
+    /// The error for a call the stream ended partway through.
+    pub(crate) fn incomplete(self) -> AegisError {
+        AegisError::IncompleteToolCall {
+            id: self.id,
+            name: self.name,
+            partial_arguments: self.arguments,
+        }
+    }
+}
+
+/// Wrap each item of `events` in `Some` and end with a `None`, so stream
+/// parsers can check for state left over when the body ends.
+pub(crate) fn mark_end<S: Stream>(events: S) -> impl Stream<Item = Option<S::Item>> {
+    events.map(Some).chain(stream::once(future::ready(None)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(streaming: bool, types: &[&str], models: &[&str]) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming,
+            max_tokens: 4096,
+            supported_content_types: types.iter().map(|t| t.to_string()).collect(),
+            models: models.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn diff_reports_what_changes() {
+        let anthropic = capabilities(true, &["text", "image"], &["claude-3-haiku"]);
+        let cohere = capabilities(false, &["text"], &["command-r"]);
+
+        let diff = anthropic.diff(&cohere);
+
+        assert_eq!(diff.streaming, Some((true, false)));
+        assert_eq!(diff.max_tokens, None);
+        assert!(diff.added_content_types.is_empty());
+        assert_eq!(diff.removed_content_types, ["image"]);
+        assert_eq!(diff.added_models, ["command-r"]);
+        assert_eq!(diff.removed_models, ["claude-3-haiku"]);
+        assert!(anthropic.diff(&anthropic).is_empty());
+    }
+
+    #[test]
+    fn replies_are_always_the_assistants_turn() {
+        let reply = |parts| Message {
+            role: Role::User,
+            content: Content { parts },
+            metadata: None,
+        };
+
+        let message = assistant_turn(reply(vec![ContentPart::text("Hi")]));
+        assert!(matches!(message.role, Role::Assistant));
+        assert!(matches!(reject_empty(reply(vec![])), Err(AegisError::EmptyResponse)));
+    }
+}
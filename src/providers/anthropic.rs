@@ -1,27 +1,93 @@
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{future, Stream, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::pin::Pin;
-use tracing::{debug, error};
+use std::{pin::Pin, sync::Mutex};
+use tracing::{debug, error, warn, Span};
 
 use crate::{
     error::AegisError,
-    models::{Content, ContentPart, Message, Metadata, Role, Usage},
-    providers::{Provider, ProviderCapabilities},
+    models::{
+        Citation, Content, ContentPart, FinishReason, Message, Metadata, Role, ToolCall, ToolChoice,
+        ToolDefinition, Usage,
+    },
+    images::{self, MAX_INLINE_IMAGE_BYTES},
+    options::SendOptions,
+    providers::{PartialToolCall, Provider, ProviderCapabilities},
+    rate_limit::RateLimitStatus,
+    registry::ModelRegistry,
+    sse,
+    stream::{self, RawStream},
 };
 
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const DEFAULT_MODEL: &str = "claude-3-sonnet-20240229";
+/// `max_tokens` for models missing from the registry. Every current Claude
+/// model can produce at least this many.
+const FALLBACK_MAX_TOKENS: u32 = 4096;
+/// Most `cache_control` breakpoints Anthropic accepts in one request.
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+/// The `anthropic-version` sent unless overridden with `with_api_version`.
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
 pub struct AnthropicProvider {
     client: Client,
     api_key: String,
+    base_url: String,
+    rate_limit: Mutex<Option<RateLimitStatus>>,
+    verbatim_roles: bool,
+    inline_images: bool,
+    api_version: String,
+    models: ModelRegistry,
 }
 
 #[derive(Serialize, Debug)]
 struct AnthropicRequest {
     model: String,
+    /// Anthropic takes the system prompt here, never as a `system` turn.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<AnthropicSystem>,
     messages: Vec<AnthropicMessage>,
     max_tokens: u32,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<AnthropicTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+}
+
+/// A plain prompt, or text blocks when some of them are cache breakpoints.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum AnthropicSystem {
+    Text(String),
+    Blocks(Vec<AnthropicContent>),
+}
+
+#[derive(Serialize, Debug)]
+struct AnthropicTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicToolChoice {
+    Auto,
+    None,
+    /// Anthropic's name for "must call some tool".
+    Any,
+    Tool { name: String },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -31,16 +97,71 @@ struct AnthropicMessage {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct AnthropicContent {
-    #[serde(rename = "type")]
-    content_type: String,
-    text: Option<String>,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContent {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        citations: Vec<AnthropicCitation>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<AnthropicCacheControl>,
+    },
+    Image {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<AnthropicImageSource>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicCacheControl {
+    Ephemeral,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+/// Any of the citation location types; only the fields we surface are kept.
+#[derive(Serialize, Deserialize, Debug)]
+struct AnthropicCitation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(default, alias = "document_title", skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cited_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_char_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end_char_index: Option<usize>,
 }
 
 #[derive(Deserialize, Debug)]
 struct AnthropicResponse {
     content: Vec<AnthropicContent>,
     id: String,
+    /// The model that answered; an alias resolves to a dated snapshot here.
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    stop_sequence: Option<String>,
     usage: Option<AnthropicUsage>,
 }
 
@@ -50,6 +171,69 @@ struct AnthropicUsage {
     output_tokens: u32,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStreamEvent {
+    MessageStart { message: AnthropicStreamMessage },
+    ContentBlockStart { content_block: AnthropicStreamBlock },
+    ContentBlockDelta { delta: AnthropicDelta },
+    ContentBlockStop,
+    MessageDelta {
+        delta: AnthropicMessageDelta,
+        usage: Option<AnthropicDeltaUsage>,
+    },
+    Error { error: AnthropicError },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicStreamMessage {
+    model: Option<String>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStreamBlock {
+    ToolUse { id: String, name: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicDelta {
+    TextDelta { text: String },
+    ThinkingDelta { thinking: String },
+    CitationsDelta { citation: AnthropicCitation },
+    InputJsonDelta { partial_json: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicMessageDelta {
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    stop_sequence: Option<String>,
+}
+
+/// What a stream has to remember between events.
+#[derive(Debug, Default)]
+struct AnthropicStreamState {
+    /// Input tokens from `message_start`, reported again with the final usage.
+    prompt_tokens: u32,
+    /// The `tool_use` block being streamed, emitted whole when it stops.
+    tool_use: Option<PartialToolCall>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicDeltaUsage {
+    output_tokens: u32,
+}
+
 #[derive(Deserialize, Debug)]
 struct AnthropicErrorResponse {
     error: AnthropicError,
@@ -68,63 +252,470 @@ impl AnthropicProvider {
         Self {
             client: Client::new(),
             api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            rate_limit: Mutex::new(None),
+            verbatim_roles: false,
+            inline_images: false,
+            api_version: DEFAULT_ANTHROPIC_VERSION.to_string(),
+            models: ModelRegistry::default(),
         }
     }
 
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Without this, tool results are sent as `user` turns.
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    /// Download `http(s)` images and send their bytes instead of the URL,
+    /// up to `MAX_INLINE_IMAGE_BYTES` each.
+    pub fn with_inline_images(mut self, enabled: bool) -> Self {
+        self.inline_images = enabled;
+        self
+    }
+
+    /// Send a different `anthropic-version` header.
+    pub fn with_api_version(mut self, version: impl Into<String>) -> Self {
+        self.api_version = version.into();
+        self
+    }
+
+    /// Look up default `max_tokens` in `models` instead of the built-in table.
+    pub fn with_model_registry(mut self, models: ModelRegistry) -> Self {
+        self.models = models;
+        self
+    }
+
+    fn record_rate_limit(&self, headers: &reqwest::header::HeaderMap) {
+        if let Some(status) = RateLimitStatus::from_anthropic_headers(headers) {
+            *self.rate_limit.lock().unwrap() = Some(status);
+        }
+    }
+
+    fn messages_url(&self) -> String {
+        format!("{}/v1/messages", self.base_url)
+    }
+
+    /// Inline remote images if enabled; see `with_inline_images`.
+    async fn inline_images(&self, mut messages: Vec<Message>) -> Result<Vec<Message>, AegisError> {
+        if self.inline_images {
+            images::inline_remote_images(&self.client, &mut messages, MAX_INLINE_IMAGE_BYTES).await?;
+        }
+        Ok(messages)
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+        stream: bool,
+    ) -> AnthropicRequest {
+        let tools = self.convert_to_anthropic_tools(&options.tools);
+        let model = options
+            .model
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+        // Anthropic rejects requests without max_tokens
+        let max_tokens = options.generation.max_tokens.unwrap_or_else(|| {
+            self.models
+                .get(&model)
+                .map_or(FALLBACK_MAX_TOKENS, |spec| spec.max_output)
+        });
+        let (system, messages) = self.split_system(messages);
+        AnthropicRequest {
+            model,
+            system,
+            messages: self.convert_to_anthropic_messages(messages),
+            max_tokens,
+            stream,
+            temperature: options.generation.temperature,
+            tool_choice: tools.as_ref().and(options.tool_choice.as_ref()).map(|choice| {
+                match choice {
+                    ToolChoice::Auto => AnthropicToolChoice::Auto,
+                    ToolChoice::None => AnthropicToolChoice::None,
+                    ToolChoice::Required => AnthropicToolChoice::Any,
+                    ToolChoice::Specific(name) => AnthropicToolChoice::Tool { name: name.clone() },
+                }
+            }),
+            tools,
+            top_p: options.generation.top_p,
+            stop_sequences: options.generation.stop_sequences.clone(),
+            top_k: options.anthropic.top_k,
+        }
+    }
+
+    /// Take the system messages out of `messages`, joining their text into
+    /// the top-level `system` prompt. Kept as turns with verbatim roles.
+    /// If any of their text is marked for caching, the prompt is sent as one
+    /// block per text part instead, so the breakpoint stays where it was put.
+    fn split_system(&self, messages: Vec<Message>) -> (Option<AnthropicSystem>, Vec<Message>) {
+        if self.verbatim_roles {
+            return (None, messages);
+        }
+        let (system, turns): (Vec<_>, Vec<_>) = messages
+            .into_iter()
+            .partition(|msg| matches!(msg.role, Role::System));
+        let cached = system
+            .iter()
+            .flat_map(|msg| &msg.content.parts)
+            .any(|part| matches!(part, ContentPart::Text { cache: true, .. }));
+        let system = if cached {
+            let blocks = system
+                .into_iter()
+                .flat_map(|msg| msg.content.parts)
+                .filter_map(|part| match part {
+                    ContentPart::Text { text, cache, .. } if !text.is_empty() => {
+                        Some(AnthropicContent::Text {
+                            text,
+                            citations: Vec::new(),
+                            cache_control: cache.then_some(AnthropicCacheControl::Ephemeral),
+                        })
+                    }
+                    _ => None,
+                })
+                .collect();
+            Some(AnthropicSystem::Blocks(blocks))
+        } else {
+            let system = system
+                .iter()
+                .map(|msg| msg.content.to_string())
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            (!system.is_empty()).then_some(AnthropicSystem::Text(system))
+        };
+        (system, turns)
+    }
+
+    /// Anthropic rejects consecutive turns with the same role, so these are
+    /// merged after role mapping, e.g. tool results followed by user text.
     fn convert_to_anthropic_messages(&self, messages: Vec<Message>) -> Vec<AnthropicMessage> {
-        messages.into_iter()
+        let converted = messages.into_iter()
             .map(|msg| AnthropicMessage {
                 role: match msg.role {
+                    _ if self.verbatim_roles => msg.role.as_str(),
                     Role::User => "user",
                     Role::Assistant => "assistant",
+                    // Only reached with verbatim roles; see `split_system`
                     Role::System => "system",
+                    // Tool results are sent back as user turns carrying tool_result blocks
+                    Role::Tool => "user",
                 }.to_string(),
                 content: msg.content.parts.into_iter()
-                    .map(|part| match part {
-                        ContentPart::Text { text } => AnthropicContent {
-                            content_type: "text".to_string(),
-                            text: Some(text),
+                    .filter_map(|part| Some(match part {
+                        ContentPart::Text { text, cache, .. } => AnthropicContent::Text {
+                            text,
+                            citations: Vec::new(),
+                            cache_control: cache.then_some(AnthropicCacheControl::Ephemeral),
                         },
-                        ContentPart::Image { image_url: _ } => AnthropicContent {
-                            content_type: "image".to_string(),
-                            text: None,
+                        ContentPart::Image { image_url, .. } => AnthropicContent::Image {
+                            source: Some(match images::parse_data_url(&image_url) {
+                                Some((media_type, data)) => AnthropicImageSource::Base64 {
+                                    media_type: media_type.to_string(),
+                                    data: data.to_string(),
+                                },
+                                None => AnthropicImageSource::Url { url: image_url },
+                            }),
                         },
-                    })
+                        ContentPart::ToolCall(call) => AnthropicContent::ToolUse {
+                            id: call.id,
+                            name: call.name,
+                            input: call.arguments,
+                        },
+                        ContentPart::ToolResult { tool_call_id, content } => {
+                            AnthropicContent::ToolResult {
+                                tool_use_id: tool_call_id,
+                                content,
+                            }
+                        }
+                        // Thinking can only be sent back with its signature, which isn't kept
+                        ContentPart::Reasoning { .. } => return None,
+                    }))
                     .collect(),
-            })
-            .collect()
+            });
+
+        let mut merged: Vec<AnthropicMessage> = Vec::new();
+        for message in converted {
+            match merged.last_mut() {
+                Some(previous) if previous.role == message.role => {
+                    previous.content.extend(message.content)
+                }
+                _ => merged.push(message),
+            }
+        }
+        merged
     }
 
-    fn convert_from_anthropic_response(
-        &self,
-        content: Vec<AnthropicContent>,
-        usage: Option<AnthropicUsage>,
-    ) -> Message {
+    fn convert_to_anthropic_tools(&self, tools: &[ToolDefinition]) -> Option<Vec<AnthropicTool>> {
+        if tools.is_empty() {
+            return None;
+        }
+        Some(
+            tools
+                .iter()
+                .map(|tool| AnthropicTool {
+                    name: tool.name.clone(),
+                    description: tool.description.clone(),
+                    input_schema: tool.parameters.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    fn convert_citation(citation: AnthropicCitation) -> Citation {
+        Citation {
+            url: citation.url,
+            title: citation.title,
+            cited_text: citation.cited_text,
+            start_index: citation.start_char_index,
+            end_index: citation.end_char_index,
+        }
+    }
+
+    /// Map one streamed event to a message delta. Tool calls are emitted once
+    /// their block stops, with the arguments assembled from the JSON fragments.
+    fn convert_stream_event(
+        data: &str,
+        state: &mut AnthropicStreamState,
+        provider: &str,
+    ) -> Option<Result<Message, AegisError>> {
+        let event = match serde_json::from_str::<AnthropicStreamEvent>(data) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Skipping unparseable Anthropic stream event: {}", e);
+                return None;
+            }
+        };
+        let (parts, metadata) = match event {
+            AnthropicStreamEvent::MessageStart { message } => {
+                state.prompt_tokens = message.usage.map_or(0, |u| u.input_tokens);
+                (
+                    Vec::new(),
+                    Some(Metadata {
+                        model: message.model,
+                        provider: Some(provider.to_string()),
+                        usage: None,
+                        response_id: None,
+                        finish_reason: None,
+                        stop_sequence: None,
+                        system_fingerprint: None,
+                        warnings: Vec::new(),
+                    }),
+                )
+            }
+            AnthropicStreamEvent::ContentBlockStart {
+                content_block: AnthropicStreamBlock::ToolUse { id, name },
+            } => {
+                state.tool_use = Some(PartialToolCall {
+                    id,
+                    name,
+                    arguments: String::new(),
+                });
+                return None;
+            }
+            AnthropicStreamEvent::ContentBlockDelta {
+                delta: AnthropicDelta::InputJsonDelta { partial_json },
+            } => {
+                if let Some(tool_use) = &mut state.tool_use {
+                    tool_use.arguments.push_str(&partial_json);
+                }
+                return None;
+            }
+            AnthropicStreamEvent::ContentBlockStop => match state.tool_use.take()?.finish() {
+                Ok(call) => (vec![call], None),
+                Err(e) => return Some(Err(e)),
+            },
+            AnthropicStreamEvent::ContentBlockDelta {
+                delta: AnthropicDelta::TextDelta { text },
+            } if !text.is_empty() => (vec![ContentPart::text(text)], None),
+            AnthropicStreamEvent::ContentBlockDelta {
+                delta: AnthropicDelta::ThinkingDelta { thinking },
+            } if !thinking.is_empty() => (vec![ContentPart::Reasoning { text: thinking }], None),
+            AnthropicStreamEvent::ContentBlockDelta {
+                delta: AnthropicDelta::CitationsDelta { citation },
+            } => (
+                vec![ContentPart::Text {
+                    text: String::new(),
+                    annotations: vec![Self::convert_citation(citation)],
+                    cache: false,
+                }],
+                None,
+            ),
+            AnthropicStreamEvent::MessageDelta { delta, usage } => (
+                Vec::new(),
+                Some(Metadata {
+                    model: None,
+                    provider: Some(provider.to_string()),
+                    usage: usage.map(|usage| Usage {
+                        prompt_tokens: state.prompt_tokens,
+                        completion_tokens: usage.output_tokens,
+                        total_tokens: state.prompt_tokens + usage.output_tokens,
+                        ..Usage::default()
+                    }),
+                    response_id: None,
+                    finish_reason: delta.stop_reason.as_deref().map(FinishReason::from_provider),
+                    stop_sequence: delta.stop_sequence,
+                    system_fingerprint: None,
+                    warnings: Vec::new(),
+                }),
+            ),
+            AnthropicStreamEvent::Error { error } => {
+                return Some(Err(AegisError::APIError(format!(
+                    "Type: {}, Message: {}",
+                    error.r#type, error.message
+                ))))
+            }
+            _ => return None,
+        };
+        Some(Ok(Message {
+            role: Role::Assistant,
+            content: Content { parts },
+            metadata,
+        }))
+    }
+
+    fn convert_from_anthropic_response(&self, response: AnthropicResponse, model: &str) -> Message {
         Message {
             role: Role::Assistant,
             content: Content {
-                parts: content
+                parts: response
+                    .content
                     .into_iter()
-                    .filter_map(|c| {
-                        if c.content_type == "text" {
-                            c.text.map(|text| ContentPart::Text { text })
-                        } else {
-                            None
+                    .filter_map(|c| match c {
+                        AnthropicContent::Text { text, citations, .. } => Some(ContentPart::Text {
+                            text,
+                            annotations: citations.into_iter().map(Self::convert_citation).collect(),
+                            cache: false,
+                        }),
+                        AnthropicContent::Image { source: Some(source) } => {
+                            Some(ContentPart::image(match source {
+                                AnthropicImageSource::Base64 { media_type, data } => {
+                                    format!("data:{};base64,{}", media_type, data)
+                                }
+                                AnthropicImageSource::Url { url } => url,
+                            }))
+                        }
+                        AnthropicContent::ToolUse { id, name, input } => {
+                            Some(ContentPart::ToolCall(ToolCall {
+                                id,
+                                name,
+                                arguments: input,
+                            }))
                         }
+                        _ => None,
                     })
                     .collect(),
             },
             metadata: Some(Metadata {
-                model: Some("claude-3-sonnet-20240229".to_string()),
-                provider: Some("anthropic".to_string()),
-                usage: usage.map(|u| Usage {
+                model: Some(response.model.unwrap_or_else(|| model.to_string())),
+                provider: Some(self.name().to_string()),
+                usage: response.usage.map(|u| Usage {
                     prompt_tokens: u.input_tokens,
                     completion_tokens: u.output_tokens,
                     total_tokens: u.input_tokens + u.output_tokens,
+                    ..Usage::default()
                 }),
+                response_id: None,
+                finish_reason: response.stop_reason.as_deref().map(FinishReason::from_provider),
+                stop_sequence: response.stop_sequence,
+                system_fingerprint: None,
+                warnings: Vec::new(),
             }),
         }
     }
+
+    async fn open_stream(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<reqwest::Response, AegisError> {
+        check_cache_breakpoints(&messages)?;
+        check_data_urls(&messages)?;
+        let messages = self.inline_images(messages).await?;
+        let request = self.build_request(messages, options, true);
+        Span::current().record("model", request.model.as_str());
+
+        let response = self.client
+            .post(self.messages_url())
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", &self.api_version)
+            .header("Accept", "text/event-stream")
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        Span::current().record("status", response.status().as_u16());
+        self.record_rate_limit(response.headers());
+        if !response.status().is_success() {
+            return Err(super::stream_error(response).await);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Fail before sending if `messages` mark more cache breakpoints than
+/// Anthropic allows, rather than let the API reject the request.
+fn check_cache_breakpoints(messages: &[Message]) -> Result<(), AegisError> {
+    let breakpoints = messages
+        .iter()
+        .flat_map(|m| &m.content.parts)
+        .filter(|part| matches!(part, ContentPart::Text { cache: true, .. }))
+        .count();
+    if breakpoints > MAX_CACHE_BREAKPOINTS {
+        return Err(AegisError::Unsupported(format!(
+            "{} cache breakpoints requested; Anthropic allows at most {}",
+            breakpoints, MAX_CACHE_BREAKPOINTS
+        )));
+    }
+    Ok(())
+}
+
+/// Fail before sending if an image is a `data:` URL that isn't base64 image
+/// data, which Anthropic would reject with a less specific error.
+fn check_data_urls(messages: &[Message]) -> Result<(), AegisError> {
+    let data_urls = messages
+        .iter()
+        .flat_map(|m| &m.content.parts)
+        .filter_map(|part| match part {
+            ContentPart::Image { image_url, .. } if image_url.starts_with("data:") => {
+                Some(image_url)
+            }
+            _ => None,
+        });
+    for url in data_urls {
+        let prefix: String = url.chars().take(40).collect();
+        let Some((media_type, data)) = images::parse_data_url(url) else {
+            return Err(AegisError::APIError(format!(
+                "Image data URL is not base64-encoded: {}",
+                prefix
+            )));
+        };
+        if !media_type.starts_with("image/") {
+            return Err(AegisError::APIError(format!(
+                "Image data URL has media type {}, expected image/*",
+                media_type
+            )));
+        }
+        if let Err(e) = STANDARD.decode(data) {
+            return Err(AegisError::APIError(format!(
+                "Image data URL has invalid base64 data ({}): {}",
+                e, prefix
+            )));
+        }
+    }
+    Ok(())
 }
 
 #[async_trait]
@@ -133,62 +724,79 @@ impl Provider for AnthropicProvider {
         crate::models::ProviderType::Anthropic
     }
 
-    async fn send_message(&self, messages: Vec<Message>) -> Result<Message, AegisError> {
-        let anthropic_messages = self.convert_to_anthropic_messages(messages);
-        
-        let request = AnthropicRequest {
-            model: "claude-3-sonnet-20240229".to_string(),
-            messages: anthropic_messages,
-            max_tokens: 4096,
-            stream: false,
-        };
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        check_cache_breakpoints(&messages)?;
+        check_data_urls(&messages)?;
+        let messages = self.inline_images(messages).await?;
+        let request = self.build_request(messages, options, false);
+        Span::current().record("model", request.model.as_str());
 
-        debug!("Sending request to Anthropic: {:?}", request);
+        // Message content is logged (and redacted) by Aegis, not here
+        debug!(
+            "Sending request to Anthropic: model={}, messages={}",
+            request.model,
+            request.messages.len()
+        );
 
         let response = self.client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(self.messages_url())
             .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
+            .header("anthropic-version", &self.api_version)
             .json(&request)
             .send()
             .await
             .map_err(|e| {
                 error!("Network error: {:?}", e);
-                AegisError::NetworkError(e)
+                AegisError::from(e)
             })?;
 
         let status = response.status();
+        let retry_after = crate::retry::retry_after(response.headers());
+        Span::current().record("status", status.as_u16());
+        self.record_rate_limit(response.headers());
+        let warnings = super::header_warnings(response.headers());
         let body = response.text().await.map_err(|e| {
             error!("Failed to get response body: {:?}", e);
-            AegisError::NetworkError(e)
+            AegisError::from(e)
         })?;
 
         debug!("Response status: {}", status);
 
         match status {
             reqwest::StatusCode::OK => {
-                match serde_json::from_str::<AnthropicResponse>(&body) {
+                match super::parse_body::<AnthropicResponse>(&body) {
                     Ok(response) => {
                         debug!("Successfully parsed response with ID: {}", response.id);
-                        Ok(self.convert_from_anthropic_response(
-                            response.content,
-                            response.usage,
-                        ))
+                        Ok(self.convert_from_anthropic_response(response, &request.model))
                     }
                     Err(e) => {
-                        error!("Failed to parse successful response: {:?}", e);
-                        Err(AegisError::APIError(e.to_string()))
+                        error!("Failed to parse successful response: {}", e);
+                        super::lenient_message(&body, self.name(), |value| {
+                            super::text_blocks(value.get("content"))
+                        })
+                        .inspect(|_| warn!("Recovered text from unrecognised Anthropic response"))
+                        .ok_or(e)
                     }
                 }
+                .map(|message| super::with_warnings(message, warnings, &body))
+                .and_then(|message| super::reject_filtered(message, Vec::new()))
             }
             reqwest::StatusCode::TOO_MANY_REQUESTS => {
                 error!("Rate limit exceeded");
-                Err(AegisError::RateLimitExceeded)
+                Err(AegisError::RateLimitExceeded { retry_after })
             }
             reqwest::StatusCode::UNAUTHORIZED => {
                 error!("Invalid API key");
                 Err(AegisError::InvalidAPIKey)
             }
+            status if status.is_server_error() => {
+                error!("Server error: Status {}, Body: {}", status, body);
+                Err(AegisError::ServerError(status.as_u16(), body))
+            }
             _ => {
                 match serde_json::from_str::<AnthropicErrorResponse>(&body) {
                     Ok(error_response) => {
@@ -216,49 +824,45 @@ impl Provider for AnthropicProvider {
     async fn stream_message(
         &self,
         messages: Vec<Message>,
+        options: &SendOptions,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<Message, AegisError>> + Send>>, AegisError> {
-        let anthropic_messages = self.convert_to_anthropic_messages(messages);
-        
-        let request = AnthropicRequest {
-            model: "claude-3-sonnet-20240229".to_string(),
-            messages: anthropic_messages,
-            max_tokens: 4096,
-            stream: true,
-        };
+        let response = self.open_stream(messages, options).await?;
 
-        let response = self.client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Accept", "text/event-stream")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| AegisError::NetworkError(e))?;
+        let provider = self.name().to_string();
+        let stream = super::mark_end(sse::decode(response.bytes_stream()))
+            .scan(AnthropicStreamState::default(), move |state, event| {
+                future::ready(Some(match event {
+                    Some(Ok(event)) => Self::convert_stream_event(&event.data, state, &provider),
+                    Some(Err(e)) => Some(Err(e)),
+                    // A tool_use block still open at the end never got its content_block_stop
+                    None => state.tool_use.take().map(|call| Err(call.incomplete())),
+                }))
+            })
+            .filter_map(future::ready);
 
-        if !response.status().is_success() {
-            return Err(AegisError::APIError("Stream request failed".to_string()));
-        }
+        Ok(Box::pin(stream))
+    }
 
-        let stream = response
-            .bytes_stream()
-            .map(move |chunk| {
-                chunk
-                    .map_err(|e| AegisError::NetworkError(e))
-                    .and_then(|bytes| {
-                        // Parse SSE chunk and convert to Message
-                        let text = String::from_utf8_lossy(&bytes);
-                        Ok(Message {
-                            role: Role::Assistant,
-                            content: Content {
-                                parts: vec![ContentPart::Text { text: text.to_string() }],
-                            },
-                            metadata: None,
-                        })
-                    })
-            });
+    async fn stream_raw(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        let response = self.open_stream(messages, options).await?;
+        Ok(stream::raw(response))
+    }
 
-        Ok(Box::pin(stream))
+    async fn list_models(&self) -> Result<Vec<String>, AegisError> {
+        let request = self
+            .client
+            .get(format!("{}/v1/models?limit=1000", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", &self.api_version);
+        super::list_model_ids(request).await
+    }
+
+    async fn warmup(&self) {
+        super::warmup(&self.client, &self.base_url).await
     }
 
     fn capabilities(&self) -> ProviderCapabilities {
@@ -266,7 +870,233 @@ impl Provider for AnthropicProvider {
             streaming: true,
             max_tokens: 4096,
             supported_content_types: vec!["text".to_string(), "image".to_string()],
-            models: vec!["claude-3-sonnet-20240229".to_string()],
+            models: vec![DEFAULT_MODEL.to_string()],
         }
     }
+
+    fn last_rate_limit_status(&self) -> Option<RateLimitStatus> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::AnthropicOptions, providers::testing};
+
+    #[test]
+    fn top_k_is_sent_only_when_set() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+
+        let request = provider.build_request(Vec::new(), &SendOptions::default(), false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body.get("top_k").is_none());
+
+        let options = SendOptions::new().with_anthropic(AnthropicOptions::new().with_top_k(40));
+        let request = provider.build_request(Vec::new(), &options, false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["top_k"], 40);
+    }
+
+    #[test]
+    fn system_messages_become_the_top_level_system_prompt() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let history = vec![
+            Message::text(Role::System, "You are a geography tutor."),
+            Message::user("Capital of Vietnam?"),
+            Message::text(Role::System, "Answer briefly."),
+        ];
+
+        let request = provider.build_request(history, &SendOptions::default(), false);
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["system"], "You are a geography tutor.\n\nAnswer briefly.");
+        assert_eq!(
+            body["messages"],
+            serde_json::json!([
+                { "role": "user", "content": [{ "type": "text", "text": "Capital of Vietnam?" }] },
+            ])
+        );
+
+        let request = provider.build_request(Vec::new(), &SendOptions::default(), false);
+        assert!(serde_json::to_value(&request).unwrap().get("system").is_none());
+    }
+
+    #[test]
+    fn cached_system_text_is_sent_as_blocks() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let system = Message::new(
+            Role::System,
+            vec![
+                ContentPart::cached_text("<handbook>...</handbook>"),
+                ContentPart::text("Answer briefly."),
+            ],
+        );
+
+        let request = provider.build_request(vec![system], &SendOptions::default(), false);
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            body["system"],
+            serde_json::json!([
+                {
+                    "type": "text",
+                    "text": "<handbook>...</handbook>",
+                    "cache_control": { "type": "ephemeral" },
+                },
+                { "type": "text", "text": "Answer briefly." },
+            ])
+        );
+    }
+
+    #[test]
+    fn png_data_urls_become_base64_image_blocks() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let image = Message::new(
+            Role::User,
+            vec![ContentPart::image("data:image/png;base64,iVBORw0KGgo=")],
+        );
+        assert!(check_data_urls(std::slice::from_ref(&image)).is_ok());
+
+        let request = provider.build_request(vec![image], &SendOptions::default(), false);
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"][0],
+            serde_json::json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo=" },
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_data_urls_are_rejected() {
+        let image = |url: &str| Message::new(Role::User, vec![ContentPart::image(url)]);
+
+        for url in [
+            "data:image/png,iVBORw0KGgo=",
+            "data:text/plain;base64,SGVsbG8=",
+            "data:image/png;base64,not base64!",
+        ] {
+            let result = check_data_urls(&[image(url)]);
+            assert!(matches!(result, Err(AegisError::APIError(_))), "{} was accepted", url);
+        }
+        assert!(check_data_urls(&[image("https://example.com/lantern.png")]).is_ok());
+    }
+
+    #[test]
+    fn max_tokens_defaults_to_the_model_max_output() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let max_tokens = |options: &SendOptions| {
+            let request = provider.build_request(Vec::new(), options, false);
+            serde_json::to_value(&request).unwrap()["max_tokens"].clone()
+        };
+
+        assert_eq!(max_tokens(&SendOptions::new().with_model("claude-3-5-sonnet-latest")), 8192);
+        assert_eq!(max_tokens(&SendOptions::new().with_model("claude-next")), 4096);
+        assert_eq!(max_tokens(&SendOptions::new().with_max_tokens(256)), 256);
+    }
+
+    #[tokio::test]
+    async fn cache_breakpoints_land_on_the_marked_block() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let document = Message::new(Role::User, vec![
+            ContentPart::text("Answer from this report only."),
+            ContentPart::cached_text("<report>...</report>"),
+            ContentPart::text("Who wrote it?"),
+        ]);
+
+        let request = provider.build_request(vec![document], &SendOptions::default(), false);
+        let body = serde_json::to_value(&request).unwrap();
+        let blocks = body["messages"][0]["content"].as_array().unwrap();
+        let cached: Vec<bool> = blocks.iter().map(|b| b.get("cache_control").is_some()).collect();
+        assert_eq!(cached, [false, true, false]);
+        assert_eq!(blocks[1]["cache_control"], serde_json::json!({ "type": "ephemeral" }));
+
+        let parts = (0..5).map(|i| ContentPart::cached_text(i.to_string())).collect();
+        let too_many = Message::new(Role::User, parts);
+        let result = provider.send_message(vec![too_many], &SendOptions::default()).await;
+        assert!(matches!(result, Err(AegisError::Unsupported(_))));
+    }
+
+    #[test]
+    fn verbatim_roles_skip_the_tool_remap() {
+        let tool_turn = || {
+            Message::new(
+                Role::Tool,
+                vec![ContentPart::ToolResult {
+                    tool_call_id: "toolu_01".to_string(),
+                    content: "22°C".to_string(),
+                }],
+            )
+        };
+        let provider = AnthropicProvider::new("test-key".to_string());
+
+        let request = provider.build_request(vec![tool_turn()], &SendOptions::default(), false);
+        assert_eq!(request.messages[0].role, "user");
+
+        let provider = provider.with_verbatim_roles(true);
+        let request = provider.build_request(vec![tool_turn()], &SendOptions::default(), false);
+        assert_eq!(request.messages[0].role, "tool");
+    }
+
+    #[test]
+    fn consecutive_same_role_turns_are_merged() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let history = vec![
+            Message::user("What is the capital of Vietnam?"),
+            Message::user("Answer in one word."),
+            Message::text(Role::Assistant, "Hanoi."),
+        ];
+
+        let request = provider.build_request(history, &SendOptions::default(), false);
+
+        let roles: Vec<_> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant"]);
+        assert_eq!(request.messages[0].content.len(), 2);
+    }
+
+    #[test]
+    fn tool_results_in_one_turn_share_a_user_message() {
+        let result = |id: &str, content: &str| {
+            Message::new(
+                Role::Tool,
+                vec![ContentPart::ToolResult {
+                    tool_call_id: id.to_string(),
+                    content: content.to_string(),
+                }],
+            )
+        };
+        let provider = AnthropicProvider::new("test-key".to_string());
+        let history = vec![result("toolu_hanoi", "31°C"), result("toolu_hue", "27°C")];
+
+        let request = provider.build_request(history, &SendOptions::default(), false);
+
+        let body = serde_json::to_value(&request.messages).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{
+                "role": "user",
+                "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_hanoi", "content": "31°C" },
+                    { "type": "tool_result", "tool_use_id": "toolu_hue", "content": "27°C" },
+                ]
+            }])
+        );
+    }
+
+    #[test]
+    fn tool_choice_maps_to_anthropic_objects_only_with_tools() {
+        let provider = AnthropicProvider::new("test-key".to_string());
+        testing::assert_tool_choice_shapes(
+            |options| provider.build_request(Vec::new(), options, false),
+            [
+                serde_json::json!({ "type": "auto" }),
+                serde_json::json!({ "type": "none" }),
+                serde_json::json!({ "type": "any" }),
+                serde_json::json!({ "type": "tool", "name": "get_weather" }),
+            ],
+        );
+    }
 }
@@ -0,0 +1,417 @@
+use async_trait::async_trait;
+use futures::{future, Stream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use tracing::{warn, Span};
+
+use crate::{
+    error::AegisError,
+    models::{Content, ContentPart, FinishReason, Message, Metadata, Role, Usage},
+    options::SendOptions,
+    providers::{Provider, ProviderCapabilities},
+    sse,
+    stream::{self, RawStream},
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.cohere.com";
+const DEFAULT_MODEL: &str = "command-r-plus";
+
+pub struct CohereProvider {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    verbatim_roles: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CohereRequest {
+    model: String,
+    messages: Vec<CohereMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    /// Cohere's name for `top_p`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CohereMessage {
+    role: String,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereResponse {
+    message: CohereResponseMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+    /// v2 reports token counts under `usage`, v1 under `meta`.
+    #[serde(alias = "meta")]
+    usage: Option<CohereUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereResponseMessage {
+    #[serde(default)]
+    content: Vec<CohereContent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereContent {
+    #[serde(rename = "type")]
+    content_type: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereUsage {
+    tokens: Option<CohereTokens>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereTokens {
+    input_tokens: f64,
+    output_tokens: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum CohereStreamEvent {
+    #[serde(rename = "content-delta")]
+    ContentDelta { delta: CohereContentDelta },
+    #[serde(rename = "message-end")]
+    MessageEnd { delta: CohereMessageEnd },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereContentDelta {
+    message: CohereDeltaMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereDeltaMessage {
+    content: CohereDeltaContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereDeltaContent {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereMessageEnd {
+    #[serde(default)]
+    finish_reason: Option<String>,
+    usage: Option<CohereUsage>,
+}
+
+impl CohereProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            verbatim_roles: false,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    fn chat_url(&self) -> String {
+        format!("{}/v2/chat", self.base_url)
+    }
+
+    /// Fails with `Unsupported` if `options.tools` is set or the history
+    /// holds tool calls, rather than send a request without them.
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+        stream: bool,
+    ) -> Result<CohereRequest, AegisError> {
+        let calls_tools = messages
+            .iter()
+            .flat_map(|m| &m.content.parts)
+            .any(|part| matches!(part, ContentPart::ToolCall(_)));
+        if !options.tools.is_empty() || calls_tools {
+            return Err(AegisError::Unsupported(format!(
+                "{} does not support tools yet",
+                self.name()
+            )));
+        }
+        Ok(CohereRequest {
+            model: options
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            messages: self.convert_to_cohere_messages(messages),
+            stream,
+            temperature: options.generation.temperature,
+            max_tokens: options.generation.max_tokens,
+            p: options.generation.top_p,
+            stop_sequences: options.generation.stop_sequences.clone(),
+        })
+    }
+
+    fn convert_to_cohere_messages(&self, messages: Vec<Message>) -> Vec<CohereMessage> {
+        messages
+            .into_iter()
+            .map(|msg| {
+                let mut tool_call_id = None;
+                let content = msg
+                    .content
+                    .parts
+                    .into_iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text, .. } => Some(text),
+                        ContentPart::ToolResult { tool_call_id: id, content } => {
+                            tool_call_id = Some(id);
+                            Some(content)
+                        }
+                        _ => None, // Skip non-text content for now
+                    })
+                    .collect::<Vec<_>>()
+                    .join("");
+                CohereMessage {
+                    role: match msg.role {
+                        _ if self.verbatim_roles => msg.role.as_str(),
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                        Role::System => "system",
+                        Role::Tool => "tool",
+                    }
+                    .to_string(),
+                    content,
+                    tool_call_id,
+                }
+            })
+            .collect()
+    }
+
+    fn convert_usage(usage: Option<CohereUsage>) -> Option<Usage> {
+        usage.and_then(|u| u.tokens).map(|t| {
+            let prompt_tokens = t.input_tokens as u32;
+            let completion_tokens = t.output_tokens as u32;
+            Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+                ..Usage::default()
+            }
+        })
+    }
+
+    fn convert_from_cohere_response(&self, response: CohereResponse, model: &str) -> Message {
+        Message {
+            role: Role::Assistant,
+            content: Content {
+                parts: response
+                    .message
+                    .content
+                    .into_iter()
+                    .filter(|c| c.content_type == "text")
+                    .map(|c| ContentPart::text(c.text))
+                    .collect(),
+            },
+            metadata: Some(Metadata {
+                model: Some(model.to_string()),
+                provider: Some(self.name().to_string()),
+                usage: Self::convert_usage(response.usage),
+                response_id: None,
+                finish_reason: response.finish_reason.as_deref().map(FinishReason::from_provider),
+                stop_sequence: None,
+                system_fingerprint: None,
+                warnings: Vec::new(),
+            }),
+        }
+    }
+
+    /// Map one streamed event to a message delta; events without text or usage yield `None`.
+    fn convert_stream_event(
+        data: &str,
+        provider: &str,
+        model: &str,
+    ) -> Option<Result<Message, AegisError>> {
+        let event = match serde_json::from_str::<CohereStreamEvent>(data) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Skipping unparseable Cohere stream event: {}", e);
+                return None;
+            }
+        };
+        match event {
+            CohereStreamEvent::ContentDelta { delta } if !delta.message.content.text.is_empty() => {
+                Some(Ok(Message {
+                    role: Role::Assistant,
+                    content: Content {
+                        parts: vec![ContentPart::text(delta.message.content.text)],
+                    },
+                    metadata: None,
+                }))
+            }
+            CohereStreamEvent::MessageEnd { delta } => Some(Ok(Message {
+                role: Role::Assistant,
+                content: Content { parts: Vec::new() },
+                metadata: Some(Metadata {
+                    model: Some(model.to_string()),
+                    provider: Some(provider.to_string()),
+                    usage: Self::convert_usage(delta.usage),
+                    response_id: None,
+                    finish_reason: delta.finish_reason.as_deref().map(FinishReason::from_provider),
+                    stop_sequence: None,
+                    system_fingerprint: None,
+                    warnings: Vec::new(),
+                }),
+            })),
+            _ => None,
+        }
+    }
+
+    async fn open_stream(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<(reqwest::Response, String), AegisError> {
+        let request = self.build_request(messages, options, true)?;
+        Span::current().record("model", request.model.as_str());
+
+        let response = self
+            .client
+            .post(self.chat_url())
+            .bearer_auth(&self.api_key)
+            .header("Accept", "text/event-stream")
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        Span::current().record("status", response.status().as_u16());
+        if !response.status().is_success() {
+            return Err(super::stream_error(response).await);
+        }
+
+        Ok((response, request.model))
+    }
+}
+
+#[async_trait]
+impl Provider for CohereProvider {
+    fn provider_type(&self) -> crate::models::ProviderType {
+        crate::models::ProviderType::Cohere
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let request = self.build_request(messages, options, false)?;
+        Span::current().record("model", request.model.as_str());
+
+        let response = self
+            .client
+            .post(self.chat_url())
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        let status = response.status();
+        let retry_after = crate::retry::retry_after(response.headers());
+        Span::current().record("status", status.as_u16());
+        let warnings = super::header_warnings(response.headers());
+        let body = response.text().await.map_err(AegisError::from)?;
+
+        match status {
+            reqwest::StatusCode::OK => {
+                match super::parse_body::<CohereResponse>(&body) {
+                    Ok(parsed) => Ok(self.convert_from_cohere_response(parsed, &request.model)),
+                    Err(e) => super::lenient_message(&body, self.name(), |value| {
+                        super::text_blocks(value.pointer("/message/content"))
+                    })
+                    .inspect(|_| warn!("Recovered text from unrecognised Cohere response: {}", e))
+                    .ok_or(e),
+                }
+                .map(|message| super::with_warnings(message, warnings, &body))
+                .and_then(|message| super::reject_filtered(message, Vec::new()))
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(AegisError::RateLimitExceeded { retry_after })
+            }
+            reqwest::StatusCode::UNAUTHORIZED => Err(AegisError::InvalidAPIKey),
+            status if status.is_server_error() => {
+                Err(AegisError::ServerError(status.as_u16(), body))
+            }
+            _ => Err(AegisError::APIError(format!(
+                "Status: {}, Body: {}",
+                status, body
+            ))),
+        }
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Message, AegisError>> + Send>>, AegisError> {
+        let (response, model) = self.open_stream(messages, options).await?;
+
+        let provider = self.name().to_string();
+        let stream = sse::decode(response.bytes_stream()).filter_map(move |event| {
+            future::ready(match event {
+                Ok(event) => Self::convert_stream_event(&event.data, &provider, &model),
+                Err(e) => Some(Err(e)),
+            })
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn stream_raw(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        let (response, _) = self.open_stream(messages, options).await?;
+        Ok(stream::raw(response))
+    }
+
+    async fn warmup(&self) {
+        super::warmup(&self.client, &self.base_url).await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming: true,
+            max_tokens: 4096,
+            supported_content_types: vec!["text".to_string()],
+            models: vec![DEFAULT_MODEL.to_string(), "command-r".to_string()],
+        }
+    }
+}
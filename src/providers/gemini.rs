@@ -0,0 +1,412 @@
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::{warn, Span};
+
+use crate::{
+    error::AegisError,
+    models::{Content, ContentPart, FinishReason, Message, Metadata, Role, Usage},
+    options::SendOptions,
+    providers::{Provider, ProviderCapabilities},
+    sse,
+    stream::{self, MessageStream, RawStream},
+};
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+const DEFAULT_MODEL: &str = "gemini-1.5-flash";
+
+pub struct GeminiProvider {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    verbatim_roles: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GeminiContent {
+    /// Absent on system instructions, and on some safety-blocked candidates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GeminiPart {
+    /// Absent on parts we don't read, such as `functionCall`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+/// Both `generateContent` and each `streamGenerateContent` event have this
+/// shape; streamed events carry a slice of the text each.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    usage_metadata: Option<GeminiUsage>,
+    model_version: Option<String>,
+    response_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    content: Option<GeminiContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+    #[serde(default)]
+    total_token_count: u32,
+}
+
+impl GeminiProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            verbatim_roles: false,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    /// The model is part of the path: `/v1beta/models/<model>:<method>`.
+    fn model_url(&self, model: &str, method: &str) -> String {
+        format!("{}/v1beta/models/{}:{}", self.base_url, model, method)
+    }
+
+    fn model(options: &SendOptions) -> String {
+        options
+            .model
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string())
+    }
+
+    /// Fails with `Unsupported` if `options.tools` is set, rather than send
+    /// a request the model would answer without them.
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<GeminiRequest, AegisError> {
+        if !options.tools.is_empty() {
+            return Err(AegisError::Unsupported(format!(
+                "{} does not support tools yet",
+                self.name()
+            )));
+        }
+        let generation = &options.generation;
+        let generation_config = (generation.temperature.is_some()
+            || generation.max_tokens.is_some()
+            || generation.top_p.is_some()
+            || !generation.stop_sequences.is_empty())
+        .then(|| GenerationConfig {
+            temperature: generation.temperature,
+            max_output_tokens: generation.max_tokens,
+            top_p: generation.top_p,
+            stop_sequences: generation.stop_sequences.clone(),
+        });
+        let (system, contents) = self.convert_to_gemini_contents(messages);
+        Ok(GeminiRequest {
+            contents,
+            system_instruction: (!system.is_empty()).then_some(GeminiContent {
+                role: None,
+                parts: system,
+            }),
+            generation_config,
+        })
+    }
+
+    /// Split `messages` into the system instruction's parts and the turns.
+    /// Gemini calls the assistant `model` and has no tool role, so tool
+    /// results go back as user turns. Only text is sent; a turn left with no
+    /// parts, such as one that only called tools, is skipped, since Gemini
+    /// rejects empty turns.
+    fn convert_to_gemini_contents(
+        &self,
+        messages: Vec<Message>,
+    ) -> (Vec<GeminiPart>, Vec<GeminiContent>) {
+        let mut system = Vec::new();
+        let mut contents = Vec::new();
+        for msg in messages {
+            let parts: Vec<GeminiPart> = msg
+                .content
+                .parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text, .. } => Some(text),
+                    ContentPart::ToolResult { content, .. } => Some(content),
+                    _ => None,
+                })
+                .map(|text| GeminiPart { text: Some(text) })
+                .collect();
+            let role = match msg.role {
+                _ if self.verbatim_roles => msg.role.as_str(),
+                Role::System => {
+                    system.extend(parts);
+                    continue;
+                }
+                Role::User | Role::Tool => "user",
+                Role::Assistant => "model",
+            };
+            if parts.is_empty() {
+                continue;
+            }
+            contents.push(GeminiContent {
+                role: Some(role.to_string()),
+                parts,
+            });
+        }
+        (system, contents)
+    }
+
+    fn convert_usage(usage: Option<GeminiUsage>) -> Option<Usage> {
+        usage.map(|u| Usage {
+            prompt_tokens: u.prompt_token_count,
+            completion_tokens: u.candidates_token_count,
+            total_tokens: u.total_token_count,
+            ..Usage::default()
+        })
+    }
+
+    /// Convert a whole response, or one streamed slice of it. Gemini
+    /// reports usage on every streamed event, so `final_only` keeps metadata
+    /// off all but the event carrying the finish reason.
+    fn convert_from_gemini_response(
+        response: GeminiResponse,
+        provider: &str,
+        model: &str,
+        final_only: bool,
+    ) -> Message {
+        let candidate = response.candidates.into_iter().next();
+        let finish_reason = candidate.as_ref().and_then(|c| c.finish_reason.clone());
+        let parts = candidate
+            .and_then(|c| c.content)
+            .map(|content| content.parts)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|part| part.text)
+            .map(ContentPart::text)
+            .collect();
+        let metadata = (!final_only || finish_reason.is_some()).then(|| Metadata {
+            model: Some(response.model_version.unwrap_or_else(|| model.to_string())),
+            provider: Some(provider.to_string()),
+            usage: Self::convert_usage(response.usage_metadata),
+            response_id: response.response_id,
+            finish_reason: finish_reason.as_deref().map(FinishReason::from_provider),
+            stop_sequence: None,
+            system_fingerprint: None,
+            warnings: Vec::new(),
+        });
+        Message {
+            role: Role::Assistant,
+            content: Content { parts },
+            metadata,
+        }
+    }
+
+    /// Map one streamed event to a message delta; events without text or a
+    /// finish reason yield `None`.
+    fn convert_stream_event(
+        data: &str,
+        provider: &str,
+        model: &str,
+    ) -> Option<Result<Message, AegisError>> {
+        let response = match serde_json::from_str::<GeminiResponse>(data) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Skipping unparseable Gemini stream event: {}", e);
+                return None;
+            }
+        };
+        let delta = Self::convert_from_gemini_response(response, provider, model, true);
+        if delta.content.parts.is_empty() && delta.metadata.is_none() {
+            return None;
+        }
+        Some(Ok(delta))
+    }
+
+    async fn open_stream(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<(reqwest::Response, String), AegisError> {
+        let model = Self::model(options);
+        let request = self.build_request(messages, options)?;
+        Span::current().record("model", model.as_str());
+
+        let response = self
+            .client
+            .post(self.model_url(&model, "streamGenerateContent"))
+            .query(&[("alt", "sse")])
+            .header("x-goog-api-key", &self.api_key)
+            .header("Accept", "text/event-stream")
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        Span::current().record("status", response.status().as_u16());
+        if !response.status().is_success() {
+            return Err(super::stream_error(response).await);
+        }
+
+        Ok((response, model))
+    }
+}
+
+#[async_trait]
+impl Provider for GeminiProvider {
+    fn provider_type(&self) -> crate::models::ProviderType {
+        crate::models::ProviderType::Gemini
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let model = Self::model(options);
+        let request = self.build_request(messages, options)?;
+        Span::current().record("model", model.as_str());
+
+        let response = self
+            .client
+            .post(self.model_url(&model, "generateContent"))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        let status = response.status();
+        let retry_after = crate::retry::retry_after(response.headers());
+        Span::current().record("status", status.as_u16());
+        let warnings = super::header_warnings(response.headers());
+        let body = response.text().await.map_err(AegisError::from)?;
+
+        match status {
+            reqwest::StatusCode::OK => {
+                let message = match super::parse_body::<GeminiResponse>(&body) {
+                    Ok(parsed) => Ok(Self::convert_from_gemini_response(
+                        parsed,
+                        self.name(),
+                        &model,
+                        false,
+                    )),
+                    Err(e) => super::lenient_message(&body, self.name(), |value| {
+                        let parts = value.pointer("/candidates/0/content/parts")?.as_array()?;
+                        Some(parts.iter().filter_map(|p| p.get("text")?.as_str()).collect())
+                    })
+                    .inspect(|_| warn!("Recovered text from unrecognised Gemini response: {}", e))
+                    .ok_or(e),
+                }?;
+                super::reject_filtered(super::with_warnings(message, warnings, &body), Vec::new())
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(AegisError::RateLimitExceeded { retry_after })
+            }
+            // Gemini answers a bad key with 400 `API_KEY_INVALID` rather than 401.
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(AegisError::InvalidAPIKey)
+            }
+            reqwest::StatusCode::BAD_REQUEST if body.contains("API_KEY_INVALID") => {
+                Err(AegisError::InvalidAPIKey)
+            }
+            status if status.is_server_error() => {
+                Err(AegisError::ServerError(status.as_u16(), body))
+            }
+            _ => Err(AegisError::APIError(format!(
+                "Status: {}, Body: {}",
+                status, body
+            ))),
+        }
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let (response, model) = self.open_stream(messages, options).await?;
+
+        let provider = self.name().to_string();
+        let stream = sse::decode(response.bytes_stream()).filter_map(move |event| {
+            future::ready(match event {
+                Ok(event) => Self::convert_stream_event(&event.data, &provider, &model),
+                Err(e) => Some(Err(e)),
+            })
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn stream_raw(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        let (response, _) = self.open_stream(messages, options).await?;
+        Ok(stream::raw(response))
+    }
+
+    async fn warmup(&self) {
+        super::warmup(&self.client, &self.base_url).await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming: true,
+            max_tokens: 8192,
+            supported_content_types: vec!["text".to_string()],
+            models: vec!["gemini-1.5-pro".to_string(), DEFAULT_MODEL.to_string()],
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{models::ProviderType, options::SendOptions};
+
+use super::openai_compatible::{CompatibleApi, OpenAICompatibleProvider};
+
+/// Mistral's La Plateforme. Mistral reports usage on the final stream chunk
+/// without being asked, and takes `MistralOptions` as extra body fields.
+pub type MistralProvider = OpenAICompatibleProvider<Mistral>;
+
+pub struct Mistral;
+
+impl CompatibleApi for Mistral {
+    const PROVIDER_TYPE: ProviderType = ProviderType::Mistral;
+    const DEFAULT_BASE_URL: &'static str = "https://api.mistral.ai";
+    const DEFAULT_MODEL: &'static str = "mistral-large-latest";
+    const MODELS: &'static [&'static str] =
+        &["mistral-large-latest", "codestral-latest", "mistral-small-latest"];
+
+    fn extra_body(options: &SendOptions) -> serde_json::Map<String, serde_json::Value> {
+        let mut extra = serde_json::Map::new();
+        if let Some(safe_prompt) = options.mistral.safe_prompt {
+            extra.insert("safe_prompt".to_string(), safe_prompt.into());
+        }
+        extra
+    }
+}
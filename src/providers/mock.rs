@@ -0,0 +1,211 @@
+//! An offline provider for testing and demoing code built on Aegis.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+
+use crate::{
+    error::AegisError,
+    models::{Content, ContentPart, FinishReason, Message, Metadata, ProviderType, Role},
+    options::SendOptions,
+    providers::{Provider, ProviderCapabilities},
+    stream::{MessageStream, StreamAccumulator, StreamRecording},
+};
+
+/// Name a `MockProvider` registers under unless given another.
+const DEFAULT_NAME: &str = "mock";
+
+/// One scripted turn of a [`MockProvider`].
+#[derive(Clone)]
+enum MockReply {
+    Text(String),
+    Error(Arc<dyn Fn() -> AegisError + Send + Sync>),
+    Recording(StreamRecording),
+}
+
+impl fmt::Debug for MockReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MockReply::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            MockReply::Error(_) => f.write_str("Error(..)"),
+            MockReply::Recording(recording) => {
+                f.debug_tuple("Recording").field(recording).finish()
+            }
+        }
+    }
+}
+
+/// A provider that never touches the network, for testing apps built on
+/// Aegis. It plays back the replies and errors it was scripted with, in
+/// order, then echoes the last user message; with no script it only echoes.
+/// Streamed replies arrive one word per delta, except recorded streams,
+/// which are replayed delta for delta.
+///
+/// Register it with `AegisConfig::with_mock` and address it as
+/// `ProviderType::Custom` with its name, `"mock"` by default:
+///
+/// ```
+/// use aegis::{config::AegisConfig, models::ProviderType, providers::mock::MockProvider, Aegis};
+///
+/// let mock = MockProvider::new().with_reply("The capital of Vietnam is Hanoi.");
+/// let aegis = Aegis::new(AegisConfig::new().with_mock(mock));
+/// assert!(aegis.capabilities(ProviderType::Custom("mock".to_string())).is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    name: String,
+    script: Vec<MockReply>,
+    /// Shared between clones, so providers rebuilt by
+    /// `Aegis::reload_providers` carry on with the script.
+    next: Arc<AtomicUsize>,
+    latency: Duration,
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self {
+            name: DEFAULT_NAME.to_string(),
+            script: Vec::new(),
+            next: Arc::default(),
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// Register as `ProviderType::Custom(name)`, e.g. to run several mocks
+    /// side by side.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Queue `text` as the next scripted reply.
+    pub fn with_reply(mut self, text: impl Into<String>) -> Self {
+        self.script.push(MockReply::Text(text.into()));
+        self
+    }
+
+    /// Queue a failure; `error` builds the error each time it is played.
+    pub fn with_error(mut self, error: impl Fn() -> AegisError + Send + Sync + 'static) -> Self {
+        self.script.push(MockReply::Error(Arc::new(error)));
+        self
+    }
+
+    /// Queue a stream captured with `stream::record_stream`. Streaming calls
+    /// replay it as recorded; `send_message` returns its deltas joined, or
+    /// its error.
+    pub fn with_recording(mut self, recording: StreamRecording) -> Self {
+        self.script.push(MockReply::Recording(recording));
+        self
+    }
+
+    /// Wait this long before every reply, or before the first delta of a
+    /// stream.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// The next scripted reply, or an echo of the last user message once
+    /// the script has run out.
+    async fn next_reply(&self, messages: &[Message]) -> MockReply {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        match self.script.get(self.next.fetch_add(1, Ordering::SeqCst)) {
+            Some(reply) => reply.clone(),
+            None => MockReply::Text(
+                messages
+                    .iter()
+                    .rev()
+                    .find(|m| matches!(m.role, Role::User))
+                    .map(|m| m.content.to_string())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn message(&self, parts: Vec<ContentPart>, finish_reason: Option<FinishReason>) -> Message {
+        Message {
+            role: Role::Assistant,
+            content: Content { parts },
+            metadata: Some(Metadata {
+                model: Some(self.name.clone()),
+                provider: Some(self.name.clone()),
+                finish_reason,
+                ..Metadata::default()
+            }),
+        }
+    }
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Custom(self.name.clone())
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let text = match self.next_reply(&messages).await {
+            MockReply::Text(text) => text,
+            MockReply::Error(error) => return Err(error()),
+            MockReply::Recording(recording) => {
+                let mut accumulator = StreamAccumulator::new();
+                let mut replay = recording.replay();
+                while let Some(delta) = replay.next().await {
+                    accumulator.push(&delta?);
+                }
+                return Ok(accumulator.into_message());
+            }
+        };
+        Ok(self.message(vec![ContentPart::text(text)], Some(FinishReason::Stop)))
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let text = match self.next_reply(&messages).await {
+            MockReply::Text(text) => text,
+            MockReply::Error(error) => return Err(error()),
+            MockReply::Recording(recording) => return Ok(recording.replay()),
+        };
+        let mut deltas: Vec<_> = text
+            .split_inclusive(' ')
+            .map(|word| Ok(self.message(vec![ContentPart::text(word)], None)))
+            .collect();
+        deltas.push(Ok(self.message(Vec::new(), Some(FinishReason::Stop))));
+        Ok(Box::pin(stream::iter(deltas)))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming: true,
+            max_tokens: 4096,
+            supported_content_types: vec!["text".to_string()],
+            models: vec![self.name.clone()],
+        }
+    }
+}
@@ -1,44 +1,180 @@
+mod batch;
+
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
+use futures::{future, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::pin::Pin;
+use std::{collections::BTreeMap, sync::Mutex};
+use tracing::{warn, Span};
 
 use crate::{
+    batch::{BatchId, BatchRequest, BatchResult, BatchStatus},
     error::AegisError,
-    models::{Content, ContentPart, Message, Metadata, Role},
-    providers::{Provider, ProviderCapabilities},
+    models::{
+        Citation, Completions, Content, ContentPart, FinishReason, Message, Metadata, Role,
+        ToolCall, ToolChoice, ToolDefinition,
+    },
+    options::SendOptions,
+    providers::{PartialToolCall, Provider, ProviderCapabilities},
+    rate_limit::RateLimitStatus,
+    sse,
+    stream::{self, MessageStream, RawStream},
 };
 
+const DEFAULT_BASE_URL: &str = "https://api.openai.com";
+const DEFAULT_MODEL: &str = "gpt-4-turbo-preview";
+
 pub struct OpenAIProvider {
     client: Client,
     api_key: String,
+    base_url: String,
+    rate_limit: Mutex<Option<RateLimitStatus>>,
+    verbatim_roles: bool,
 }
 
 #[derive(Debug, Serialize)]
 struct OpenAIRequest {
     model: String,
     messages: Vec<OpenAIMessage>,
-    temperature: f32,
-    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAITool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prediction: Option<OpenAIPrediction>,
+    /// Number of choices to generate; see `send_message_n`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct OpenAIStreamOptions {
+    /// Ask for a final chunk carrying token usage.
+    pub(super) include_usage: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct OpenAITool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: OpenAIFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct OpenAIMessage {
+pub(super) struct OpenAIMessage {
     role: String,
-    content: String,
+    #[serde(default)]
+    content: Option<OpenAIContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OpenAIToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+    /// Citations for the message text, e.g. from web search models.
+    #[serde(default, skip_serializing)]
+    annotations: Vec<OpenAIAnnotation>,
+}
+
+/// Message content is either a plain string or an array of typed parts.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<OpenAIContentPart>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIContentPart {
+    Text { text: String },
+    ImageUrl { image_url: OpenAIImageUrl },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIAnnotation {
+    UrlCitation { url_citation: OpenAIUrlCitation },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUrlCitation {
+    url: String,
+    #[serde(default)]
+    title: Option<String>,
+    start_index: usize,
+    end_index: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    call_type: String,
+    function: OpenAIFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    /// JSON-encoded arguments, as OpenAI sends and expects them.
+    arguments: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
+    /// The model that answered; an alias resolves to a dated snapshot here.
+    #[serde(default)]
+    model: Option<String>,
     choices: Vec<OpenAIChoice>,
     usage: Option<OpenAIUsage>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct OpenAIChoice {
+    #[serde(default)]
+    index: u32,
     message: OpenAIMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+    /// Azure's per-category moderation verdicts, keyed by category.
+    #[serde(default)]
+    content_filter_results: BTreeMap<String, OpenAIFilterResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIFilterResult {
+    #[serde(default)]
+    filtered: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,164 +182,935 @@ struct OpenAIUsage {
     prompt_tokens: u32,
     completion_tokens: u32,
     total_tokens: u32,
+    #[serde(default)]
+    completion_tokens_details: Option<OpenAICompletionTokensDetails>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAICompletionTokensDetails {
+    #[serde(default)]
+    accepted_prediction_tokens: Option<u32>,
+    #[serde(default)]
+    rejected_prediction_tokens: Option<u32>,
+}
+
+impl From<OpenAIUsage> for crate::models::Usage {
+    fn from(usage: OpenAIUsage) -> Self {
+        let details = usage.completion_tokens_details;
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            accepted_prediction_tokens: details.as_ref().and_then(|d| d.accepted_prediction_tokens),
+            rejected_prediction_tokens: details.as_ref().and_then(|d| d.rejected_prediction_tokens),
+            estimated: false,
+        }
+    }
+}
+
+/// Predicted output, sent as `{"type": "content", "content": ...}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIPrediction {
+    Content { content: String },
+}
+
+/// One `chat.completion.chunk` event of a streamed response.
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChoice {
+    #[serde(default)]
+    delta: OpenAIStreamDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAIStreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+    /// Visible thinking from reasoning models such as DeepSeek's, streamed
+    /// ahead of the answer.
+    #[serde(default)]
+    reasoning_content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAIToolCallDelta>,
+    /// Citations for the text, usually sent once it is complete.
+    #[serde(default)]
+    annotations: Vec<OpenAIAnnotation>,
+}
+
+/// A fragment of a streamed tool call. The ID and name come with the first
+/// fragment for each `index`; the arguments are spread over the rest.
+#[derive(Debug, Deserialize)]
+struct OpenAIToolCallDelta {
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: OpenAIFunctionDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAIFunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
 }
 
 impl OpenAIProvider {
     pub fn new(api_key: String) -> Self {
         Self { 
             client: Client::new(),
-            api_key 
+            api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            rate_limit: Mutex::new(None),
+            verbatim_roles: false,
         }
     }
 
-    fn convert_to_openai_messages(&self, messages: Vec<Message>) -> Vec<OpenAIMessage> {
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    fn record_rate_limit(&self, headers: &reqwest::header::HeaderMap) {
+        if let Some(status) = RateLimitStatus::from_openai_headers(headers) {
+            *self.rate_limit.lock().unwrap() = Some(status);
+        }
+    }
+
+    fn chat_completions_url(&self) -> String {
+        format!("{}/v1/chat/completions", self.base_url)
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+        stream: bool,
+    ) -> OpenAIRequest {
+        let tools = Self::convert_to_openai_tools(&options.tools);
+        OpenAIRequest {
+            model: options
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            messages: Self::convert_to_openai_messages(messages, self.verbatim_roles),
+            temperature: options.generation.temperature,
+            max_tokens: options.generation.max_tokens,
+            top_p: options.generation.top_p,
+            stop: options.generation.stop_sequences.clone(),
+            stream,
+            // OpenAI rejects parallel_tool_calls on requests without tools
+            parallel_tool_calls: tools.as_ref().and(options.parallel_tool_calls),
+            tool_choice: tools
+                .as_ref()
+                .and(options.tool_choice.as_ref())
+                .map(Self::convert_tool_choice),
+            tools,
+            stream_options: stream.then_some(OpenAIStreamOptions { include_usage: true }),
+            prediction: options
+                .openai
+                .prediction
+                .clone()
+                .map(|content| OpenAIPrediction::Content { content }),
+            n: None,
+        }
+    }
+
+    /// Also used by OpenAI-compatible providers, hence no `&self`.
+    ///
+    /// OpenAI takes each tool result as its own `tool` message naming its
+    /// call, so a turn carrying several results, e.g. answers to parallel
+    /// calls, is split into one message per result, followed by whatever
+    /// else the turn held.
+    pub(super) fn convert_to_openai_messages(
+        messages: Vec<Message>,
+        verbatim_roles: bool,
+    ) -> Vec<OpenAIMessage> {
         messages.into_iter()
-            .map(|msg| OpenAIMessage {
-                role: match msg.role {
-                    Role::User => "user",
-                    Role::Assistant => "assistant",
-                    Role::System => "system",
-                }.to_string(),
-                content: msg.content.parts.into_iter()
+            .flat_map(|msg| {
+                let (results, parts): (Vec<_>, Vec<_>) = msg
+                    .content
+                    .parts
+                    .into_iter()
+                    .partition(|part| matches!(part, ContentPart::ToolResult { .. }));
+                let mut converted: Vec<OpenAIMessage> = results
+                    .into_iter()
                     .filter_map(|part| match part {
-                        ContentPart::Text { text } => Some(text),
-                        _ => None, // Skip non-text content for now
+                        ContentPart::ToolResult { tool_call_id, content } => Some(OpenAIMessage {
+                            role: match msg.role {
+                                _ if verbatim_roles => msg.role.as_str(),
+                                _ => "tool",
+                            }
+                            .to_string(),
+                            content: Some(OpenAIContent::Text(content)),
+                            tool_calls: None,
+                            tool_call_id: Some(tool_call_id),
+                            annotations: Vec::new(),
+                        }),
+                        _ => None,
                     })
-                    .collect::<Vec<_>>()
-                    .join(""),
+                    .collect();
+                if !converted.is_empty() && parts.is_empty() {
+                    return converted;
+                }
+
+                let mut content = Vec::new();
+                let mut tool_calls = Vec::new();
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text, .. } => {
+                            content.push(OpenAIContentPart::Text { text })
+                        }
+                        // Remote URLs and base64 data URLs are both passed as-is
+                        ContentPart::Image { image_url, .. } => {
+                            content.push(OpenAIContentPart::ImageUrl {
+                                image_url: OpenAIImageUrl { url: image_url },
+                            })
+                        }
+                        ContentPart::ToolCall(call) => tool_calls.push(OpenAIToolCall {
+                            id: call.id,
+                            call_type: "function".to_string(),
+                            function: OpenAIFunctionCall {
+                                name: call.name,
+                                arguments: match call.arguments {
+                                    serde_json::Value::String(raw) => raw,
+                                    arguments => arguments.to_string(),
+                                },
+                            },
+                        }),
+                        _ => {} // Skip reasoning, which isn't sent back
+                    }
+                }
+                converted.push(OpenAIMessage {
+                    role: match msg.role {
+                        _ if verbatim_roles => msg.role.as_str(),
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                        Role::System => "system",
+                        Role::Tool => "tool",
+                    }.to_string(),
+                    content: Self::convert_content(content, !tool_calls.is_empty()),
+                    tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+                    tool_call_id: None,
+                    annotations: Vec::new(),
+                });
+                converted
             })
             .collect()
     }
 
-    fn convert_from_openai_message(&self, msg: OpenAIMessage, usage: Option<OpenAIUsage>) -> Message {
+    /// Plain text is sent as a string; content with images needs the array
+    /// form. Assistant turns that only call tools carry no content.
+    fn convert_content(parts: Vec<OpenAIContentPart>, has_tool_calls: bool) -> Option<OpenAIContent> {
+        if parts.iter().any(|part| matches!(part, OpenAIContentPart::ImageUrl { .. })) {
+            return Some(OpenAIContent::Parts(parts));
+        }
+        let text: String = parts
+            .into_iter()
+            .filter_map(|part| match part {
+                OpenAIContentPart::Text { text } => Some(text),
+                _ => None,
+            })
+            .collect();
+        if text.is_empty() && has_tool_calls {
+            None
+        } else {
+            Some(OpenAIContent::Text(text))
+        }
+    }
+
+    /// Also used by Mistral and xAI, which take the same shape.
+    pub(super) fn convert_tool_choice(choice: &ToolChoice) -> serde_json::Value {
+        match choice {
+            ToolChoice::Auto => "auto".into(),
+            ToolChoice::None => "none".into(),
+            ToolChoice::Required => "required".into(),
+            ToolChoice::Specific(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name }
+            }),
+        }
+    }
+
+    pub(super) fn convert_to_openai_tools(tools: &[ToolDefinition]) -> Option<Vec<OpenAITool>> {
+        if tools.is_empty() {
+            return None;
+        }
+        Some(
+            tools
+                .iter()
+                .map(|tool| OpenAITool {
+                    tool_type: "function".to_string(),
+                    function: OpenAIFunction {
+                        name: tool.name.clone(),
+                        description: tool.description.clone(),
+                        parameters: tool.parameters.clone(),
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    fn convert_annotations(annotations: Vec<OpenAIAnnotation>) -> Vec<Citation> {
+        annotations
+            .into_iter()
+            .filter_map(|annotation| match annotation {
+                OpenAIAnnotation::UrlCitation { url_citation } => Some(Citation {
+                    url: Some(url_citation.url),
+                    title: url_citation.title,
+                    cited_text: None,
+                    start_index: Some(url_citation.start_index),
+                    end_index: Some(url_citation.end_index),
+                }),
+                OpenAIAnnotation::Unknown => None,
+            })
+            .collect()
+    }
+
+    fn convert_from_openai_message(
+        msg: OpenAIMessage,
+        finish_reason: Option<String>,
+        usage: Option<OpenAIUsage>,
+        provider: &str,
+        model: &str,
+    ) -> Message {
+        let mut parts = Vec::new();
+        let annotations = Self::convert_annotations(msg.annotations);
+        match msg.content {
+            Some(OpenAIContent::Text(text)) if !text.is_empty() => {
+                parts.push(ContentPart::Text {
+                    text,
+                    annotations,
+                    cache: false,
+                });
+            }
+            Some(OpenAIContent::Parts(content)) => {
+                // Annotations belong to the whole reply, so they go on its first text part
+                let mut annotations = Some(annotations);
+                parts.extend(content.into_iter().filter_map(|part| match part {
+                    OpenAIContentPart::Text { text } => Some(ContentPart::Text {
+                        text,
+                        annotations: annotations.take().unwrap_or_default(),
+                        cache: false,
+                    }),
+                    OpenAIContentPart::ImageUrl { image_url } => {
+                        Some(ContentPart::image(image_url.url))
+                    }
+                    OpenAIContentPart::Unknown => None,
+                }));
+            }
+            _ => {}
+        }
+        for call in msg.tool_calls.unwrap_or_default() {
+            parts.push(ContentPart::ToolCall(ToolCall {
+                id: call.id,
+                name: call.function.name,
+                arguments: serde_json::from_str(&call.function.arguments)
+                    .unwrap_or(serde_json::Value::String(call.function.arguments)),
+            }));
+        }
         Message {
             role: match msg.role.as_str() {
                 "assistant" => Role::Assistant,
                 "user" => Role::User,
-                "system" => Role::System,
-                _ => Role::Assistant,
-            },
-            content: Content {
-                parts: vec![ContentPart::Text { 
-                    text: msg.content 
-                }],
+                "system" | "developer" => Role::System,
+                "tool" => Role::Tool,
+                // Replies are the model's turn whatever the role says
+                other => {
+                    warn!("Treating unrecognised {} role {:?} as assistant", provider, other);
+                    Role::Assistant
+                }
             },
+            content: Content { parts },
             metadata: Some(Metadata {
-                model: Some("gpt-4-turbo-preview".to_string()),
-                provider: Some("openai".to_string()),
-                usage: usage.map(|u| crate::models::Usage {
-                    prompt_tokens: u.prompt_tokens,
-                    completion_tokens: u.completion_tokens,
-                    total_tokens: u.total_tokens,
-                }),
+                model: Some(model.to_string()),
+                provider: Some(provider.to_string()),
+                usage: usage.map(Into::into),
+                response_id: None,
+                finish_reason: finish_reason.as_deref().map(FinishReason::from_provider),
+                stop_sequence: None,
+                system_fingerprint: None,
+                warnings: Vec::new(),
             }),
         }
     }
-}
 
-#[async_trait]
-impl Provider for OpenAIProvider {
-    fn provider_type(&self) -> crate::models::ProviderType {
-        crate::models::ProviderType::OpenAI
+    /// Convert a successful chat completions body, recovering plain text if it
+    /// no longer matches our types.
+    pub(super) fn convert_response_body(
+        body: &str,
+        provider: &str,
+        model: &str,
+    ) -> Result<Message, AegisError> {
+        let parsed: OpenAIResponse = match super::parse_body(body) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                return super::lenient_message(body, provider, |value| {
+                    value
+                        .pointer("/choices/0/message/content")
+                        .and_then(|c| c.as_str())
+                        .map(str::to_string)
+                })
+                .inspect(|_| warn!("Recovered text from unrecognised {} response: {}", provider, e))
+                .ok_or(e);
+            }
+        };
+
+        if let Some(choice) = parsed.choices.into_iter().next() {
+            let mut message = Self::convert_from_openai_message(
+                choice.message,
+                choice.finish_reason,
+                parsed.usage,
+                provider,
+                parsed.model.as_deref().unwrap_or(model),
+            );
+            if let Some(metadata) = &mut message.metadata {
+                metadata.system_fingerprint = parsed.system_fingerprint;
+            }
+            let categories = choice
+                .content_filter_results
+                .into_iter()
+                .filter(|(_, result)| result.filtered)
+                .map(|(category, _)| category)
+                .collect();
+            super::reject_filtered(message, categories)
+        } else {
+            Err(AegisError::EmptyResponse)
+        }
     }
 
-    async fn send_message(&self, messages: Vec<Message>) -> Result<Message, AegisError> {
-        let openai_messages = self.convert_to_openai_messages(messages);
-        
-        let request = OpenAIRequest {
-            model: "gpt-4-turbo-preview".to_string(),
-            messages: openai_messages,
-            temperature: 0.7,
-            max_tokens: 2048,
-            stream: false,
+    /// Convert every choice of a successful chat completions body. Usage
+    /// covers them all, so it goes on the result rather than on each choice,
+    /// and a filtered choice is kept with its `ContentFilter` finish reason.
+    fn convert_choices_body(
+        body: &str,
+        provider: &str,
+        model: &str,
+    ) -> Result<Completions, AegisError> {
+        let OpenAIResponse {
+            model: echoed,
+            mut choices,
+            usage,
+            system_fingerprint,
+        } = super::parse_body(body)?;
+        if choices.is_empty() {
+            return Err(AegisError::EmptyResponse);
+        }
+        let model = echoed.as_deref().unwrap_or(model);
+        choices.sort_by_key(|choice| choice.index);
+        let choices = choices
+            .into_iter()
+            .map(|choice| {
+                let mut message = Self::convert_from_openai_message(
+                    choice.message,
+                    choice.finish_reason,
+                    None,
+                    provider,
+                    model,
+                );
+                if let Some(metadata) = &mut message.metadata {
+                    metadata.system_fingerprint = system_fingerprint.clone();
+                }
+                message
+            })
+            .collect();
+        Ok(Completions {
+            choices,
+            usage: usage.map(Into::into).unwrap_or_default(),
+        })
+    }
+
+    /// Decode a chat completions event stream into text deltas. Tool calls are
+    /// assembled from their fragments and emitted whole, together with the
+    /// finish reason; usage follows on the final chunk.
+    pub(super) fn convert_stream(response: reqwest::Response, provider: String) -> MessageStream {
+        Self::convert_byte_stream(response.bytes_stream(), provider)
+    }
+
+    /// [`convert_stream`](Self::convert_stream) over the raw body chunks,
+    /// which may split events and their JSON anywhere.
+    fn convert_byte_stream<S, B>(bytes: S, provider: String) -> MessageStream
+    where
+        S: futures::Stream<Item = Result<B, reqwest::Error>> + Send + 'static,
+        B: AsRef<[u8]> + 'static,
+    {
+        let stream = super::mark_end(sse::decode(bytes))
+            .scan(Vec::new(), move |tool_calls: &mut Vec<PartialToolCall>, event| {
+                future::ready(Some(match event {
+                    Some(Ok(event)) => {
+                        Self::convert_stream_chunk(&event.data, &provider, tool_calls)
+                    }
+                    Some(Err(e)) => Some(Err(e)),
+                    // Calls are only drained on a finish reason, so any left were cut off
+                    None => tool_calls.drain(..).next().map(|call| Err(call.incomplete())),
+                }))
+            })
+            .filter_map(future::ready);
+        Box::pin(stream)
+    }
+
+    fn convert_stream_chunk(
+        data: &str,
+        provider: &str,
+        tool_calls: &mut Vec<PartialToolCall>,
+    ) -> Option<Result<Message, AegisError>> {
+        if data.trim() == "[DONE]" {
+            return None;
+        }
+        let chunk = match serde_json::from_str::<OpenAIStreamChunk>(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!("Skipping unparseable {} stream chunk: {}", provider, e);
+                return None;
+            }
         };
+        let mut choice = chunk.choices.into_iter().next();
+        let fragments = choice
+            .as_mut()
+            .map(|c| std::mem::take(&mut c.delta.tool_calls))
+            .unwrap_or_default();
+        let annotations = choice
+            .as_mut()
+            .map(|c| Self::convert_annotations(std::mem::take(&mut c.delta.annotations)))
+            .unwrap_or_default();
+        for fragment in fragments {
+            if tool_calls.len() <= fragment.index {
+                tool_calls.resize_with(fragment.index + 1, PartialToolCall::default);
+            }
+            let call = &mut tool_calls[fragment.index];
+            if let Some(id) = fragment.id {
+                call.id = id;
+            }
+            if let Some(name) = fragment.function.name {
+                call.name = name;
+            }
+            call.arguments.push_str(fragment.function.arguments.as_deref().unwrap_or_default());
+        }
+        let finish_reason = choice.as_ref().and_then(|c| c.finish_reason.as_deref());
+        let mut parts = Vec::new();
+        if let Some(text) = choice.as_ref().and_then(|c| c.delta.reasoning_content.as_deref()) {
+            if !text.is_empty() {
+                parts.push(ContentPart::Reasoning {
+                    text: text.to_string(),
+                });
+            }
+        }
+        let text = choice.as_ref().and_then(|c| c.delta.content.as_deref()).unwrap_or_default();
+        if !text.is_empty() || !annotations.is_empty() {
+            parts.push(ContentPart::Text {
+                text: text.to_string(),
+                annotations,
+                cache: false,
+            });
+        }
+        if finish_reason.is_some() {
+            match tool_calls.drain(..).map(PartialToolCall::finish).collect::<Result<Vec<_>, _>>() {
+                Ok(calls) => parts.extend(calls),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let metadata = (finish_reason.is_some() || chunk.usage.is_some()).then(|| Metadata {
+            model: chunk.model,
+            provider: Some(provider.to_string()),
+            usage: chunk.usage.map(Into::into),
+            response_id: None,
+            finish_reason: finish_reason.map(FinishReason::from_provider),
+            stop_sequence: None,
+            system_fingerprint: chunk.system_fingerprint,
+            warnings: Vec::new(),
+        });
+        if parts.is_empty() && metadata.is_none() {
+            return None;
+        }
+        Some(Ok(Message {
+            role: Role::Assistant,
+            content: Content { parts },
+            metadata,
+        }))
+    }
+
+    async fn open_stream(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<reqwest::Response, AegisError> {
+        let request = self.build_request(messages, options, true);
+        Span::current().record("model", request.model.as_str());
 
         let response = self.client
-            .post("https://api.openai.com/v1/chat/completions")
+            .post(self.chat_completions_url())
             .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Accept", "text/event-stream")
             .json(&request)
             .send()
             .await
-            .map_err(|e| AegisError::NetworkError(e))?;
+            .map_err(AegisError::from)?;
+
+        Span::current().record("status", response.status().as_u16());
+        self.record_rate_limit(response.headers());
+        if !response.status().is_success() {
+            return Err(super::stream_error(response).await);
+        }
+
+        Ok(response)
+    }
+
+    /// Send a non-streaming request and return the body of a successful
+    /// response, with any deprecation warnings from its headers.
+    async fn post_completion(
+        &self,
+        request: &OpenAIRequest,
+    ) -> Result<(String, Vec<String>), AegisError> {
+        Span::current().record("model", request.model.as_str());
+
+        let response = self.client
+            .post(self.chat_completions_url())
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
 
         let status = response.status();
-        let body = response.text().await.map_err(|e| AegisError::NetworkError(e))?;
+        let retry_after = crate::retry::retry_after(response.headers());
+        Span::current().record("status", status.as_u16());
+        self.record_rate_limit(response.headers());
+        let warnings = super::header_warnings(response.headers());
+        let body = response.text().await.map_err(AegisError::from)?;
 
         match status {
-            reqwest::StatusCode::OK => {
-                let parsed: OpenAIResponse = serde_json::from_str(&body)
-                    .map_err(|e| AegisError::APIError(e.to_string()))?;
-                
-                if let Some(choice) = parsed.choices.into_iter().next() {
-                    Ok(self.convert_from_openai_message(choice.message, parsed.usage))
-                } else {
-                    Err(AegisError::APIError("No response choices".to_string()))
-                }
+            reqwest::StatusCode::OK => Ok((body, warnings)),
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(AegisError::RateLimitExceeded { retry_after })
             }
-            reqwest::StatusCode::TOO_MANY_REQUESTS => Err(AegisError::RateLimitExceeded),
             reqwest::StatusCode::UNAUTHORIZED => Err(AegisError::InvalidAPIKey),
+            status if status.is_server_error() => {
+                Err(AegisError::ServerError(status.as_u16(), body))
+            }
             _ => Err(AegisError::APIError(format!(
                 "Status: {}, Body: {}",
                 status, body
             )))
         }
     }
+}
+
+#[async_trait]
+impl Provider for OpenAIProvider {
+    fn provider_type(&self) -> crate::models::ProviderType {
+        crate::models::ProviderType::OpenAI
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let request = self.build_request(messages, options, false);
+        let (body, warnings) = self.post_completion(&request).await?;
+        Self::convert_response_body(&body, self.name(), &request.model)
+            .map(|message| super::with_warnings(message, warnings, &body))
+    }
+
+    /// Ask for all `n` choices in one request, with OpenAI's `n` parameter.
+    async fn send_message_n(
+        &self,
+        messages: Vec<Message>,
+        n: u32,
+        options: &SendOptions,
+    ) -> Result<Completions, AegisError> {
+        let mut request = self.build_request(messages, options, false);
+        request.n = Some(n.max(1));
+        let (body, warnings) = self.post_completion(&request).await?;
+        let mut completions = Self::convert_choices_body(&body, self.name(), &request.model)?;
+        completions.choices = completions
+            .choices
+            .into_iter()
+            .map(|choice| super::with_warnings(choice, warnings.clone(), &body))
+            .collect();
+        Ok(completions)
+    }
 
     async fn stream_message(
         &self,
         messages: Vec<Message>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<Message, AegisError>> + Send>>, AegisError> {
-        let openai_messages = self.convert_to_openai_messages(messages);
-        
-        let request = OpenAIRequest {
-            model: "gpt-4-turbo-preview".to_string(),
-            messages: openai_messages,
-            temperature: 0.7,
-            max_tokens: 2048,
-            stream: true,
-        };
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let response = self.open_stream(messages, options).await?;
 
-        let response = self.client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Accept", "text/event-stream")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| AegisError::NetworkError(e))?;
+        Ok(Self::convert_stream(response, self.name().to_string()))
+    }
 
-        if !response.status().is_success() {
-            return Err(AegisError::APIError("Stream request failed".to_string()));
-        }
-
-        let stream = response
-            .bytes_stream()
-            .map(move |chunk| {
-                chunk
-                    .map_err(|e| AegisError::NetworkError(e))
-                    .and_then(|bytes| {
-                        // Parse SSE chunk and convert to Message
-                        // This is a simplified version - you'll need to implement proper SSE parsing
-                        let text = String::from_utf8_lossy(&bytes);
-                        Ok(Message {
-                            role: Role::Assistant,
-                            content: Content {
-                                parts: vec![ContentPart::Text { text: text.to_string() }],
-                            },
-                            metadata: None,
-                        })
-                    })
-            });
+    async fn stream_raw(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        let response = self.open_stream(messages, options).await?;
+        Ok(stream::raw(response))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AegisError> {
+        let request = self
+            .client
+            .get(format!("{}/v1/models", self.base_url))
+            .bearer_auth(&self.api_key);
+        super::list_model_ids(request).await
+    }
 
-        Ok(Box::pin(stream))
+    async fn warmup(&self) {
+        super::warmup(&self.client, &self.base_url).await
     }
 
     fn capabilities(&self) -> ProviderCapabilities {
         ProviderCapabilities {
             streaming: true,
             max_tokens: 4096,
-            supported_content_types: vec!["text".to_string()],
-            models: vec!["gpt-4-turbo-preview".to_string()],
+            supported_content_types: vec!["text".to_string(), "image".to_string()],
+            models: vec![DEFAULT_MODEL.to_string()],
+        }
+    }
+
+    fn last_rate_limit_status(&self) -> Option<RateLimitStatus> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+
+    async fn batch_submit(&self, requests: Vec<BatchRequest>) -> Result<BatchId, AegisError> {
+        self.submit_batch(requests).await
+    }
+
+    async fn batch_poll(&self, id: &BatchId) -> Result<BatchStatus, AegisError> {
+        self.poll_batch(id).await
+    }
+
+    async fn batch_results(&self, id: &BatchId) -> Result<Vec<BatchResult>, AegisError> {
+        self.collect_batch_results(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing;
+
+    #[test]
+    fn unknown_reply_roles_are_treated_as_assistant() {
+        let body = serde_json::json!({
+            "choices": [{
+                "message": { "role": "critic", "content": "Hanoi." },
+                "finish_reason": "stop"
+            }]
+        });
+
+        let message =
+            OpenAIProvider::convert_response_body(&body.to_string(), "openai", "gpt-4o").unwrap();
+
+        assert!(matches!(message.role, Role::Assistant));
+        assert_eq!(message.content.to_string(), "Hanoi.");
+    }
+
+    #[test]
+    fn parallel_tool_calls_only_sent_with_tools() {
+        let provider = OpenAIProvider::new("test-key".to_string());
+        let options = SendOptions::new().with_parallel_tool_calls(false);
+
+        let request = provider.build_request(Vec::new(), &options, false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body.get("parallel_tool_calls").is_none());
+        assert!(body.get("tools").is_none());
+
+        let options = options.with_tools(vec![ToolDefinition {
+            name: "get_weather".to_string(),
+            description: "Get the current weather".to_string(),
+            parameters: serde_json::json!({ "type": "object" }),
+        }]);
+        let request = provider.build_request(Vec::new(), &options, false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["parallel_tool_calls"], false);
+        assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+    }
+
+    #[test]
+    fn unset_generation_params_are_omitted() {
+        let provider = OpenAIProvider::new("test-key".to_string());
+
+        let request = provider.build_request(Vec::new(), &SendOptions::new(), false);
+        let body = serde_json::to_value(&request).unwrap();
+        for field in ["temperature", "max_tokens", "top_p", "stop"] {
+            assert!(body.get(field).is_none(), "{} was sent", field);
         }
+
+        let options = SendOptions::new()
+            .with_temperature(0.0)
+            .with_max_tokens(64)
+            .with_top_p(0.5)
+            .with_stop_sequences(vec!["\n\n".to_string()]);
+        let request = provider.build_request(Vec::new(), &options, false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["temperature"], 0.0);
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["top_p"], 0.5);
+        assert_eq!(body["stop"], serde_json::json!(["\n\n"]));
+    }
+
+    #[tokio::test]
+    async fn stream_split_mid_json_reconstructs_the_text() {
+        let body = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"The capital of Vietnam\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\" is Hanoi.\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        // Cut every event in the middle of its JSON, and the line prefix too
+        let chunks: Vec<Result<Vec<u8>, reqwest::Error>> = body
+            .as_bytes()
+            .chunks(7)
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect();
+
+        let deltas: Vec<_> =
+            OpenAIProvider::convert_byte_stream(futures::stream::iter(chunks), "openai".to_string())
+                .map(|delta| delta.unwrap())
+                .collect()
+                .await;
+
+        // The empty first delta is dropped
+        assert_eq!(deltas.len(), 3);
+        let text: String = deltas.iter().map(|d| d.content.to_string()).collect();
+        assert_eq!(text, "The capital of Vietnam is Hanoi.");
+        let metadata = deltas[2].metadata.as_ref().unwrap();
+        assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    }
+
+    #[test]
+    fn capabilities_list_image_input() {
+        // Aegis rejects or replaces images for providers that don't list them
+        let capabilities = OpenAIProvider::new("test-key".to_string()).capabilities();
+        assert!(capabilities.supported_content_types.iter().any(|t| t == "image"));
+    }
+
+    #[test]
+    fn each_tool_result_in_a_turn_is_its_own_message() {
+        let result = |id: &str, content: &str| ContentPart::ToolResult {
+            tool_call_id: id.to_string(),
+            content: content.to_string(),
+        };
+        let turn = Message::new(
+            Role::Tool,
+            vec![result("call_hanoi", "31°C"), result("call_hue", "27°C")],
+        );
+
+        let request = OpenAIProvider::new("test-key".to_string()).build_request(
+            vec![turn],
+            &SendOptions::default(),
+            false,
+        );
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            body["messages"],
+            serde_json::json!([
+                { "role": "tool", "content": "31°C", "tool_call_id": "call_hanoi" },
+                { "role": "tool", "content": "27°C", "tool_call_id": "call_hue" },
+            ])
+        );
+    }
+
+    #[test]
+    fn images_switch_content_to_the_parts_array() {
+        let messages = vec![
+            Message::user("Hello"),
+            Message::new(
+                Role::User,
+                vec![
+                    ContentPart::text("Compare these."),
+                    ContentPart::image("https://example.com/lantern.png"),
+                    ContentPart::image("data:image/png;base64,iVBORw0KGgo="),
+                ],
+            ),
+        ];
+
+        let request = OpenAIProvider::new("test-key".to_string()).build_request(
+            messages,
+            &SendOptions::default(),
+            false,
+        );
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["messages"][0]["content"], "Hello");
+        assert_eq!(
+            body["messages"][1]["content"],
+            serde_json::json!([
+                { "type": "text", "text": "Compare these." },
+                { "type": "image_url", "image_url": { "url": "https://example.com/lantern.png" } },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+            ])
+        );
+    }
+
+    #[test]
+    fn anthropic_top_k_is_not_sent() {
+        let provider = OpenAIProvider::new("test-key".to_string());
+        let options = SendOptions::new()
+            .with_anthropic(crate::options::AnthropicOptions::new().with_top_k(40));
+
+        let request = provider.build_request(Vec::new(), &options, false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body.get("top_k").is_none());
+    }
+
+    #[test]
+    fn unset_temperature_is_omitted_not_defaulted() {
+        let provider = OpenAIProvider::new("test-key".to_string());
+
+        let request = provider.build_request(Vec::new(), &SendOptions::new(), false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body.get("temperature").is_none());
+
+        // Zero is a real setting (greedy sampling), not the same as unset
+        let options = SendOptions::new().with_temperature(0.0);
+        let request = provider.build_request(Vec::new(), &options, false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["temperature"], 0.0);
+    }
+
+    #[test]
+    fn tool_choice_maps_to_openai_shapes_only_with_tools() {
+        let provider = OpenAIProvider::new("test-key".to_string());
+        testing::assert_tool_choice_shapes(
+            |options| provider.build_request(Vec::new(), options, false),
+            [
+                serde_json::json!("auto"),
+                serde_json::json!("none"),
+                serde_json::json!("required"),
+                serde_json::json!({ "type": "function", "function": { "name": "get_weather" } }),
+            ],
+        );
     }
 }
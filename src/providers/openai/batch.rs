@@ -0,0 +1,202 @@
+//! OpenAI's Batch API. Requests are uploaded as a JSONL file through the
+//! Files API and run as a batch; results are downloaded the same way.
+
+use std::collections::HashSet;
+
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+
+use super::{OpenAIProvider, OpenAIRequest, DEFAULT_MODEL};
+use crate::{
+    batch::{BatchId, BatchRequest, BatchResult, BatchStatus},
+    error::AegisError,
+    models::Message,
+    providers::{read_json, read_text, Provider},
+};
+
+const BATCH_ENDPOINT: &str = "/v1/chat/completions";
+const COMPLETION_WINDOW: &str = "24h";
+
+/// One line of the uploaded input file.
+#[derive(Debug, Serialize)]
+struct BatchInputLine<'a> {
+    custom_id: &'a str,
+    method: &'static str,
+    url: &'static str,
+    body: OpenAIRequest,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileObject {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateBatchRequest<'a> {
+    input_file_id: &'a str,
+    endpoint: &'static str,
+    completion_window: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchObject {
+    id: String,
+    status: String,
+    #[serde(default)]
+    output_file_id: Option<String>,
+    #[serde(default)]
+    error_file_id: Option<String>,
+}
+
+/// One line of an output or error file.
+#[derive(Debug, Deserialize)]
+struct BatchOutputLine {
+    custom_id: String,
+    #[serde(default)]
+    response: Option<BatchOutputResponse>,
+    #[serde(default)]
+    error: Option<BatchOutputError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchOutputResponse {
+    status_code: u16,
+    body: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchOutputError {
+    message: String,
+}
+
+impl OpenAIProvider {
+    pub(super) async fn submit_batch(
+        &self,
+        requests: Vec<BatchRequest>,
+    ) -> Result<BatchId, AegisError> {
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = requests.iter().find(|r| !seen.insert(r.custom_id.as_str())) {
+            return Err(AegisError::APIError(format!(
+                "custom_id {} is used by more than one batch request",
+                duplicate.custom_id
+            )));
+        }
+        let mut jsonl = String::new();
+        for request in requests {
+            let line = BatchInputLine {
+                custom_id: &request.custom_id,
+                method: "POST",
+                url: BATCH_ENDPOINT,
+                body: self.build_request(request.messages, &request.options, false),
+            };
+            jsonl.push_str(&serde_json::to_string(&line).expect("batch lines serialize"));
+            jsonl.push('\n');
+        }
+        let file = self.upload_batch_file(jsonl).await?;
+
+        let response = self
+            .client
+            .post(format!("{}/v1/batches", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&CreateBatchRequest {
+                input_file_id: &file.id,
+                endpoint: BATCH_ENDPOINT,
+                completion_window: COMPLETION_WINDOW,
+            })
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+        let batch: BatchObject = read_json(response).await?;
+        Ok(BatchId(batch.id))
+    }
+
+    pub(super) async fn poll_batch(&self, id: &BatchId) -> Result<BatchStatus, AegisError> {
+        Ok(BatchStatus::from_provider(
+            &self.get_batch(id).await?.status,
+        ))
+    }
+
+    /// Results of every request in a finished batch, successes and failures
+    /// alike, in the order the provider lists them.
+    pub(super) async fn collect_batch_results(&self, id: &BatchId) -> Result<Vec<BatchResult>, AegisError> {
+        let batch = self.get_batch(id).await?;
+        let status = BatchStatus::from_provider(&batch.status);
+        if !status.is_finished() {
+            return Err(AegisError::APIError(format!(
+                "batch {} has not finished (status {})",
+                id, batch.status
+            )));
+        }
+
+        let mut results = Vec::new();
+        for file_id in [batch.output_file_id, batch.error_file_id]
+            .into_iter()
+            .flatten()
+        {
+            let content = self.download_file(&file_id).await?;
+            for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                let line: BatchOutputLine = crate::providers::parse_body(line)?;
+                results.push(BatchResult {
+                    result: self.convert_output_line(&line),
+                    custom_id: line.custom_id,
+                });
+            }
+        }
+        Ok(results)
+    }
+
+    fn convert_output_line(&self, line: &BatchOutputLine) -> Result<Message, AegisError> {
+        match (&line.response, &line.error) {
+            (Some(response), _) if response.status_code == 200 => {
+                Self::convert_response_body(&response.body.to_string(), self.name(), DEFAULT_MODEL)
+            }
+            (Some(response), _) => Err(AegisError::APIError(format!(
+                "Status: {}, Body: {}",
+                response.status_code, response.body
+            ))),
+            (None, Some(error)) => Err(AegisError::APIError(error.message.clone())),
+            (None, None) => Err(AegisError::EmptyResponse),
+        }
+    }
+
+    async fn get_batch(&self, id: &BatchId) -> Result<BatchObject, AegisError> {
+        let response = self
+            .client
+            .get(format!("{}/v1/batches/{}", self.base_url, id))
+            .bearer_auth(&self.api_key)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+        read_json(response).await
+    }
+
+    /// Upload `jsonl` with purpose `batch`.
+    async fn upload_batch_file(&self, jsonl: String) -> Result<FileObject, AegisError> {
+        let file = Part::text(jsonl)
+            .file_name("batch.jsonl")
+            .mime_str("application/jsonl")
+            .expect("application/jsonl is a valid MIME type");
+        let form = Form::new().text("purpose", "batch").part("file", file);
+
+        let response = self
+            .client
+            .post(format!("{}/v1/files", self.base_url))
+            .bearer_auth(&self.api_key)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+        read_json(response).await
+    }
+
+    async fn download_file(&self, file_id: &str) -> Result<String, AegisError> {
+        let response = self
+            .client
+            .get(format!("{}/v1/files/{}/content", self.base_url, file_id))
+            .bearer_auth(&self.api_key)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+        read_text(response).await
+    }
+}
@@ -0,0 +1,246 @@
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Serialize;
+use tracing::Span;
+
+use crate::{
+    error::AegisError,
+    models::{Message, ProviderType},
+    options::SendOptions,
+    providers::{
+        openai::{OpenAIMessage, OpenAIProvider, OpenAIStreamOptions, OpenAITool},
+        Provider, ProviderCapabilities,
+    },
+    stream::{self, MessageStream, RawStream},
+};
+
+/// What sets one OpenAI-compatible chat API apart from another.
+pub trait CompatibleApi: Send + Sync + 'static {
+    const PROVIDER_TYPE: ProviderType;
+    const DEFAULT_BASE_URL: &'static str;
+    const DEFAULT_MODEL: &'static str;
+    /// Models reported in `capabilities`, default model included.
+    const MODELS: &'static [&'static str];
+    /// Ask for usage on the final stream chunk via `stream_options`, for
+    /// APIs that only report it when asked.
+    const STREAM_USAGE: bool = false;
+
+    /// Fields to add to the request body beyond the OpenAI ones, e.g.
+    /// Mistral's `safe_prompt`.
+    fn extra_body(_options: &SendOptions) -> serde_json::Map<String, serde_json::Value> {
+        serde_json::Map::new()
+    }
+}
+
+/// A provider whose chat API is OpenAI-compatible, so message conversion
+/// and stream parsing are shared with `OpenAIProvider`. Mistral and xAI are
+/// instances of this; see `MistralProvider` and `XaiProvider`.
+pub struct OpenAICompatibleProvider<A> {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    verbatim_roles: bool,
+    api: PhantomData<A>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAICompatibleRequest {
+    model: String,
+    messages: Vec<OpenAIMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAITool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<A: CompatibleApi> OpenAICompatibleProvider<A> {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: A::DEFAULT_BASE_URL.to_string(),
+            verbatim_roles: false,
+            api: PhantomData,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    fn chat_completions_url(&self) -> String {
+        format!("{}/v1/chat/completions", self.base_url)
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+        stream: bool,
+    ) -> OpenAICompatibleRequest {
+        let tools = OpenAIProvider::convert_to_openai_tools(&options.tools);
+        OpenAICompatibleRequest {
+            model: options
+                .model
+                .clone()
+                .unwrap_or_else(|| A::DEFAULT_MODEL.to_string()),
+            messages: OpenAIProvider::convert_to_openai_messages(messages, self.verbatim_roles),
+            stream,
+            temperature: options.generation.temperature,
+            max_tokens: options.generation.max_tokens,
+            top_p: options.generation.top_p,
+            stop: options.generation.stop_sequences.clone(),
+            parallel_tool_calls: tools.as_ref().and(options.parallel_tool_calls),
+            tool_choice: tools
+                .as_ref()
+                .and(options.tool_choice.as_ref())
+                .map(OpenAIProvider::convert_tool_choice),
+            tools,
+            stream_options: (stream && A::STREAM_USAGE)
+                .then_some(OpenAIStreamOptions { include_usage: true }),
+            extra: A::extra_body(options),
+        }
+    }
+
+    async fn open_stream(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<reqwest::Response, AegisError> {
+        let request = self.build_request(messages, options, true);
+        Span::current().record("model", request.model.as_str());
+
+        let response = self
+            .client
+            .post(self.chat_completions_url())
+            .bearer_auth(&self.api_key)
+            .header("Accept", "text/event-stream")
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        Span::current().record("status", response.status().as_u16());
+        if !response.status().is_success() {
+            return Err(super::stream_error(response).await);
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl<A: CompatibleApi> Provider for OpenAICompatibleProvider<A> {
+    fn provider_type(&self) -> ProviderType {
+        A::PROVIDER_TYPE
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let request = self.build_request(messages, options, false);
+        Span::current().record("model", request.model.as_str());
+
+        let response = self
+            .client
+            .post(self.chat_completions_url())
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        let status = response.status();
+        let retry_after = crate::retry::retry_after(response.headers());
+        Span::current().record("status", status.as_u16());
+        let warnings = super::header_warnings(response.headers());
+        let body = response.text().await.map_err(AegisError::from)?;
+
+        match status {
+            reqwest::StatusCode::OK => {
+                OpenAIProvider::convert_response_body(&body, self.name(), &request.model)
+                    .map(|message| super::with_warnings(message, warnings, &body))
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(AegisError::RateLimitExceeded { retry_after })
+            }
+            reqwest::StatusCode::UNAUTHORIZED => Err(AegisError::InvalidAPIKey),
+            status if status.is_server_error() => {
+                Err(AegisError::ServerError(status.as_u16(), body))
+            }
+            _ => Err(AegisError::APIError(format!(
+                "Status: {}, Body: {}",
+                status, body
+            ))),
+        }
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let response = self.open_stream(messages, options).await?;
+        Ok(OpenAIProvider::convert_stream(response, self.name().to_string()))
+    }
+
+    async fn stream_raw(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        let response = self.open_stream(messages, options).await?;
+        Ok(stream::raw(response))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AegisError> {
+        let request = self
+            .client
+            .get(format!("{}/v1/models", self.base_url))
+            .bearer_auth(&self.api_key);
+        super::list_model_ids(request).await
+    }
+
+    async fn warmup(&self) {
+        super::warmup(&self.client, &self.base_url).await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming: true,
+            max_tokens: 4096,
+            supported_content_types: vec!["text".to_string()],
+            models: A::MODELS.iter().map(|model| model.to_string()).collect(),
+        }
+    }
+}
@@ -0,0 +1,647 @@
+//! OpenAI's Responses API (`/v1/responses`).
+//!
+//! Unlike chat completions, the API can keep conversation state server-side:
+//! pass `OpenAIOptions::previous_response_id` with the ID from the previous
+//! reply's `Metadata::response_id` and only the new turns need to be sent.
+
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tracing::{warn, Span};
+
+use crate::{
+    error::AegisError,
+    models::{
+        Citation, Content, ContentPart, FinishReason, Message, Metadata, Role, ToolCall, ToolChoice,
+        ToolDefinition, Usage,
+    },
+    options::SendOptions,
+    providers::{Provider, ProviderCapabilities},
+    rate_limit::RateLimitStatus,
+    sse,
+    stream::{self, MessageStream, RawStream},
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com";
+const DEFAULT_MODEL: &str = "gpt-4o";
+
+pub struct OpenAIResponsesProvider {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    rate_limit: Mutex<Option<RateLimitStatus>>,
+    verbatim_roles: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponsesRequest {
+    model: String,
+    input: Vec<InputItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ResponsesTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_response_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponsesTool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+/// One entry of the `input` array: a message, or a tool call/result, which
+/// the Responses API sends as items of their own rather than message parts.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputItem {
+    Message {
+        role: String,
+        content: Vec<InputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputContent {
+    InputText {
+        text: String,
+    },
+    InputImage {
+        image_url: String,
+    },
+    /// Earlier assistant turns are replayed as output text.
+    OutputText {
+        text: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesResponse {
+    id: String,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    output: Vec<OutputItem>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    incomplete_details: Option<IncompleteDetails>,
+    usage: Option<ResponsesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IncompleteDetails {
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputItem {
+    Message {
+        #[serde(default)]
+        content: Vec<OutputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    /// Reasoning summaries, built-in tool calls and anything newer.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputContent {
+    OutputText {
+        text: String,
+        #[serde(default)]
+        annotations: Vec<ResponsesAnnotation>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesAnnotation {
+    UrlCitation {
+        url: String,
+        #[serde(default)]
+        title: Option<String>,
+        start_index: usize,
+        end_index: usize,
+    },
+    /// File citations and anything newer.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    total_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ResponsesStreamEvent {
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(rename = "response.output_text.annotation.added")]
+    AnnotationAdded { annotation: ResponsesAnnotation },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone { item: OutputItem },
+    /// Sent instead of `response.completed` when output was cut short.
+    #[serde(rename = "response.completed", alias = "response.incomplete")]
+    Completed { response: ResponsesResponse },
+    #[serde(other)]
+    Other,
+}
+
+impl OpenAIResponsesProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            rate_limit: Mutex::new(None),
+            verbatim_roles: false,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Without this, tool turns are sent as `user`.
+    pub fn with_verbatim_roles(mut self, verbatim: bool) -> Self {
+        self.verbatim_roles = verbatim;
+        self
+    }
+
+    fn record_rate_limit(&self, headers: &reqwest::header::HeaderMap) {
+        if let Some(status) = RateLimitStatus::from_openai_headers(headers) {
+            *self.rate_limit.lock().unwrap() = Some(status);
+        }
+    }
+
+    fn responses_url(&self) -> String {
+        format!("{}/v1/responses", self.base_url)
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+        stream: bool,
+    ) -> ResponsesRequest {
+        let tools = Self::convert_tools(&options.tools);
+        // The Responses API has no stop sequences, so those are not sent.
+        ResponsesRequest {
+            model: options
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            input: self.convert_to_input(messages),
+            max_output_tokens: options.generation.max_tokens,
+            stream,
+            temperature: options.generation.temperature,
+            top_p: options.generation.top_p,
+            parallel_tool_calls: tools.as_ref().and(options.parallel_tool_calls),
+            tool_choice: tools
+                .as_ref()
+                .and(options.tool_choice.as_ref())
+                .map(Self::convert_tool_choice),
+            tools,
+            previous_response_id: options.openai.previous_response_id.clone(),
+        }
+    }
+
+    fn convert_to_input(&self, messages: Vec<Message>) -> Vec<InputItem> {
+        let mut input = Vec::new();
+        for msg in messages {
+            let role = match msg.role {
+                _ if self.verbatim_roles => msg.role.as_str(),
+                Role::User | Role::Tool => "user",
+                Role::Assistant => "assistant",
+                Role::System => "system",
+            };
+            let mut content = Vec::new();
+            let mut items = Vec::new();
+            for part in msg.content.parts {
+                match part {
+                    ContentPart::Text { text, .. } if matches!(msg.role, Role::Assistant) => {
+                        content.push(InputContent::OutputText { text })
+                    }
+                    ContentPart::Text { text, .. } => {
+                        content.push(InputContent::InputText { text })
+                    }
+                    ContentPart::Image { image_url, .. } => {
+                        content.push(InputContent::InputImage { image_url })
+                    }
+                    ContentPart::ToolCall(call) => items.push(InputItem::FunctionCall {
+                        call_id: call.id,
+                        name: call.name,
+                        arguments: match call.arguments {
+                            serde_json::Value::String(raw) => raw,
+                            arguments => arguments.to_string(),
+                        },
+                    }),
+                    ContentPart::ToolResult {
+                        tool_call_id,
+                        content,
+                    } => items.push(InputItem::FunctionCallOutput {
+                        call_id: tool_call_id,
+                        output: content,
+                    }),
+                    ContentPart::Reasoning { .. } => {}
+                }
+            }
+            if !content.is_empty() {
+                input.push(InputItem::Message {
+                    role: role.to_string(),
+                    content,
+                });
+            }
+            input.extend(items);
+        }
+        input
+    }
+
+    /// Like chat completions, but a specific function is named at the top level.
+    fn convert_tool_choice(choice: &ToolChoice) -> serde_json::Value {
+        match choice {
+            ToolChoice::Auto => "auto".into(),
+            ToolChoice::None => "none".into(),
+            ToolChoice::Required => "required".into(),
+            ToolChoice::Specific(name) => serde_json::json!({ "type": "function", "name": name }),
+        }
+    }
+
+    fn convert_tools(tools: &[ToolDefinition]) -> Option<Vec<ResponsesTool>> {
+        if tools.is_empty() {
+            return None;
+        }
+        Some(
+            tools
+                .iter()
+                .map(|tool| ResponsesTool {
+                    tool_type: "function".to_string(),
+                    name: tool.name.clone(),
+                    description: tool.description.clone(),
+                    parameters: tool.parameters.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    fn convert_output_item(item: OutputItem) -> Vec<ContentPart> {
+        match item {
+            OutputItem::Message { content } => content
+                .into_iter()
+                .filter_map(|c| match c {
+                    OutputContent::OutputText { text, annotations } => Some(ContentPart::Text {
+                        text,
+                        annotations: annotations
+                            .into_iter()
+                            .filter_map(Self::convert_annotation)
+                            .collect(),
+                        cache: false,
+                    }),
+                    OutputContent::Other => None,
+                })
+                .collect(),
+            OutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+            } => vec![ContentPart::ToolCall(ToolCall {
+                id: call_id,
+                name,
+                arguments: serde_json::from_str(&arguments)
+                    .unwrap_or(serde_json::Value::String(arguments)),
+            })],
+            OutputItem::Other => Vec::new(),
+        }
+    }
+
+    fn convert_annotation(annotation: ResponsesAnnotation) -> Option<Citation> {
+        match annotation {
+            ResponsesAnnotation::UrlCitation {
+                url,
+                title,
+                start_index,
+                end_index,
+            } => Some(Citation {
+                url: Some(url),
+                title,
+                cited_text: None,
+                start_index: Some(start_index),
+                end_index: Some(end_index),
+            }),
+            ResponsesAnnotation::Other => None,
+        }
+    }
+
+    /// `model` is the one requested, reported if the response doesn't name one.
+    fn convert_metadata(response: &ResponsesResponse, provider: &str, model: &str) -> Metadata {
+        let finish_reason = match (&response.status, &response.incomplete_details) {
+            (_, Some(details)) => Some(FinishReason::from_provider(&details.reason)),
+            (Some(status), None) if status == "completed" => Some(
+                if response
+                    .output
+                    .iter()
+                    .any(|item| matches!(item, OutputItem::FunctionCall { .. }))
+                {
+                    FinishReason::ToolCalls
+                } else {
+                    FinishReason::Stop
+                },
+            ),
+            _ => None,
+        };
+        Metadata {
+            model: Some(response.model.clone().unwrap_or_else(|| model.to_string())),
+            provider: Some(provider.to_string()),
+            usage: response.usage.as_ref().map(|u| Usage {
+                prompt_tokens: u.input_tokens,
+                completion_tokens: u.output_tokens,
+                total_tokens: u.total_tokens,
+                ..Usage::default()
+            }),
+            response_id: Some(response.id.clone()),
+            finish_reason,
+            stop_sequence: None,
+            system_fingerprint: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn convert_from_response(response: ResponsesResponse, provider: &str, model: &str) -> Message {
+        let metadata = Self::convert_metadata(&response, provider, model);
+        Message {
+            role: Role::Assistant,
+            content: Content {
+                parts: response
+                    .output
+                    .into_iter()
+                    .flat_map(Self::convert_output_item)
+                    .collect(),
+            },
+            metadata: Some(metadata),
+        }
+    }
+
+    /// Map one streamed event to a message delta. Text arrives as deltas, tool
+    /// calls once complete, and the final event carries usage and the response ID.
+    fn convert_stream_event(
+        data: &str,
+        provider: &str,
+        model: &str,
+    ) -> Option<Result<Message, AegisError>> {
+        let event = match serde_json::from_str::<ResponsesStreamEvent>(data) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Skipping unparseable OpenAI Responses stream event: {}", e);
+                return None;
+            }
+        };
+        let (parts, metadata) = match event {
+            ResponsesStreamEvent::OutputTextDelta { delta } if !delta.is_empty() => {
+                (vec![ContentPart::text(delta)], None)
+            }
+            ResponsesStreamEvent::AnnotationAdded { annotation } => (
+                vec![ContentPart::Text {
+                    text: String::new(),
+                    annotations: vec![Self::convert_annotation(annotation)?],
+                    cache: false,
+                }],
+                None,
+            ),
+            ResponsesStreamEvent::OutputItemDone {
+                item: item @ OutputItem::FunctionCall { .. },
+            } => (Self::convert_output_item(item), None),
+            ResponsesStreamEvent::Completed { response } => (
+                Vec::new(),
+                Some(Self::convert_metadata(&response, provider, model)),
+            ),
+            _ => return None,
+        };
+        Some(Ok(Message {
+            role: Role::Assistant,
+            content: Content { parts },
+            metadata,
+        }))
+    }
+
+    async fn open_stream(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<(reqwest::Response, String), AegisError> {
+        let request = self.build_request(messages, options, true);
+        Span::current().record("model", request.model.as_str());
+
+        let response = self
+            .client
+            .post(self.responses_url())
+            .bearer_auth(&self.api_key)
+            .header("Accept", "text/event-stream")
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        Span::current().record("status", response.status().as_u16());
+        self.record_rate_limit(response.headers());
+        if !response.status().is_success() {
+            return Err(super::stream_error(response).await);
+        }
+
+        Ok((response, request.model))
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAIResponsesProvider {
+    fn provider_type(&self) -> crate::models::ProviderType {
+        crate::models::ProviderType::OpenAI
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let request = self.build_request(messages, options, false);
+        Span::current().record("model", request.model.as_str());
+
+        let response = self
+            .client
+            .post(self.responses_url())
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await
+            .map_err(AegisError::from)?;
+
+        let status = response.status();
+        let retry_after = crate::retry::retry_after(response.headers());
+        Span::current().record("status", status.as_u16());
+        self.record_rate_limit(response.headers());
+        let warnings = super::header_warnings(response.headers());
+        let body = response.text().await.map_err(AegisError::from)?;
+
+        match status {
+            reqwest::StatusCode::OK => match super::parse_body::<ResponsesResponse>(&body) {
+                Ok(parsed) => {
+                    Ok(Self::convert_from_response(parsed, self.name(), &request.model))
+                }
+                Err(e) => super::lenient_message(&body, self.name(), |value| {
+                    value
+                        .get("output")?
+                        .as_array()?
+                        .iter()
+                        .filter_map(|item| {
+                            item.get("content")?
+                                .as_array()?
+                                .iter()
+                                .filter(|c| {
+                                    c.get("type").and_then(|t| t.as_str()) == Some("output_text")
+                                })
+                                .filter_map(|c| c.get("text").and_then(|t| t.as_str()))
+                                .map(str::to_string)
+                                .reduce(|a, b| a + &b)
+                        })
+                        .reduce(|a, b| a + &b)
+                })
+                .inspect(|_| {
+                    warn!(
+                        "Recovered text from unrecognised OpenAI Responses response: {}",
+                        e
+                    )
+                })
+                .ok_or(e),
+            }
+            .map(|message| super::with_warnings(message, warnings, &body))
+            .and_then(|message| super::reject_filtered(message, Vec::new())),
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(AegisError::RateLimitExceeded { retry_after })
+            }
+            reqwest::StatusCode::UNAUTHORIZED => Err(AegisError::InvalidAPIKey),
+            status if status.is_server_error() => {
+                Err(AegisError::ServerError(status.as_u16(), body))
+            }
+            _ => Err(AegisError::APIError(format!(
+                "Status: {}, Body: {}",
+                status, body
+            ))),
+        }
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let (response, model) = self.open_stream(messages, options).await?;
+
+        let provider = self.name().to_string();
+        let stream = sse::decode(response.bytes_stream()).filter_map(move |event| {
+            future::ready(match event {
+                Ok(event) => Self::convert_stream_event(&event.data, &provider, &model),
+                Err(e) => Some(Err(e)),
+            })
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn stream_raw(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<RawStream, AegisError> {
+        let (response, _) = self.open_stream(messages, options).await?;
+        Ok(stream::raw(response))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AegisError> {
+        let request = self
+            .client
+            .get(format!("{}/v1/models", self.base_url))
+            .bearer_auth(&self.api_key);
+        super::list_model_ids(request).await
+    }
+
+    async fn warmup(&self) {
+        super::warmup(&self.client, &self.base_url).await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming: true,
+            max_tokens: 16384,
+            supported_content_types: vec!["text".to_string(), "image".to_string()],
+            models: vec![DEFAULT_MODEL.to_string(), "gpt-4o-mini".to_string()],
+        }
+    }
+
+    fn last_rate_limit_status(&self) -> Option<RateLimitStatus> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing;
+
+    #[test]
+    fn tool_choice_names_specific_functions_at_top_level() {
+        let provider = OpenAIResponsesProvider::new("test-key".to_string());
+        testing::assert_tool_choice_shapes(
+            |options| provider.build_request(Vec::new(), options, false),
+            [
+                serde_json::json!("auto"),
+                serde_json::json!("none"),
+                serde_json::json!("required"),
+                serde_json::json!({ "type": "function", "name": "get_weather" }),
+            ],
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! In-crate fake providers for unit tests.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use async_trait::async_trait;
+use futures::{future, stream};
+use tokio::sync::{Notify, Semaphore};
+
+use crate::{
+    error::AegisError,
+    models::{Message, Metadata, ProviderType, Role, ToolChoice, ToolDefinition},
+    options::SendOptions,
+    providers::{Provider, ProviderCapabilities},
+    stream::MessageStream,
+};
+
+/// Replies with a fixed message, or streams it as one delta per text chunk.
+pub(crate) struct ScriptedProvider {
+    pub provider_type: ProviderType,
+    pub chunks: Vec<String>,
+    /// Attached to every streamed delta, e.g. to report usage as it goes.
+    pub metadata: Option<Metadata>,
+}
+
+impl ScriptedProvider {
+    pub fn new(provider_type: ProviderType, chunks: &[&str]) -> Self {
+        Self {
+            provider_type,
+            chunks: chunks.iter().map(|c| c.to_string()).collect(),
+            metadata: None,
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+}
+
+#[async_trait]
+impl Provider for ScriptedProvider {
+    fn provider_type(&self) -> ProviderType {
+        self.provider_type.clone()
+    }
+
+    async fn send_message(
+        &self,
+        _messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        Ok(Message::text(Role::Assistant, self.chunks.concat()))
+    }
+
+    async fn stream_message(
+        &self,
+        _messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        let deltas: Vec<_> = self
+            .chunks
+            .iter()
+            .cloned()
+            .map(|c| {
+                Ok(Message {
+                    metadata: self.metadata.clone(),
+                    ..Message::text(Role::Assistant, c)
+                })
+            })
+            .collect();
+        Ok(Box::pin(stream::iter(deltas)))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            streaming: true,
+            max_tokens: 1024,
+            supported_content_types: vec!["text".to_string()],
+            models: vec!["scripted".to_string()],
+        }
+    }
+}
+
+/// Replies with the text of the last message it was sent; an empty last
+/// message fails with `APIError`.
+pub(crate) struct EchoProvider;
+
+#[async_trait]
+impl Provider for EchoProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Anthropic
+    }
+
+    async fn send_message(
+        &self,
+        messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let text = messages.last().map(|m| m.content.to_string()).unwrap_or_default();
+        if text.is_empty() {
+            return Err(AegisError::APIError("empty prompt".to_string()));
+        }
+        Ok(Message::text(Role::Assistant, text))
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        Ok(crate::stream::into_stream(self.send_message(messages, options).await))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ScriptedProvider::new(ProviderType::Anthropic, &[]).capabilities()
+    }
+}
+
+/// Replies with `text` once `gate` has a permit for the request, signalling
+/// `started` when one arrives, so a test can hold requests in flight.
+pub(crate) struct GatedProvider {
+    pub text: &'static str,
+    pub gate: Arc<Semaphore>,
+    pub started: Arc<Notify>,
+}
+
+#[async_trait]
+impl Provider for GatedProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::OpenAI
+    }
+
+    async fn send_message(
+        &self,
+        _messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        self.started.notify_one();
+        self.gate.acquire().await.unwrap().forget();
+        Ok(Message::text(Role::Assistant, self.text.to_string()))
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        Ok(crate::stream::into_stream(self.send_message(messages, options).await))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ScriptedProvider::new(ProviderType::OpenAI, &[]).capabilities()
+    }
+}
+
+/// Never replies. `cancelled` is set once a pending request is dropped.
+#[derive(Default)]
+pub(crate) struct StalledProvider {
+    pub cancelled: Arc<AtomicBool>,
+}
+
+/// Sets its flag when dropped, i.e. when the future holding it is cancelled.
+struct CancelGuard(Arc<AtomicBool>);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Provider for StalledProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::OpenAI
+    }
+
+    async fn send_message(
+        &self,
+        _messages: Vec<Message>,
+        _options: &SendOptions,
+    ) -> Result<Message, AegisError> {
+        let _guard = CancelGuard(Arc::clone(&self.cancelled));
+        future::pending().await
+    }
+
+    async fn stream_message(
+        &self,
+        messages: Vec<Message>,
+        options: &SendOptions,
+    ) -> Result<MessageStream, AegisError> {
+        Ok(crate::stream::into_stream(self.send_message(messages, options).await))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ScriptedProvider::new(ProviderType::OpenAI, &[]).capabilities()
+    }
+}
+
+/// Check how the request body `build` makes carries `tool_choice`: left out
+/// without tools, then `expected` for `Auto`, `None`, `Required` and
+/// `Specific("get_weather")` in turn once a tool is offered.
+pub(crate) fn assert_tool_choice_shapes<R: serde::Serialize>(
+    build: impl Fn(&SendOptions) -> R,
+    expected: [serde_json::Value; 4],
+) {
+    let options = SendOptions::new().with_tool_choice(ToolChoice::Required);
+    let body = serde_json::to_value(build(&options)).unwrap();
+    assert!(body.get("tool_choice").is_none());
+
+    let options = options.with_tools(vec![ToolDefinition {
+        name: "get_weather".to_string(),
+        description: "Get the current weather".to_string(),
+        parameters: serde_json::json!({ "type": "object" }),
+    }]);
+    let choices = [
+        ToolChoice::Auto,
+        ToolChoice::None,
+        ToolChoice::Required,
+        ToolChoice::Specific("get_weather".to_string()),
+    ];
+    for (choice, expected) in choices.into_iter().zip(expected) {
+        let options = options.clone().with_tool_choice(choice);
+        let body = serde_json::to_value(build(&options)).unwrap();
+        assert_eq!(body["tool_choice"], expected);
+    }
+}
@@ -0,0 +1,16 @@
+use crate::models::ProviderType;
+
+use super::openai_compatible::{CompatibleApi, OpenAICompatibleProvider};
+
+/// xAI's Grok models. Streamed usage is only reported when asked for.
+pub type XaiProvider = OpenAICompatibleProvider<Xai>;
+
+pub struct Xai;
+
+impl CompatibleApi for Xai {
+    const PROVIDER_TYPE: ProviderType = ProviderType::Xai;
+    const DEFAULT_BASE_URL: &'static str = "https://api.x.ai";
+    const DEFAULT_MODEL: &'static str = "grok-2-latest";
+    const MODELS: &'static [&'static str] = &["grok-2-latest", "grok-beta"];
+    const STREAM_USAGE: bool = true;
+}
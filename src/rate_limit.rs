@@ -0,0 +1,49 @@
+//! Rate-limit state reported by providers in response headers.
+
+use reqwest::header::HeaderMap;
+
+/// Remaining request/token allowance from the most recent response.
+///
+/// `*_reset` values are passed through in the provider's own format: an
+/// RFC 3339 timestamp for Anthropic, a duration such as `6m0s` for OpenAI.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimitStatus {
+    pub requests_limit: Option<u64>,
+    pub requests_remaining: Option<u64>,
+    pub requests_reset: Option<String>,
+    pub tokens_limit: Option<u64>,
+    pub tokens_remaining: Option<u64>,
+    pub tokens_reset: Option<String>,
+}
+
+impl RateLimitStatus {
+    /// Parse `anthropic-ratelimit-*` headers.
+    pub fn from_anthropic_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::from_headers(headers, |kind, field| format!("anthropic-ratelimit-{}-{}", kind, field))
+    }
+
+    /// Parse `x-ratelimit-*` headers as sent by OpenAI and compatible APIs.
+    pub fn from_openai_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::from_headers(headers, |kind, field| format!("x-ratelimit-{}-{}", field, kind))
+    }
+
+    fn from_headers(headers: &HeaderMap, name: impl Fn(&str, &str) -> String) -> Option<Self> {
+        let text = |kind: &str, field: &str| {
+            headers
+                .get(name(kind, field))
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let number = |kind: &str, field: &str| text(kind, field).and_then(|v| v.parse().ok());
+
+        let status = Self {
+            requests_limit: number("requests", "limit"),
+            requests_remaining: number("requests", "remaining"),
+            requests_reset: text("requests", "reset"),
+            tokens_limit: number("tokens", "limit"),
+            tokens_remaining: number("tokens", "remaining"),
+            tokens_reset: text("tokens", "reset"),
+        };
+        (status != Self::default()).then_some(status)
+    }
+}
@@ -0,0 +1,170 @@
+//! Masking of personal data in message text.
+//!
+//! By default a [`RedactionPolicy`] only affects what Aegis logs, so model
+//! quality is unchanged. Call [`RedactionPolicy::scrub_before_send`] to also
+//! mask the text that is sent to the provider.
+
+use regex::Regex;
+
+use crate::models::{ContentPart, Message};
+
+#[derive(Debug, Clone)]
+pub struct RedactionRule {
+    pub name: String,
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RedactionPolicy {
+    rules: Vec<RedactionRule>,
+    scrub_before_send: bool,
+}
+
+impl RedactionPolicy {
+    /// A policy with no rules; add them with [`RedactionPolicy::with_pattern`].
+    /// [`RedactionPolicy::default`] starts from the common PII rules instead.
+    pub fn empty() -> Self {
+        Self {
+            rules: Vec::new(),
+            scrub_before_send: false,
+        }
+    }
+
+    /// Mask every match of `pattern` with `[REDACTED_<NAME>]`.
+    pub fn with_pattern(mut self, name: &str, pattern: &str) -> Result<Self, regex::Error> {
+        self.rules.push(RedactionRule {
+            name: name.to_string(),
+            pattern: Regex::new(pattern)?,
+            replacement: format!("[REDACTED_{}]", name.to_uppercase()),
+        });
+        Ok(self)
+    }
+
+    /// Also scrub messages before they are sent, not only before they are logged.
+    pub fn scrub_before_send(mut self, enabled: bool) -> Self {
+        self.scrub_before_send = enabled;
+        self
+    }
+
+    pub fn scrubs_before_send(&self) -> bool {
+        self.scrub_before_send
+    }
+
+    pub fn rules(&self) -> &[RedactionRule] {
+        &self.rules
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        self.rules.iter().fold(text.to_string(), |text, rule| {
+            rule.pattern
+                .replace_all(&text, rule.replacement.as_str())
+                .into_owned()
+        })
+    }
+
+    /// Mask the text parts and tool results of `message` in place.
+    pub fn scrub(&self, message: &mut Message) {
+        for part in &mut message.content.parts {
+            match part {
+                ContentPart::Text { text, .. } => *text = self.redact(text),
+                ContentPart::ToolResult { content, .. } => *content = self.redact(content),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Default for RedactionPolicy {
+    /// Emails, US social security numbers, credit-card-like digit runs and phone numbers.
+    fn default() -> Self {
+        Self::empty()
+            .with_pattern("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}")
+            .and_then(|p| p.with_pattern("ssn", r"\b\d{3}-\d{2}-\d{4}\b"))
+            .and_then(|p| p.with_pattern("card", r"\b(?:\d[ -]?){12,18}\d\b"))
+            .and_then(|p| {
+                p.with_pattern("phone", r"(?:\+\d{1,3}[ .-]?)?\(?\b\d{3}\)?[ .-]?\d{3}[ .-]?\d{4}\b")
+            })
+            .expect("default redaction patterns are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::logging;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn default_policy_masks_common_pii() {
+        let policy = RedactionPolicy::default();
+        let text = policy.redact(
+            "Mail jane.doe@example.com, call 415-555-0132, card 4111 1111 1111 1111, SSN 078-05-1120",
+        );
+
+        assert_eq!(
+            text,
+            "Mail [REDACTED_EMAIL], call [REDACTED_PHONE], card [REDACTED_CARD], SSN [REDACTED_SSN]"
+        );
+    }
+
+    #[test]
+    fn empty_policy_masks_only_added_patterns() {
+        let policy = RedactionPolicy::empty().with_pattern("ticket", r"TCK-\d+").unwrap();
+
+        assert_eq!(
+            policy.redact("TCK-42 from jane.doe@example.com"),
+            "[REDACTED_TICKET] from jane.doe@example.com"
+        );
+    }
+
+    #[test]
+    fn fake_ssn_is_masked_in_logs() {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let policy = RedactionPolicy::default();
+        let messages = vec![Message::user("My SSN is 078-05-1120")];
+        tracing::subscriber::with_default(subscriber, || {
+            logging::log_messages(Some(&policy), &messages);
+        });
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("My SSN is [REDACTED_SSN]"));
+        assert!(!logs.contains("078-05-1120"));
+        // Logging-only by default: the message itself is untouched
+        assert_eq!(messages[0].content.to_string(), "My SSN is 078-05-1120");
+    }
+
+    #[test]
+    fn scrub_masks_message_in_place() {
+        let policy = RedactionPolicy::default().scrub_before_send(true);
+        let mut message = Message::user("reach me at jane.doe@example.com");
+        policy.scrub(&mut message);
+
+        assert!(policy.scrubs_before_send());
+        assert_eq!(message.content.to_string(), "reach me at [REDACTED_EMAIL]");
+    }
+}
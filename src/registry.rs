@@ -0,0 +1,284 @@
+//! Per-model capabilities and limits, looked up by model ID.
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+/// The role a model expects instructions to be sent in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionRole {
+    System,
+    /// OpenAI reasoning models take instructions as `developer` messages.
+    Developer,
+    /// The model accepts no instruction role; fold instructions into the first user turn.
+    User,
+}
+
+/// List price in US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl Pricing {
+    /// Cost of one request's tokens.
+    pub fn cost(&self, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+        (f64::from(prompt_tokens) * self.input_per_mtok
+            + f64::from(completion_tokens) * self.output_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+/// What a model supports and how large its requests may be.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSpec {
+    pub context_window: u32,
+    pub max_output: u32,
+    pub supports_vision: bool,
+    pub supports_tools: bool,
+    /// Send `max_completion_tokens` instead of `max_tokens` (OpenAI reasoning models).
+    pub uses_max_completion_tokens: bool,
+    pub instruction_role: InstructionRole,
+    /// Temperatures the model accepts; `None` if it rejects the parameter
+    /// altogether, as OpenAI reasoning models do.
+    pub temperature: Option<RangeInclusive<f32>>,
+    /// Used for cost accounting; `None` when the price isn't known.
+    pub pricing: Option<Pricing>,
+}
+
+impl ModelSpec {
+    /// A text-only model with tool support, a `system` role and OpenAI's
+    /// 0–2 temperature range.
+    pub fn new(context_window: u32, max_output: u32) -> Self {
+        Self {
+            context_window,
+            max_output,
+            supports_vision: false,
+            supports_tools: true,
+            uses_max_completion_tokens: false,
+            instruction_role: InstructionRole::System,
+            temperature: Some(0.0..=2.0),
+            pricing: None,
+        }
+    }
+
+    pub fn with_vision(mut self, supports_vision: bool) -> Self {
+        self.supports_vision = supports_vision;
+        self
+    }
+
+    pub fn with_tools(mut self, supports_tools: bool) -> Self {
+        self.supports_tools = supports_tools;
+        self
+    }
+
+    pub fn with_max_completion_tokens(mut self, uses_max_completion_tokens: bool) -> Self {
+        self.uses_max_completion_tokens = uses_max_completion_tokens;
+        self
+    }
+
+    pub fn with_instruction_role(mut self, instruction_role: InstructionRole) -> Self {
+        self.instruction_role = instruction_role;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: Option<RangeInclusive<f32>>) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Set the list price in US dollars per million input and output tokens.
+    pub fn with_pricing(mut self, input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        self.pricing = Some(Pricing {
+            input_per_mtok,
+            output_per_mtok,
+        });
+        self
+    }
+
+    fn reasoning(context_window: u32, max_output: u32) -> Self {
+        Self::new(context_window, max_output)
+            .with_max_completion_tokens(true)
+            .with_instruction_role(InstructionRole::Developer)
+            .with_temperature(None)
+    }
+
+    /// Anthropic and Cohere models take temperatures up to 1.
+    fn unit_temperature(context_window: u32, max_output: u32) -> Self {
+        Self::new(context_window, max_output).with_temperature(Some(0.0..=1.0))
+    }
+}
+
+/// Model specs keyed by model ID.
+///
+/// Lookups match exactly first, then fall back to the longest registered ID
+/// that prefixes the requested one, so `gpt-4o-2024-08-06` resolves to the
+/// `gpt-4o` entry. `ModelRegistry::default()` ships the built-in table.
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    models: HashMap<String, ModelSpec>,
+}
+
+impl ModelRegistry {
+    /// An empty registry with no built-in models.
+    pub fn new() -> Self {
+        Self {
+            models: HashMap::new(),
+        }
+    }
+
+    /// Add or replace the spec for `model`.
+    pub fn with_model(mut self, model: impl Into<String>, spec: ModelSpec) -> Self {
+        self.insert(model, spec);
+        self
+    }
+
+    pub fn insert(&mut self, model: impl Into<String>, spec: ModelSpec) {
+        self.models.insert(model.into(), spec);
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelSpec> {
+        self.models.get(model).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(id, _)| model.starts_with(id.as_str()))
+                .max_by_key(|(id, _)| id.len())
+                .map(|(_, spec)| spec)
+        })
+    }
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::new()
+            // OpenAI
+            .with_model(
+                "gpt-4o",
+                ModelSpec::new(128_000, 16_384)
+                    .with_vision(true)
+                    .with_pricing(2.50, 10.00),
+            )
+            .with_model(
+                "gpt-4o-mini",
+                ModelSpec::new(128_000, 16_384)
+                    .with_vision(true)
+                    .with_pricing(0.15, 0.60),
+            )
+            .with_model(
+                "gpt-4-turbo",
+                ModelSpec::new(128_000, 4_096)
+                    .with_vision(true)
+                    .with_pricing(10.00, 30.00),
+            )
+            .with_model("gpt-4", ModelSpec::new(8_192, 8_192).with_pricing(30.00, 60.00))
+            .with_model("gpt-3.5-turbo", ModelSpec::new(16_385, 4_096).with_pricing(0.50, 1.50))
+            .with_model(
+                "o1",
+                ModelSpec::reasoning(200_000, 100_000)
+                    .with_vision(true)
+                    .with_pricing(15.00, 60.00),
+            )
+            .with_model(
+                "o1-mini",
+                ModelSpec::reasoning(128_000, 65_536)
+                    .with_tools(false)
+                    .with_instruction_role(InstructionRole::User)
+                    .with_pricing(3.00, 12.00),
+            )
+            .with_model("o3-mini", ModelSpec::reasoning(200_000, 100_000).with_pricing(1.10, 4.40))
+            // Anthropic
+            .with_model(
+                "claude-3-5-sonnet",
+                ModelSpec::unit_temperature(200_000, 8_192)
+                    .with_vision(true)
+                    .with_pricing(3.00, 15.00),
+            )
+            .with_model(
+                "claude-3-5-haiku",
+                ModelSpec::unit_temperature(200_000, 8_192)
+                    .with_vision(true)
+                    .with_pricing(0.80, 4.00),
+            )
+            .with_model(
+                "claude-3-opus",
+                ModelSpec::unit_temperature(200_000, 4_096)
+                    .with_vision(true)
+                    .with_pricing(15.00, 75.00),
+            )
+            .with_model(
+                "claude-3-sonnet",
+                ModelSpec::unit_temperature(200_000, 4_096)
+                    .with_vision(true)
+                    .with_pricing(3.00, 15.00),
+            )
+            .with_model(
+                "claude-3-haiku",
+                ModelSpec::unit_temperature(200_000, 4_096)
+                    .with_vision(true)
+                    .with_pricing(0.25, 1.25),
+            )
+            // Cohere
+            .with_model(
+                "command-r-plus",
+                ModelSpec::unit_temperature(128_000, 4_096).with_pricing(2.50, 10.00),
+            )
+            .with_model(
+                "command-r",
+                ModelSpec::unit_temperature(128_000, 4_096).with_pricing(0.15, 0.60),
+            )
+            // Mistral
+            .with_model(
+                "mistral-large",
+                ModelSpec::new(128_000, 4_096)
+                    .with_temperature(Some(0.0..=1.5))
+                    .with_pricing(2.00, 6.00),
+            )
+            .with_model(
+                "mistral-small",
+                ModelSpec::new(32_000, 4_096)
+                    .with_temperature(Some(0.0..=1.5))
+                    .with_pricing(0.20, 0.60),
+            )
+            .with_model(
+                "codestral",
+                ModelSpec::new(32_000, 4_096)
+                    .with_temperature(Some(0.0..=1.5))
+                    .with_pricing(0.30, 0.90),
+            )
+            // xAI
+            .with_model("grok-2", ModelSpec::new(131_072, 4_096).with_pricing(2.00, 10.00))
+            .with_model("grok-beta", ModelSpec::new(131_072, 4_096).with_pricing(5.00, 15.00))
+            // Gemini
+            .with_model("gemini-1.5-pro", ModelSpec::new(2_097_152, 8_192).with_pricing(1.25, 5.00))
+            .with_model("gemini-1.5-flash", ModelSpec::new(1_048_576, 8_192).with_pricing(0.075, 0.30))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dated_ids_resolve_to_the_longest_prefix() {
+        let registry = ModelRegistry::default();
+
+        let spec = registry.get("gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(spec.max_output, 16_384);
+        assert!(spec.supports_vision);
+
+        let spec = registry.get("o1-mini-2024-09-12").unwrap();
+        assert!(!spec.supports_tools);
+        assert_eq!(spec.instruction_role, InstructionRole::User);
+
+        assert_eq!(registry.get("claude-3-sonnet-20240229").unwrap().context_window, 200_000);
+        assert!(registry.get("llama-3").is_none());
+    }
+
+    #[test]
+    fn overrides_replace_baseline_entries() {
+        let registry =
+            ModelRegistry::default().with_model("gpt-4o", ModelSpec::new(64_000, 1_000));
+
+        assert_eq!(registry.get("gpt-4o").unwrap().context_window, 64_000);
+        assert!(registry.get("o3-mini").unwrap().uses_max_completion_tokens);
+    }
+}
@@ -0,0 +1,323 @@
+//! Retrying of transient provider failures.
+//!
+//! Both `send_message` and the opening of a stream go through [`retry`]. For
+//! streams only the initial request is retried: once the provider has started
+//! sending deltas, a failure is surfaced through the stream instead, since
+//! blindly re-sending would duplicate output the caller has already seen.
+//!
+//! A rate limit that names its own wait in `Retry-After` is retried after
+//! that wait (capped at `max_backoff`); otherwise the policy's exponential
+//! backoff applies.
+//!
+//! Waits go through tokio's timer, as do request deadlines, so tests can run
+//! them on virtual time with `#[tokio::test(start_paused = true)]` instead
+//! of really sleeping.
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tracing::warn;
+
+use crate::error::AegisError;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; `0` disables retrying.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Delay before retry number `attempt` (starting at 0), capped at `max_backoff`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt as i32);
+        self.initial_backoff.mul_f64(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+/// The wait requested by a `Retry-After` header, given either as seconds or
+/// as an HTTP date. `None` if the header is missing or unparseable; a date
+/// already past means no wait.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Run `op`, re-running it on retryable errors as allowed by `policy`.
+pub(crate) async fn retry<T, F, Fut>(policy: Option<&RetryPolicy>, mut op: F) -> Result<T, AegisError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AegisError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if e.is_retryable() => match policy {
+                Some(policy) if attempt < policy.max_retries => {
+                    let delay = match e {
+                        AegisError::RateLimitExceeded {
+                            retry_after: Some(wait),
+                        } => wait.min(policy.max_backoff),
+                        _ => policy.backoff(attempt),
+                    };
+                    warn!("Retrying after {} ({:?}): attempt {}", e.kind(), delay, attempt + 1);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return Err(e),
+            },
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        config::AegisConfig,
+        models::{Message, ProviderType},
+        providers::anthropic::AnthropicProvider,
+        Aegis,
+    };
+
+    fn aegis(server: &MockServer, config: AegisConfig) -> Aegis {
+        Aegis::with_providers(
+            vec![Arc::new(
+                AnthropicProvider::new("test-key".to_string()).with_base_url(server.uri()),
+            )],
+            config,
+        )
+    }
+
+    async fn rate_limited_then_streaming() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(429))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+                "text/event-stream",
+            ))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy::new(5)
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(300));
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(300));
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, retry_after.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_dates() {
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        let wait = retry_after(&headers(&later)).unwrap();
+        assert!(wait > Duration::from_secs(110) && wait <= Duration::from_secs(120));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+
+    async fn rate_limited_once(response: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(response)
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "msg_1",
+                "content": [{ "type": "text", "text": "Hello!" }],
+                "stop_reason": "end_turn"
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_runs_on_virtual_time() {
+        let policy = RetryPolicy::new(4).with_initial_backoff(Duration::from_secs(10));
+        let started = tokio::time::Instant::now();
+        let wall_clock = std::time::Instant::now();
+        let mut attempts = 0;
+
+        let result = retry(Some(&policy), || {
+            attempts += 1;
+            let result = match attempts {
+                1..=4 => Err(AegisError::EmptyResponse),
+                _ => Ok(attempts),
+            };
+            async move { result }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 5);
+        // 10s + 20s + 30s (capped) + 30s, none of it really waited
+        assert_eq!(started.elapsed(), Duration::from_secs(90));
+        assert!(wall_clock.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_waits_as_long_as_retry_after_asks() {
+        let server =
+            rate_limited_once(ResponseTemplate::new(429).insert_header("retry-after", "1")).await;
+        let policy = RetryPolicy::new(1).with_initial_backoff(Duration::from_millis(1));
+        let started = tokio::time::Instant::now();
+
+        aegis(&server, AegisConfig::new().with_retry(policy))
+            .send_message(ProviderType::Anthropic, vec![Message::user("Hello")])
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_without_retry_after_uses_backoff() {
+        let server = rate_limited_once(ResponseTemplate::new(429)).await;
+        let policy = RetryPolicy::new(1).with_initial_backoff(Duration::from_millis(1));
+        let started = std::time::Instant::now();
+
+        let reply = aegis(&server, AegisConfig::new().with_retry(policy))
+            .send_message(ProviderType::Anthropic, vec![Message::user("Hello")])
+            .await
+            .unwrap();
+
+        assert_eq!(reply.content.to_string(), "Hello!");
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stream_start_retries_after_rate_limit() {
+        let server = rate_limited_then_streaming().await;
+        let policy = RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(1));
+
+        let mut stream = aegis(&server, AegisConfig::new().with_retry(policy))
+            .stream_message(ProviderType::Anthropic, vec![Message::user("Hello")])
+            .await
+            .unwrap();
+        while let Some(chunk) = stream.next().await {
+            chunk.unwrap();
+        }
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stream_start_fails_without_retry_policy() {
+        let server = rate_limited_then_streaming().await;
+
+        let result = aegis(&server, AegisConfig::new())
+            .stream_message(ProviderType::Anthropic, vec![Message::user("Hello")])
+            .await;
+
+        assert!(matches!(result, Err(AegisError::RateLimitExceeded { .. })));
+    }
+
+    #[tokio::test]
+    async fn empty_reply_is_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "msg_empty",
+                "content": [],
+                "stop_reason": "end_turn"
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "msg_1",
+                "content": [{ "type": "text", "text": "Hello!" }],
+                "stop_reason": "end_turn"
+            })))
+            .mount(&server)
+            .await;
+        let policy = RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(1));
+
+        let reply = aegis(&server, AegisConfig::new().with_retry(policy))
+            .send_message(ProviderType::Anthropic, vec![Message::user("Hello")])
+            .await
+            .unwrap();
+
+        assert_eq!(reply.content.to_string(), "Hello!");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,180 @@
+//! Server-sent events decoding shared by the streaming providers.
+
+use std::collections::VecDeque;
+
+use futures::{stream, Stream, StreamExt};
+use tracing::warn;
+
+use crate::error::AegisError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incremental decoder that buffers partial events across network chunks.
+///
+/// Bytes are only decoded once a whole event has arrived, so multi-byte
+/// characters split across chunks survive intact. Events that are not valid
+/// UTF-8 are dropped with a warning rather than passed on as mangled text.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buffer: Vec<u8>,
+    skipped: usize,
+}
+
+impl SseDecoder {
+    /// Feed a chunk of bytes and return every event it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.block_end() {
+            let block: Vec<u8> = self.buffer.drain(..end).collect();
+            events.extend(self.decode_block(&block));
+        }
+        events
+    }
+
+    /// Flush a trailing event that was not terminated by a blank line.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let block = std::mem::take(&mut self.buffer);
+        let event = self.decode_block(&block);
+        if self.skipped() > 0 {
+            warn!("Skipped {} undecodable SSE event(s) in this stream", self.skipped());
+        }
+        event
+    }
+
+    /// Number of events dropped because they were not valid UTF-8.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Offset just past the first blank line (`\n\n` or `\r\n\r\n`), if any.
+    fn block_end(&self) -> Option<usize> {
+        let mut line_start = 0;
+        while let Some(offset) = self.buffer[line_start..].iter().position(|&b| b == b'\n') {
+            let newline = line_start + offset;
+            if matches!(&self.buffer[line_start..newline], b"" | b"\r") && line_start > 0 {
+                return Some(newline + 1);
+            }
+            line_start = newline + 1;
+        }
+        None
+    }
+
+    fn decode_block(&mut self, block: &[u8]) -> Option<SseEvent> {
+        match std::str::from_utf8(block) {
+            Ok(text) => parse_block(&text.replace("\r\n", "\n")),
+            Err(e) => {
+                self.skipped += 1;
+                warn!("Skipping SSE event that is not valid UTF-8 ({} bytes): {}", block.len(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Parse one event, or `None` for keepalives: blocks of only `:` comment
+/// lines and `ping` events. Proxies and Anthropic send these to hold idle
+/// connections open; they carry nothing for the parsers.
+fn parse_block(block: &str) -> Option<SseEvent> {
+    let mut event = None;
+    let mut data: Option<String> = None;
+    for line in block.lines() {
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event = Some(value.to_string()),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            // Comments, `id:` and `retry:` carry nothing we use
+            _ => {}
+        }
+    }
+    if event.as_deref() == Some("ping") {
+        return None;
+    }
+    data.map(|data| SseEvent { event, data })
+}
+
+/// Decode a response byte stream into SSE events.
+pub(crate) fn decode<S, B>(bytes: S) -> impl Stream<Item = Result<SseEvent, AegisError>> + Send
+where
+    S: Stream<Item = Result<B, reqwest::Error>> + Send + 'static,
+    B: AsRef<[u8]>,
+{
+    let state = (Box::pin(bytes), SseDecoder::default(), VecDeque::new(), false);
+    stream::unfold(state, |(mut bytes, mut decoder, mut pending, mut done)| async move {
+        loop {
+            if let Some(event) = pending.pop_front() {
+                return Some((Ok(event), (bytes, decoder, pending, done)));
+            }
+            if done {
+                return None;
+            }
+            match bytes.next().await {
+                Some(Ok(chunk)) => pending.extend(decoder.push(chunk.as_ref())),
+                Some(Err(e)) => {
+                    done = true;
+                    return Some((Err(AegisError::from(e)), (bytes, decoder, pending, done)));
+                }
+                None => {
+                    done = true;
+                    pending.extend(decoder.finish());
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multibyte_characters_split_across_chunks_are_kept() {
+        let mut decoder = SseDecoder::default();
+        let bytes = "data: Hà Nội\n\n".as_bytes();
+        // Split inside the two-byte "à"
+        let split = bytes.iter().position(|&b| b == 0xC3).unwrap() + 1;
+
+        assert!(decoder.push(&bytes[..split]).is_empty());
+        let events = decoder.push(&bytes[split..]);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "Hà Nội");
+    }
+
+    #[test]
+    fn invalid_utf8_events_are_skipped() {
+        let mut decoder = SseDecoder::default();
+        let mut bytes = b"event: delta\r\ndata: one\r\n\r\n".to_vec();
+        bytes.extend_from_slice(b"data: \xff\xfe\x00garbage\n\n");
+        bytes.extend_from_slice(b"data: two\n\n");
+
+        let events = decoder.push(&bytes);
+
+        let data: Vec<_> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["one", "two"]);
+        assert_eq!(events[0].event.as_deref(), Some("delta"));
+        assert_eq!(decoder.skipped(), 1);
+    }
+
+    #[test]
+    fn keepalives_are_dropped() {
+        let mut decoder = SseDecoder::default();
+        let bytes = b": keepalive\n\nevent: ping\ndata: {\"type\": \"ping\"}\n\n:\n\ndata: one\n\n";
+
+        let events = decoder.push(bytes);
+
+        assert_eq!(events, vec![SseEvent { event: None, data: "one".to_string() }]);
+    }
+}
@@ -0,0 +1,625 @@
+//! Helpers for working with streamed responses.
+
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{
+    error::AegisError,
+    models::{Content, ContentPart, FinishReason, Message, Metadata, Role, Usage},
+};
+
+/// The boxed stream of message deltas returned by `Provider::stream_message`.
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<Message, AegisError>> + Send>>;
+
+/// Undecoded response body chunks, as returned by `Aegis::stream_message_raw`.
+pub type RawStream = Pin<Box<dyn Stream<Item = Result<Bytes, AegisError>> + Send>>;
+
+/// Pass a response body through chunk by chunk, exactly as received.
+pub(crate) fn raw(response: reqwest::Response) -> RawStream {
+    Box::pin(response.bytes_stream().map_err(AegisError::from))
+}
+
+/// Turn a complete `send_message` result into a one-item stream, so code
+/// written against streams can accept non-streaming completions too.
+pub fn into_stream(result: Result<Message, AegisError>) -> MessageStream {
+    Box::pin(stream::once(future::ready(result)))
+}
+
+/// End `stream` with [`AegisError::StreamIdle`] once `idle` passes without a
+/// delta, counting from when it is first polled. Dropping the inner stream
+/// closes its connection.
+pub(crate) fn idle_timeout(stream: MessageStream, idle: Duration) -> MessageStream {
+    Box::pin(stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        match tokio::time::timeout(idle, stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            Err(_) => Some((Err(AegisError::StreamIdle(idle)), None)),
+        }
+    }))
+}
+
+/// End `stream` with [`AegisError::Timeout`] if it is still going at
+/// `deadline` (see `SendOptions::with_deadline`). Dropping the inner stream
+/// closes its connection.
+pub(crate) fn deadline(stream: MessageStream, deadline: Instant) -> MessageStream {
+    Box::pin(stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        match tokio::time::timeout_at(deadline.into(), stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            Err(_) => Some((Err(AegisError::Timeout), None)),
+        }
+    }))
+}
+
+/// Deltas buffered per subscriber of a shared stream before the slowest one
+/// starts missing them.
+const SHARED_STREAM_CAPACITY: usize = 256;
+
+/// An item of a stream shared with `Aegis::stream_shared`. Every subscriber
+/// sees the same error, so it is reference-counted rather than cloned.
+pub type SharedStreamItem = Result<Message, Arc<AegisError>>;
+
+/// Drive `stream` on a background task and broadcast each item.
+///
+/// The returned receiver sees everything; receivers made later with
+/// `resubscribe` see items from that point on. Once every receiver is
+/// dropped the upstream stream is dropped too, closing its connection.
+pub(crate) fn broadcast(mut stream: MessageStream) -> broadcast::Receiver<SharedStreamItem> {
+    let (sender, receiver) = broadcast::channel(SHARED_STREAM_CAPACITY);
+    tokio::spawn(async move {
+        while let Some(item) = stream.next().await {
+            if sender.send(item.map_err(Arc::new)).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// One item of a recorded stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Delta(Message),
+    /// An error, kept as its [`AegisError::kind`] and message since errors
+    /// themselves can't be serialized.
+    Error { kind: String, message: String },
+}
+
+/// Everything a stream yielded, in order, as captured by [`record_stream`].
+/// Serialize it to keep as a fixture and replay it with
+/// [`replay`](Self::replay) or `MockProvider::with_recording`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamRecording {
+    pub events: Vec<RecordedEvent>,
+}
+
+impl StreamRecording {
+    /// Yield the recorded events again. A recorded error is rebuilt as the
+    /// variant it was recorded from, and ends the stream as it did the
+    /// original. Errors whose details aren't in their message, such as a
+    /// network error, come back as `AegisError::APIError` carrying it.
+    pub fn replay(&self) -> MessageStream {
+        let items: Vec<_> = self
+            .events
+            .iter()
+            .map(|event| match event {
+                RecordedEvent::Delta(message) => Ok(message.clone()),
+                RecordedEvent::Error { kind, message } => Err(rebuild_error(kind, message)),
+            })
+            .collect();
+        Box::pin(stream::iter(items))
+    }
+}
+
+/// The error recorded as `kind` with the display text `message`.
+fn rebuild_error(kind: &str, message: &str) -> AegisError {
+    // The text after the prefix `variant` puts in front of its string.
+    let text = |variant: fn(String) -> AegisError| {
+        let prefix = variant(String::new()).to_string();
+        variant(message.strip_prefix(prefix.as_str()).unwrap_or(message).to_string())
+    };
+    let rebuilt = match kind {
+        "provider_not_found" => Some(AegisError::ProviderNotFound),
+        "invalid_api_key" => Some(AegisError::InvalidAPIKey),
+        "empty_response" => Some(AegisError::EmptyResponse),
+        "cancelled" => Some(AegisError::Cancelled),
+        "timeout" => Some(AegisError::Timeout),
+        "rate_limit_exceeded" => Some(AegisError::RateLimitExceeded { retry_after: None }),
+        "api_error" => Some(text(AegisError::APIError)),
+        "incomplete_response" => Some(text(AegisError::IncompleteResponse)),
+        "invalid_image" => Some(text(AegisError::InvalidImage)),
+        "content_flagged" => Some(text(AegisError::ContentFlagged)),
+        "conversation_not_found" => Some(text(AegisError::ConversationNotFound)),
+        "unsupported" => Some(text(AegisError::Unsupported)),
+        "server_error" => message
+            .strip_prefix("Server error (status ")
+            .and_then(|rest| rest.split_once("): "))
+            .and_then(|(status, body)| {
+                Some(AegisError::ServerError(status.parse().ok()?, body.to_string()))
+            }),
+        "stream_idle" => message
+            .strip_prefix("No stream data for ")
+            .and_then(parse_duration)
+            .map(AegisError::StreamIdle),
+        "budget_exceeded" => message
+            .strip_prefix("Spent $")
+            .and_then(|rest| rest.strip_suffix(" budget"))
+            .and_then(|rest| rest.split_once(" of a $"))
+            .and_then(|(spent, budget)| {
+                Some(AegisError::BudgetExceeded {
+                    spent_usd: spent.parse().ok()?,
+                    budget_usd: budget.parse().ok()?,
+                })
+            }),
+        _ => None,
+    };
+    rebuilt.unwrap_or_else(|| AegisError::APIError(message.to_string()))
+}
+
+/// A duration in the `Debug` form of [`Duration`], e.g. `30s` or `1.5ms`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    [("ns", 1e-9), ("µs", 1e-6), ("ms", 1e-3), ("s", 1.0)]
+        .into_iter()
+        .find_map(|(unit, scale)| {
+            let value: f64 = text.strip_suffix(unit)?.parse().ok()?;
+            Some(Duration::from_secs_f64(value * scale))
+        })
+}
+
+/// Handle to the recording [`record_stream`] fills in as its stream is read.
+#[derive(Debug, Clone, Default)]
+pub struct StreamRecorder(Arc<Mutex<StreamRecording>>);
+
+impl StreamRecorder {
+    /// The events recorded so far; all of them once the stream has ended.
+    pub fn recording(&self) -> StreamRecording {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Pass `stream` through unchanged while recording every delta and error,
+/// e.g. to capture a live response as a replayable fixture.
+pub fn record_stream(stream: MessageStream) -> (MessageStream, StreamRecorder) {
+    let recorder = StreamRecorder::default();
+    let log = Arc::clone(&recorder.0);
+    let stream = stream.inspect(move |item| {
+        let event = match item {
+            Ok(message) => RecordedEvent::Delta(message.clone()),
+            Err(e) => RecordedEvent::Error {
+                kind: e.kind().to_string(),
+                message: e.to_string(),
+            },
+        };
+        log.lock().unwrap().events.push(event);
+    });
+    (Box::pin(stream), recorder)
+}
+
+/// The stream returned by [`stream_partial_json`].
+pub type PartialJsonStream =
+    Pin<Box<dyn Stream<Item = Result<Option<Value>, AegisError>> + Send>>;
+
+/// Parse a JSON-mode response as it streams in.
+///
+/// After each delta, yields a best-effort parse of the text received so far
+/// (see [`parse_partial_json`]), or `None` while it can't be read as JSON
+/// yet. Errors from `stream` pass through.
+pub fn stream_partial_json(stream: MessageStream) -> PartialJsonStream {
+    Box::pin(stream.scan(StreamAccumulator::new(), |accumulator, delta| {
+        let item = delta.map(|delta| {
+            accumulator.push(&delta);
+            parse_partial_json(&accumulator.text())
+        });
+        future::ready(Some(item))
+    }))
+}
+
+/// Leniently parse the start of a JSON document, closing the open string
+/// and brackets. A trailing key, or a value too short to read such as `tr`,
+/// is dropped along with its preceding comma. `None` if nothing can be made
+/// of the text, e.g. it is empty or not JSON at all.
+pub fn parse_partial_json(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    // Places the text can be cut back to if it won't parse as it stands:
+    // before each comma and after each opening bracket.
+    let mut cuts = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => cuts.push(i + 1),
+            ',' => cuts.push(i),
+            _ => {}
+        }
+    }
+
+    if let Ok(value) = serde_json::from_str(&close_json(text)?) {
+        return Some(value);
+    }
+    cuts.into_iter()
+        .rev()
+        .find_map(|cut| serde_json::from_str(&close_json(&text[..cut])?).ok())
+}
+
+/// `text` with its open string and brackets closed, or `None` if it closes
+/// a bracket it never opened.
+fn close_json(text: &str) -> Option<String> {
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => open.push('}'),
+            '[' => open.push(']'),
+            '}' | ']' if open.pop() != Some(c) => return None,
+            _ => {}
+        }
+    }
+
+    let mut closed = text.to_string();
+    if in_string {
+        if escaped {
+            closed.pop();
+        }
+        closed.push('"');
+    }
+    closed.extend(open.iter().rev());
+    Some(closed)
+}
+
+/// Folds streamed deltas into the complete assistant message.
+///
+/// Consecutive text deltas are joined into one text part, keeping their
+/// annotations, and likewise reasoning deltas; other parts are kept in
+/// arrival order. Metadata fields from later deltas replace earlier ones, so
+/// usage reported on the final chunk wins.
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    parts: Vec<ContentPart>,
+    metadata: Option<Metadata>,
+    /// When the first content arrived, for time-to-first-token.
+    first_content_at: Option<Instant>,
+}
+
+/// Everything collected from a finished stream, as returned by
+/// `Aegis::complete_streaming`.
+#[derive(Debug, Clone)]
+pub struct CompletionResult {
+    pub message: Message,
+    pub usage: Option<Usage>,
+    pub finish_reason: Option<FinishReason>,
+    pub model: Option<String>,
+    /// Time from the request to the first content delta; `None` if the
+    /// stream carried no content.
+    pub ttft: Option<Duration>,
+    pub total_duration: Duration,
+}
+
+impl StreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, delta: &Message) {
+        if self.first_content_at.is_none() && !delta.content.parts.is_empty() {
+            self.first_content_at = Some(Instant::now());
+        }
+        for part in &delta.content.parts {
+            match (part, self.parts.last_mut()) {
+                (
+                    ContentPart::Text { text, annotations, .. },
+                    Some(ContentPart::Text {
+                        text: acc,
+                        annotations: acc_annotations,
+                        ..
+                    }),
+                ) => {
+                    acc.push_str(text);
+                    acc_annotations.extend(annotations.iter().cloned());
+                }
+                (ContentPart::Reasoning { text }, Some(ContentPart::Reasoning { text: acc })) => {
+                    acc.push_str(text);
+                }
+                (part, _) => self.parts.push(part.clone()),
+            }
+        }
+        if let Some(update) = &delta.metadata {
+            let metadata = self.metadata.get_or_insert_with(Metadata::default);
+            if update.model.is_some() {
+                metadata.model = update.model.clone();
+            }
+            if update.provider.is_some() {
+                metadata.provider = update.provider.clone();
+            }
+            if update.usage.is_some() {
+                metadata.usage = update.usage.clone();
+            }
+            if update.response_id.is_some() {
+                metadata.response_id = update.response_id.clone();
+            }
+            if update.finish_reason.is_some() {
+                metadata.finish_reason = update.finish_reason.clone();
+            }
+            if update.stop_sequence.is_some() {
+                metadata.stop_sequence = update.stop_sequence.clone();
+            }
+            if update.system_fingerprint.is_some() {
+                metadata.system_fingerprint = update.system_fingerprint.clone();
+            }
+        }
+    }
+
+    /// Text received so far.
+    pub fn text(&self) -> String {
+        Content {
+            parts: self.parts.clone(),
+        }
+        .to_string()
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn into_message(self) -> Message {
+        Message {
+            role: Role::Assistant,
+            content: Content { parts: self.parts },
+            metadata: self.metadata,
+        }
+    }
+
+    /// The complete message with its metadata pulled out, timed from
+    /// `started`, which should be when the request was sent.
+    pub fn into_result(self, started: Instant) -> CompletionResult {
+        let ttft = self.first_content_at.map(|at| at.duration_since(started));
+        let message = self.into_message();
+        let metadata = message.metadata.clone().unwrap_or_default();
+        CompletionResult {
+            usage: metadata.usage,
+            finish_reason: metadata.finish_reason,
+            model: metadata.model,
+            ttft,
+            total_duration: started.elapsed(),
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use std::{
+        io,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::AsyncWrite;
+
+    use super::*;
+    use crate::{
+        config::AegisConfig,
+        models::{ProviderType, Usage},
+        providers::testing::ScriptedProvider,
+        Aegis,
+    };
+
+    struct BrokenWriter;
+
+    impl AsyncWrite for BrokenWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, _: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed")))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn scripted_aegis(chunks: &[&str]) -> Aegis {
+        Aegis::with_providers(
+            vec![Arc::new(ScriptedProvider::new(ProviderType::Anthropic, chunks))],
+            AegisConfig::new(),
+        )
+    }
+
+    fn delta(text: &str) -> Message {
+        Message::text(Role::Assistant, text)
+    }
+
+    #[tokio::test]
+    async fn into_stream_yields_single_item() {
+        let message = delta("Hanoi");
+
+        let items: Vec<_> = into_stream(Ok(message)).collect().await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].as_ref().unwrap().content.to_string(), "Hanoi");
+
+        let rate_limited = AegisError::RateLimitExceeded { retry_after: None };
+        let items: Vec<_> = into_stream(Err(rate_limited)).collect().await;
+        assert!(matches!(items[..], [Err(AegisError::RateLimitExceeded { .. })]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_stream_ends_after_the_idle_timeout() {
+        let stalled: MessageStream =
+            Box::pin(stream::iter([Ok(delta("Hanoi"))]).chain(stream::pending()));
+        let started = tokio::time::Instant::now();
+
+        let items: Vec<_> = idle_timeout(stalled, Duration::from_secs(30)).collect().await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap().content.to_string(), "Hanoi");
+        assert!(matches!(items[1], Err(AegisError::StreamIdle(idle)) if idle.as_secs() == 30));
+        assert_eq!(started.elapsed(), Duration::from_secs(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_still_going_at_the_deadline_ends_with_a_timeout() {
+        let stalled: MessageStream =
+            Box::pin(stream::iter([Ok(delta("Hanoi"))]).chain(stream::pending()));
+        let started = tokio::time::Instant::now();
+
+        let items: Vec<_> =
+            deadline(stalled, Instant::now() + Duration::from_secs(60)).collect().await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap().content.to_string(), "Hanoi");
+        assert!(matches!(items[1], Err(AegisError::Timeout)));
+        assert_eq!(started.elapsed().as_secs(), 60);
+    }
+
+    #[test]
+    fn accumulator_joins_text_and_keeps_final_usage() {
+        let mut accumulator = StreamAccumulator::new();
+        accumulator.push(&delta("The capital"));
+        accumulator.push(&delta(" is Hanoi."));
+        accumulator.push(&Message {
+            role: Role::Assistant,
+            content: Content { parts: Vec::new() },
+            metadata: Some(Metadata {
+                model: Some("claude-3-sonnet-20240229".to_string()),
+                provider: None,
+                usage: Some(Usage {
+                    prompt_tokens: 10,
+                    completion_tokens: 5,
+                    total_tokens: 15,
+                    ..Usage::default()
+                }),
+                response_id: None,
+                finish_reason: None,
+                stop_sequence: None,
+                system_fingerprint: None,
+                warnings: Vec::new(),
+            }),
+        });
+
+        assert_eq!(accumulator.text(), "The capital is Hanoi.");
+        let message = accumulator.into_message();
+        assert_eq!(message.content.parts.len(), 1);
+        assert_eq!(message.metadata.unwrap().usage.unwrap().total_tokens, 15);
+    }
+
+    #[test]
+    fn partial_json_closes_what_is_open() {
+        let parse = |text| parse_partial_json(text).map(|v| v.to_string());
+
+        assert_eq!(parse(r#"{"city": "Ha"#).unwrap(), r#"{"city":"Ha"}"#);
+        assert_eq!(parse(r#"{"city": "Hanoi", "popu"#).unwrap(), r#"{"city":"Hanoi"}"#);
+        assert_eq!(parse(r#"{"city": "Hanoi", "capital": tr"#).unwrap(), r#"{"city":"Hanoi"}"#);
+        assert_eq!(parse(r#"{"tags": ["a", "b\"#).unwrap(), r#"{"tags":["a","b"]}"#);
+        assert_eq!(parse(r#"[{"a": 1}, {"#).unwrap(), r#"[{"a":1},{}]"#);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("Sure! Here"), None);
+        assert_eq!(parse(r#"{"a": 1}}"#), None);
+    }
+
+    #[tokio::test]
+    async fn partial_json_grows_with_the_stream() {
+        let chunks = [
+            r#"{"ci"#,
+            r#"ty": "Ha"#,
+            r#"noi", "population": 8"#,
+            r#"053663, "tags": ["capi"#,
+            r#"tal"]}"#,
+        ];
+        let stream: MessageStream = Box::pin(stream::iter(chunks.map(|c| Ok(delta(c)))));
+
+        let values: Vec<_> = stream_partial_json(stream)
+            .map(|item| item.unwrap().unwrap().to_string())
+            .collect()
+            .await;
+
+        assert_eq!(
+            values,
+            [
+                r#"{}"#,
+                r#"{"city":"Ha"}"#,
+                r#"{"city":"Hanoi","population":8}"#,
+                r#"{"city":"Hanoi","population":8053663,"tags":["capi"]}"#,
+                r#"{"city":"Hanoi","population":8053663,"tags":["capital"]}"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_streaming_aggregates_the_stream() {
+        let aegis = scripted_aegis(&["The capital", " is Hanoi."]);
+
+        let result = aegis
+            .complete_streaming(ProviderType::Anthropic, vec![delta("Capital?")])
+            .await
+            .unwrap();
+
+        assert_eq!(result.message.content.to_string(), "The capital is Hanoi.");
+        assert!(result.usage.is_none());
+        assert!(result.ttft.unwrap() <= result.total_duration);
+    }
+
+    #[tokio::test]
+    async fn stream_to_writer_writes_every_delta() {
+        let aegis = scripted_aegis(&["The capital", " is Hanoi."]);
+        let mut output = Vec::new();
+
+        aegis
+            .stream_to_writer(ProviderType::Anthropic, vec![delta("Capital?")], &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "The capital is Hanoi.");
+    }
+
+    #[tokio::test]
+    async fn stream_to_writer_surfaces_write_errors() {
+        let aegis = scripted_aegis(&["Hanoi"]);
+
+        let result = aegis
+            .stream_to_writer(ProviderType::Anthropic, vec![delta("Capital?")], BrokenWriter)
+            .await;
+
+        assert!(matches!(result, Err(AegisError::IoError(_))));
+    }
+}
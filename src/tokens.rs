@@ -0,0 +1,196 @@
+//! Local token counting for prompts, where the provider's tokenizer is public.
+//!
+//! Only OpenAI models are supported, using the same BPE encodings as the API.
+//! Counts follow OpenAI's chat accounting (a fixed overhead per message plus
+//! the reply primer) and are estimates for anything beyond plain text.
+//!
+//! For every other provider, [`estimate_tokens`] gives a rough figure from
+//! character counts alone. [`estimate_usage`] combines the two to stand in
+//! for usage a provider didn't report.
+
+use tiktoken_rs::{tokenizer::Tokenizer, CoreBPE};
+
+use crate::{
+    error::AegisError,
+    models::{Content, ContentPart, Message, ProviderType, Usage},
+};
+
+/// Tokens added around every message by the chat format.
+const TOKENS_PER_MESSAGE: u32 = 3;
+/// Tokens that prime the assistant's reply.
+const REPLY_PRIMER_TOKENS: u32 = 3;
+/// Cost of a low-detail image; high-detail images cost more.
+const IMAGE_TOKENS: u32 = 85;
+/// Characters per token assumed by the estimate; about right for English.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Count the prompt tokens `messages` will use on `model`.
+///
+/// Returns [`AegisError::Unsupported`] for providers without a public
+/// tokenizer and for unrecognised OpenAI models.
+pub fn count_tokens(
+    provider_type: &ProviderType,
+    model: &str,
+    messages: &[Message],
+) -> Result<u32, AegisError> {
+    with_bpe(provider_type, model, |bpe| {
+        let total = messages
+            .iter()
+            .map(|message| {
+                TOKENS_PER_MESSAGE
+                    + count(bpe, message.role.as_str())
+                    + content_tokens(bpe, &message.content)
+            })
+            .sum::<u32>();
+        total + REPLY_PRIMER_TOKENS
+    })
+}
+
+/// Usage for a reply whose provider didn't report any, marked `estimated`.
+///
+/// The prompt is counted as [`count_tokens`] does and the reply's content
+/// with the same tokenizer; where there is none (or `model` is unknown),
+/// both fall back to [`estimate_tokens`].
+pub fn estimate_usage(
+    provider_type: &ProviderType,
+    model: Option<&str>,
+    prompt: &[Message],
+    reply: &Message,
+) -> Usage {
+    let counted = model.and_then(|model| {
+        let prompt_tokens = count_tokens(provider_type, model, prompt).ok()?;
+        let completion_tokens = with_bpe(provider_type, model, |bpe| {
+            content_tokens(bpe, &reply.content)
+        })
+        .ok()?;
+        Some((prompt_tokens, completion_tokens))
+    });
+    let (prompt_tokens, completion_tokens) = counted.unwrap_or_else(|| {
+        (
+            estimate_tokens(prompt) as u32,
+            reply.estimate_tokens() as u32,
+        )
+    });
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        estimated: true,
+        ..Usage::default()
+    }
+}
+
+/// Run `f` with the BPE encoding `model` uses.
+fn with_bpe<R>(
+    provider_type: &ProviderType,
+    model: &str,
+    f: impl FnOnce(&CoreBPE) -> R,
+) -> Result<R, AegisError> {
+    if *provider_type != ProviderType::OpenAI {
+        return Err(AegisError::Unsupported(format!(
+            "token counting is not available for {:?}",
+            provider_type
+        )));
+    }
+    let tokenizer = tiktoken_rs::tokenizer::get_tokenizer(model)
+        .or_else(|| model.starts_with("o3").then_some(Tokenizer::O200kBase))
+        .ok_or_else(|| {
+            AegisError::Unsupported(format!("no known tokenizer for model {}", model))
+        })?;
+    let bpe = match tokenizer {
+        Tokenizer::O200kBase => tiktoken_rs::o200k_base_singleton(),
+        _ => tiktoken_rs::cl100k_base_singleton(),
+    };
+    let bpe = bpe.lock();
+    Ok(f(&bpe))
+}
+
+fn count(bpe: &CoreBPE, text: &str) -> u32 {
+    bpe.encode_with_special_tokens(text).len() as u32
+}
+
+fn content_tokens(bpe: &CoreBPE, content: &Content) -> u32 {
+    content
+        .parts
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text { text, .. } => count(bpe, text),
+            ContentPart::Image { .. } => IMAGE_TOKENS,
+            ContentPart::ToolCall(call) => {
+                count(bpe, &call.name) + count(bpe, &call.arguments.to_string())
+            }
+            ContentPart::ToolResult { content, .. } => count(bpe, content),
+            // Not sent back to the provider
+            ContentPart::Reasoning { .. } => 0,
+        })
+        .sum::<u32>()
+}
+
+/// Approximate prompt tokens for `messages` at four characters per token,
+/// with a flat cost per image. No tokenizer involved, so it works for any
+/// provider but can be off by a fair margin, especially for code and
+/// non-English text. Prefer [`count_tokens`] where it is supported.
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    messages.iter().map(Message::estimate_tokens).sum()
+}
+
+impl Message {
+    /// Approximate tokens in this message; see [`estimate_tokens`].
+    pub fn estimate_tokens(&self) -> usize {
+        let chars = |text: &str| text.chars().count().div_ceil(CHARS_PER_TOKEN);
+        self.content
+            .parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text, .. } => chars(text),
+                ContentPart::Image { .. } => IMAGE_TOKENS as usize,
+                ContentPart::ToolCall(call) => {
+                    chars(&call.name) + chars(&call.arguments.to_string())
+                }
+                ContentPart::ToolResult { content, .. } => chars(content),
+                ContentPart::Reasoning { .. } => 0,
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+
+    #[test]
+    fn counts_openai_chat_messages() {
+        let messages = vec![
+            Message::text(Role::System, "You are a helpful assistant."),
+            Message::text(Role::User, "What is the capital of Vietnam?"),
+        ];
+
+        let tokens = count_tokens(&ProviderType::OpenAI, "gpt-4o", &messages).unwrap();
+
+        assert_eq!(tokens, 24);
+    }
+
+    #[test]
+    fn estimate_counts_four_characters_per_token() {
+        let mut messages = vec![Message::text(Role::User, "What is the capital of Vietnam?")];
+        assert_eq!(messages[0].estimate_tokens(), 8);
+
+        messages.push(Message::text(Role::User, "Hi"));
+        messages[1].content.parts.push(ContentPart::image("https://example.com/map.png"));
+        assert_eq!(estimate_tokens(&messages), 8 + 1 + 85);
+    }
+
+    #[test]
+    fn other_providers_are_unsupported() {
+        let messages = vec![Message::text(Role::User, "Hello")];
+
+        let result = count_tokens(
+            &ProviderType::Anthropic,
+            "claude-3-sonnet-20240229",
+            &messages,
+        );
+
+        assert!(matches!(result, Err(AegisError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,118 @@
+//! Lookups over a conversation's history, for code that processes logs or
+//! trims context. Import [`MessagesExt`] to use them on any `&[Message]`.
+
+use crate::models::{ContentPart, Message, Role, ToolCall};
+
+pub trait MessagesExt {
+    /// The messages sent as `role`, in order.
+    fn filter_by_role(&self, role: Role) -> impl Iterator<Item = &Message>;
+
+    /// The most recent assistant reply.
+    fn last_assistant_message(&self) -> Option<&Message>;
+
+    /// The text of every message, one per line. Messages with no text, such
+    /// as bare tool calls, are skipped.
+    fn concat_text(&self) -> String;
+
+    /// Every tool call the model made, in order.
+    fn find_tool_calls(&self) -> Vec<&ToolCall>;
+}
+
+impl MessagesExt for [Message] {
+    fn filter_by_role(&self, role: Role) -> impl Iterator<Item = &Message> {
+        self.iter().filter(move |message| message.role == role)
+    }
+
+    fn last_assistant_message(&self) -> Option<&Message> {
+        self.iter()
+            .rev()
+            .find(|message| matches!(message.role, Role::Assistant))
+    }
+
+    fn concat_text(&self) -> String {
+        self.iter()
+            .map(|message| message.content.to_string())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn find_tool_calls(&self) -> Vec<&ToolCall> {
+        self.iter()
+            .flat_map(|message| &message.content.parts)
+            .filter_map(|part| match part {
+                ContentPart::ToolCall(call) => Some(call),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation() -> Vec<Message> {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            arguments: serde_json::json!({ "location": "Hanoi" }),
+        };
+        vec![
+            Message::text(Role::System, "Be brief."),
+            Message::user("Weather in Hanoi?"),
+            Message::new(Role::Assistant, vec![ContentPart::ToolCall(call)]),
+            Message::new(
+                Role::Tool,
+                vec![ContentPart::ToolResult {
+                    tool_call_id: "call_1".to_string(),
+                    content: "31°C".to_string(),
+                }],
+            ),
+            Message::text(Role::Assistant, "31°C and sunny."),
+            Message::user("Thanks!"),
+        ]
+    }
+
+    #[test]
+    fn filter_by_role_keeps_order() {
+        let messages = conversation();
+
+        let texts: Vec<_> = messages
+            .filter_by_role(Role::User)
+            .map(|m| m.content.to_string())
+            .collect();
+
+        assert_eq!(texts, ["Weather in Hanoi?", "Thanks!"]);
+        assert_eq!(messages.filter_by_role(Role::Tool).count(), 1);
+    }
+
+    #[test]
+    fn last_assistant_message_skips_later_turns() {
+        let messages = conversation();
+
+        let last = messages.last_assistant_message().unwrap();
+
+        assert_eq!(last.content.to_string(), "31°C and sunny.");
+        assert!(messages[..2].last_assistant_message().is_none());
+    }
+
+    #[test]
+    fn concat_text_skips_messages_without_text() {
+        assert_eq!(
+            conversation().concat_text(),
+            "Be brief.\nWeather in Hanoi?\n31°C and sunny.\nThanks!"
+        );
+    }
+
+    #[test]
+    fn find_tool_calls_collects_every_call() {
+        let messages = conversation();
+
+        let calls = messages.find_tool_calls();
+
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "get_weather");
+        assert!(messages[..2].find_tool_calls().is_empty());
+    }
+}
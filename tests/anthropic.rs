@@ -0,0 +1,500 @@
+mod common;
+
+use aegis::{
+    config::AegisConfig,
+    error::AegisError,
+    models::{Citation, ContentPart, FinishReason, ProviderType, Role},
+    options::SendOptions,
+    providers::{anthropic::AnthropicProvider, Provider},
+    stream::StreamAccumulator,
+    Aegis,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const ENDPOINT: &str = "/v1/messages";
+
+fn provider(server: &MockServer) -> AnthropicProvider {
+    AnthropicProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_response_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("x-api-key", "test-key"))
+        .and(header("anthropic-version", "2023-06-01"))
+        .respond_with(common::load("anthropic/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(message.role, Role::Assistant));
+    assert_eq!(common::text_parts(&message), vec!["The capital of Vietnam is Hanoi."]);
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("anthropic"));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 14);
+    assert_eq!(usage.completion_tokens, 10);
+    assert_eq!(usage.total_tokens, 24);
+}
+
+#[tokio::test]
+async fn configured_api_version_is_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("anthropic-version", "2024-10-22"))
+        .respond_with(common::load("anthropic/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    provider(&server)
+        .with_api_version("2024-10-22")
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn generation_params_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "temperature": 0.0,
+            "max_tokens": 256,
+            "top_p": 0.9,
+            "stop_sequences": ["END"]
+        })))
+        .respond_with(common::load("anthropic/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_temperature(0.0)
+        .with_max_tokens(256)
+        .with_top_p(0.9)
+        .with_stop_sequences(vec!["END".to_string()]);
+    provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn echoed_model_wins_over_requested_alias() {
+    let server = common::serve(ENDPOINT, "anthropic/resolved_model").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::new().with_model("claude-3-5-sonnet-latest"),
+        )
+        .await
+        .unwrap();
+
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.model.as_deref(), Some("claude-3-5-sonnet-20241022"));
+}
+
+#[tokio::test]
+async fn rate_limit_headers_are_exposed() {
+    let server = common::serve(ENDPOINT, "anthropic/text").await;
+    let provider = provider(&server);
+    assert!(provider.last_rate_limit_status().is_none());
+
+    provider
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap();
+
+    let status = provider.last_rate_limit_status().unwrap();
+    assert_eq!(status.requests_limit, Some(50));
+    assert_eq!(status.requests_remaining, Some(49));
+    assert_eq!(status.tokens_remaining, Some(39976));
+    assert_eq!(status.tokens_reset.as_deref(), Some("2024-06-10T12:00:02Z"));
+}
+
+#[tokio::test]
+async fn tool_use_response_keeps_text_blocks() {
+    let server = common::serve(ENDPOINT, "anthropic/tool_use").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What's the weather in Hanoi?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(common::text_parts(&message), vec!["Let me check the weather in Hanoi."]);
+    let calls = common::tool_calls(&message);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "toolu_01A09q90qw90lq917835lq9");
+    assert_eq!(calls[0].name, "get_weather");
+    assert_eq!(
+        calls[0].arguments,
+        serde_json::json!({ "location": "Hanoi, Vietnam" })
+    );
+}
+
+#[tokio::test]
+async fn image_output_round_trips_with_text() {
+    let server = common::serve(ENDPOINT, "anthropic/image_output").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("Draw a red lantern")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(common::text_parts(&message), vec!["Here is a red lantern:"]);
+    let images = common::image_parts(&message);
+    assert_eq!(images.len(), 1);
+    assert!(images[0].starts_with("data:image/png;base64,iVBORw0KGgo"));
+}
+
+#[tokio::test]
+async fn vision_request_returns_text_description() {
+    let server = common::serve(ENDPOINT, "anthropic/vision").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::vision_message(
+                "Describe this image.",
+                "https://example.com/lantern.png",
+            )],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(common::text_parts(&message), vec!["The image shows a red lantern hanging over a street in Hoi An."]);
+}
+
+/// The signature and header of a PNG, enough to be recognised as one.
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+#[tokio::test]
+async fn remote_images_are_inlined_once() {
+    let images = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/lantern.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(PNG, "application/octet-stream"))
+        .expect(1)
+        .mount(&images)
+        .await;
+    let server = common::serve(ENDPOINT, "anthropic/vision").await;
+    let url = format!("{}/lantern.png", images.uri());
+    let mut message = common::vision_message("Are these the same lantern?", &url);
+    message.content.parts.push(ContentPart::image(&url));
+
+    provider(&server)
+        .with_inline_images(true)
+        .send_message(vec![message], &SendOptions::default())
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    let source = &body["messages"][0]["content"][1]["source"];
+    assert_eq!(source["type"], "base64");
+    assert_eq!(source["media_type"], "image/png");
+    assert_eq!(source["data"], STANDARD.encode(PNG));
+    assert_eq!(body["messages"][0]["content"][2]["source"], *source);
+}
+
+#[tokio::test]
+async fn inlining_rejects_non_image_content() {
+    let pages = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("<html></html>", "text/html"))
+        .mount(&pages)
+        .await;
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(common::load("anthropic/vision").response())
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let result = provider(&server)
+        .with_inline_images(true)
+        .send_message(
+            vec![common::vision_message("Describe this image.", &pages.uri())],
+            &SendOptions::default(),
+        )
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidImage(reason)) if reason.contains("text/html")));
+}
+
+#[tokio::test]
+async fn rate_limited_status_maps_to_rate_limit_error() {
+    let server = common::serve(ENDPOINT, "anthropic/rate_limited").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::RateLimitExceeded { .. })));
+}
+
+#[tokio::test]
+async fn unauthorized_status_maps_to_invalid_api_key() {
+    let server = common::serve(ENDPOINT, "anthropic/unauthorized").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn truncated_body_is_reported_as_incomplete() {
+    let server = common::serve(ENDPOINT, "anthropic/truncated").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    match result {
+        Err(e @ AegisError::IncompleteResponse(_)) => assert!(e.is_retryable()),
+        other => panic!("expected IncompleteResponse, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn empty_content_is_a_retryable_empty_response() {
+    let server = common::serve(ENDPOINT, "anthropic/empty_content").await;
+    let aegis = Aegis::new(
+        AegisConfig::new()
+            .with_anthropic("test-key".to_string())
+            .with_base_url(ProviderType::Anthropic, server.uri()),
+    );
+
+    let error = aegis
+        .send_message(ProviderType::Anthropic, vec![common::user_message("Hello")])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, AegisError::EmptyResponse));
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn matched_stop_sequence_is_reported() {
+    let server = common::serve(ENDPOINT, "anthropic/stop_sequence").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    assert_eq!(metadata.stop_sequence.as_deref(), Some("Observation:"));
+}
+
+#[tokio::test]
+async fn streamed_stop_sequence_is_reported() {
+    let server = common::serve(ENDPOINT, "anthropic/stream_stop_sequence").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    let metadata = accumulator.metadata().unwrap();
+    assert_eq!(metadata.stop_sequence.as_deref(), Some("Observation:"));
+}
+
+#[tokio::test]
+async fn stream_replays_recorded_events() {
+    let server = common::serve(ENDPOINT, "anthropic/stream_text").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut deltas = Vec::new();
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        let delta = delta.unwrap();
+        deltas.push(delta.content.to_string());
+        accumulator.push(&delta);
+    }
+
+    // The model on message_start, two texts, then the usage on message_delta
+    assert_eq!(deltas, ["", "The capital of Vietnam", " is Hanoi.", ""]);
+    assert_eq!(accumulator.text(), "The capital of Vietnam is Hanoi.");
+    let usage = accumulator.metadata().unwrap().usage.clone().unwrap();
+    assert_eq!(usage.prompt_tokens, 14);
+    assert_eq!(usage.completion_tokens, 10);
+}
+
+#[tokio::test]
+async fn citations_are_attached_to_text() {
+    let server = common::serve(ENDPOINT, "anthropic/citations").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    match &message.content.parts[..] {
+        [ContentPart::Text { text, annotations, .. }] => {
+            assert_eq!(text, "Hanoi is the capital of Vietnam.");
+            assert_eq!(
+                annotations,
+                &vec![Citation {
+                    url: Some("https://en.wikipedia.org/wiki/Hanoi".to_string()),
+                    title: Some("Hanoi - Wikipedia".to_string()),
+                    cited_text: Some(
+                        "Hanoi is the capital and second-most populous city of Vietnam."
+                            .to_string()
+                    ),
+                    start_index: None,
+                    end_index: None,
+                }]
+            );
+        }
+        parts => panic!("expected one text part, got {:?}", parts),
+    }
+}
+
+#[tokio::test]
+async fn streamed_citations_are_collected() {
+    let server = common::serve(ENDPOINT, "anthropic/stream_citations").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    let message = accumulator.into_message();
+    match &message.content.parts[..] {
+        [ContentPart::Text { text, annotations, .. }] => {
+            assert_eq!(text, "Hanoi is the capital of Vietnam.");
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].title.as_deref(), Some("Vietnam fact sheet"));
+            assert_eq!(annotations[0].start_index, Some(0));
+            assert_eq!(annotations[0].end_index, Some(32));
+        }
+        parts => panic!("expected one text part, got {:?}", parts),
+    }
+}
+
+#[tokio::test]
+async fn stream_rejects_error_status() {
+    let server = common::serve(ENDPOINT, "anthropic/unauthorized").await;
+
+    let result = provider(&server)
+        .stream_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn streamed_tool_use_is_assembled() {
+    let server = common::serve(ENDPOINT, "anthropic/stream_tool_use").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What's the weather in Hanoi?")],
+            &SendOptions::new().with_tools(vec![common::weather_tool()]),
+        )
+        .await
+        .unwrap();
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    let message = accumulator.into_message();
+    assert_eq!(common::text_parts(&message), vec!["Let me check the weather."]);
+    let calls = common::tool_calls(&message);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "toolu_01A9");
+    assert_eq!(calls[0].arguments, serde_json::json!({ "location": "Hanoi" }));
+    let usage = message.metadata.unwrap().usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 320);
+    assert_eq!(usage.completion_tokens, 48);
+}
+
+#[tokio::test]
+async fn malformed_tool_use_arguments_are_reported_as_incomplete() {
+    let server = common::serve(ENDPOINT, "anthropic/stream_tool_use_malformed").await;
+
+    let stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What's the weather in Hanoi?")],
+            &SendOptions::new().with_tools(vec![common::weather_tool()]),
+        )
+        .await
+        .unwrap();
+    let deltas: Vec<_> = stream.collect().await;
+
+    assert!(deltas
+        .iter()
+        .flatten()
+        .all(|message| common::tool_calls(message).is_empty()));
+    let errors: Vec<_> = deltas.iter().filter_map(|delta| delta.as_ref().err()).collect();
+    match errors[..] {
+        [AegisError::IncompleteToolCall {
+            id,
+            name,
+            partial_arguments,
+        }] => {
+            assert_eq!(id, "toolu_01A9");
+            assert_eq!(name, "get_weather");
+            assert_eq!(partial_arguments, "{\"location\": \"Ha");
+        }
+        ref other => panic!("expected an incomplete tool call, got {:?}", other),
+    }
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use aegis::{
+    error::AegisError,
+    models::{ContentPart, Message, Role, ToolCall},
+    options::SendOptions,
+    providers::{cohere::CohereProvider, Provider},
+};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer,
+};
+
+const ENDPOINT: &str = "/v2/chat";
+
+fn provider(server: &MockServer) -> CohereProvider {
+    CohereProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_response_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("authorization", "Bearer test-key"))
+        .respond_with(common::load("cohere/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(message.role, Role::Assistant));
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["The capital of Vietnam is Hanoi."]
+    );
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("cohere"));
+    assert_eq!(metadata.model.as_deref(), Some("command-r-plus"));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 71);
+    assert_eq!(usage.completion_tokens, 8);
+    assert_eq!(usage.total_tokens, 79);
+}
+
+#[tokio::test]
+async fn model_and_generation_params_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "model": "command-r",
+            "temperature": 0.0,
+            "max_tokens": 256,
+            "p": 0.9,
+            "stop_sequences": ["END"]
+        })))
+        .respond_with(common::load("cohere/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_model("command-r")
+        .with_temperature(0.0)
+        .with_max_tokens(256)
+        .with_top_p(0.9)
+        .with_stop_sequences(vec!["END".to_string()]);
+    provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn tools_are_rejected_instead_of_dropped() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(common::load("cohere/text").response())
+        .expect(0)
+        .mount(&server)
+        .await;
+    let question = common::user_message("What's the weather in Hanoi?");
+    let call = Message::new(
+        Role::Assistant,
+        vec![ContentPart::ToolCall(ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            arguments: serde_json::json!({ "location": "Hanoi" }),
+        })],
+    );
+
+    let with_tools = provider(&server)
+        .send_message(
+            vec![question.clone()],
+            &SendOptions::default().with_tools(vec![common::weather_tool()]),
+        )
+        .await;
+    let with_calls = provider(&server)
+        .stream_message(vec![question, call], &SendOptions::default())
+        .await;
+
+    assert!(matches!(with_tools, Err(AegisError::Unsupported(_))));
+    assert!(matches!(with_calls, Err(AegisError::Unsupported(_))));
+}
+
+#[tokio::test]
+async fn rate_limited_status_maps_to_rate_limit_error() {
+    let server = common::serve(ENDPOINT, "cohere/rate_limited").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::RateLimitExceeded { .. })));
+}
+
+#[tokio::test]
+async fn unauthorized_status_maps_to_invalid_api_key() {
+    let server = common::serve(ENDPOINT, "cohere/unauthorized").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn stream_yields_content_deltas_and_final_usage() {
+    let server = common::serve(ENDPOINT, "cohere/stream_text").await;
+
+    let chunks: Vec<_> = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    let text: String = chunks.iter().map(|c| c.content.to_string()).collect();
+    assert_eq!(text, "The capital of Vietnam is Hanoi.");
+    let usage = chunks
+        .last()
+        .and_then(|c| c.metadata.as_ref())
+        .and_then(|m| m.usage.as_ref())
+        .unwrap();
+    assert_eq!(usage.prompt_tokens, 71);
+    assert_eq!(usage.completion_tokens, 8);
+}
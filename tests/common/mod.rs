@@ -0,0 +1,118 @@
+//! Shared helpers for the recorded-fixture integration tests.
+//!
+//! Fixtures live under `tests/fixtures/<provider>/<name>.json` and record a
+//! provider response as `{ "status": .., "headers": {..}, "body": .. }`. A JSON
+//! `body` is replayed as `application/json`; a string `body` is replayed
+//! verbatim, which is how event-stream recordings are stored.
+
+#![allow(dead_code)]
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use aegis::models::{ContentPart, Message, ToolCall, ToolDefinition};
+use serde::Deserialize;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct Fixture {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub body: serde_json::Value,
+}
+
+impl Fixture {
+    pub fn response(&self) -> ResponseTemplate {
+        let mut template = ResponseTemplate::new(self.status);
+        for (name, value) in &self.headers {
+            template = template.insert_header(name.as_str(), value.as_str());
+        }
+        match &self.body {
+            serde_json::Value::String(raw) => template.set_body_raw(raw.clone(), "text/event-stream"),
+            body => template.set_body_json(body),
+        }
+    }
+}
+
+/// Load `tests/fixtures/<name>.json`, e.g. `load("anthropic/text")`.
+pub fn load(name: &str) -> Fixture {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{}.json", name));
+    let raw = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read fixture {}: {}", path.display(), e));
+    serde_json::from_str(&raw)
+        .unwrap_or_else(|e| panic!("failed to parse fixture {}: {}", path.display(), e))
+}
+
+/// Start a mock server that replays `fixture` for `POST <endpoint>`.
+pub async fn serve(endpoint: &str, fixture: &str) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(endpoint))
+        .respond_with(load(fixture).response())
+        .mount(&server)
+        .await;
+    server
+}
+
+pub fn user_message(text: &str) -> Message {
+    Message::user(text)
+}
+
+pub fn vision_message(text: &str, image_url: &str) -> Message {
+    let mut message = user_message(text);
+    message.content.parts.push(ContentPart::image(image_url));
+    message
+}
+
+pub fn text_parts(message: &Message) -> Vec<&str> {
+    message
+        .content
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn image_parts(message: &Message) -> Vec<&str> {
+    message
+        .content
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            ContentPart::Image { image_url, .. } => Some(image_url.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn tool_calls(message: &Message) -> Vec<&ToolCall> {
+    message
+        .content
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            ContentPart::ToolCall(call) => Some(call),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn weather_tool() -> ToolDefinition {
+    ToolDefinition {
+        name: "get_weather".to_string(),
+        description: "Get the current weather for a location".to_string(),
+        parameters: serde_json::json!({
+            "type": "object",
+            "properties": { "location": { "type": "string" } },
+            "required": ["location"]
+        }),
+    }
+}
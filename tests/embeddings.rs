@@ -0,0 +1,92 @@
+mod common;
+
+use aegis::{
+    config::AegisConfig,
+    embeddings::{jina::JinaProvider, voyage::VoyageProvider, EmbeddingProvider},
+    error::AegisError,
+    Aegis,
+};
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer,
+};
+
+const ENDPOINT: &str = "/v1/embeddings";
+
+fn inputs(texts: &[&str]) -> Vec<String> {
+    texts.iter().map(|text| text.to_string()).collect()
+}
+
+#[tokio::test]
+async fn voyage_returns_vectors_in_input_order() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("authorization", "Bearer test-key"))
+        .and(body_partial_json(serde_json::json!({
+            "model": "voyage-3",
+            "input": ["Hanoi", "Saigon"]
+        })))
+        .respond_with(common::load("voyage/embeddings").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let embeddings = VoyageProvider::new("test-key".to_string())
+        .with_base_url(server.uri())
+        .embed(inputs(&["Hanoi", "Saigon"]))
+        .await
+        .unwrap();
+
+    assert_eq!(embeddings.model, "voyage-3");
+    assert_eq!(embeddings.dimensions, 4);
+    assert_eq!(embeddings.vectors.len(), 2);
+    assert_eq!(embeddings.vectors[0][0], 0.0534);
+    assert_eq!(embeddings.vectors[1][0], 0.0121);
+    assert_eq!(embeddings.usage.unwrap().prompt_tokens, 11);
+}
+
+#[tokio::test]
+async fn jina_sends_the_configured_model() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(
+            serde_json::json!({ "model": "jina-embeddings-v3" }),
+        ))
+        .respond_with(common::load("jina/embeddings").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let embeddings = JinaProvider::new("test-key".to_string())
+        .with_base_url(server.uri())
+        .embed(inputs(&["Hanoi"]))
+        .await
+        .unwrap();
+
+    assert_eq!(embeddings.model, "jina-embeddings-v3");
+    assert_eq!(embeddings.dimensions, 3);
+}
+
+#[tokio::test]
+async fn unauthorized_maps_to_invalid_api_key() {
+    let server = common::serve(ENDPOINT, "voyage/unauthorized").await;
+
+    let result = VoyageProvider::new("bad-key".to_string())
+        .with_base_url(server.uri())
+        .embed(inputs(&["Hanoi"]))
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn embed_without_an_embedding_provider_is_not_found() {
+    // Chat providers alone don't serve embeddings
+    let aegis = Aegis::new(AegisConfig::new().with_openai("test-key".to_string()));
+
+    let result = aegis.embed(inputs(&["Hanoi"])).await;
+
+    assert!(matches!(result, Err(AegisError::ProviderNotFound)));
+}
@@ -0,0 +1,230 @@
+mod common;
+
+use aegis::{
+    error::AegisError,
+    models::{ContentPart, FinishReason, Message, Role, ToolCall},
+    options::SendOptions,
+    providers::{gemini::GeminiProvider, Provider},
+};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, query_param},
+    Mock, MockServer,
+};
+
+const ENDPOINT: &str = "/v1beta/models/gemini-1.5-flash:generateContent";
+const STREAM_ENDPOINT: &str = "/v1beta/models/gemini-1.5-flash:streamGenerateContent";
+
+fn provider(server: &MockServer) -> GeminiProvider {
+    GeminiProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_response_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("x-goog-api-key", "test-key"))
+        .and(body_partial_json(serde_json::json!({
+            "systemInstruction": { "parts": [{ "text": "Answer briefly." }] },
+            "contents": [{ "role": "user", "parts": [{ "text": "What is the capital of Vietnam?" }] }],
+        })))
+        .respond_with(common::load("gemini/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+    let system = Message::text(Role::System, "Answer briefly.");
+
+    let message = provider(&server)
+        .send_message(
+            vec![system, common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(message.role, Role::Assistant));
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["The capital of Vietnam is Hanoi."]
+    );
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("gemini"));
+    assert_eq!(metadata.model.as_deref(), Some("gemini-1.5-flash-002"));
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 9);
+    assert_eq!(usage.completion_tokens, 8);
+    assert_eq!(usage.total_tokens, 17);
+}
+
+#[tokio::test]
+async fn assistant_turns_are_sent_as_model() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1beta/models/gemini-1.5-pro:generateContent"))
+        .and(body_partial_json(serde_json::json!({
+            "contents": [
+                { "role": "user", "parts": [{ "text": "Capital of Vietnam?" }] },
+                { "role": "model", "parts": [{ "text": "Hanoi." }] },
+                { "role": "user", "parts": [{ "text": "And its population?" }] },
+            ],
+            "generationConfig": {
+                "maxOutputTokens": 64,
+                "topP": 0.5,
+                "stopSequences": ["\n\n"],
+            },
+        })))
+        .respond_with(common::load("gemini/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+    let reply = Message::text(Role::Assistant, "Hanoi.");
+    let options = SendOptions::default()
+        .with_model("gemini-1.5-pro")
+        .with_max_tokens(64)
+        .with_top_p(0.5)
+        .with_stop_sequences(vec!["\n\n".to_string()]);
+
+    provider(&server)
+        .send_message(
+            vec![
+                common::user_message("Capital of Vietnam?"),
+                reply,
+                common::user_message("And its population?"),
+            ],
+            &options,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn turns_without_text_are_skipped() {
+    let server = common::serve(ENDPOINT, "gemini/text").await;
+    let call = Message::new(
+        Role::Assistant,
+        vec![ContentPart::ToolCall(ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            arguments: serde_json::json!({ "location": "Hanoi" }),
+        })],
+    );
+    let result = Message::new(
+        Role::Tool,
+        vec![ContentPart::ToolResult {
+            tool_call_id: "call_1".to_string(),
+            content: "31°C and sunny".to_string(),
+        }],
+    );
+
+    provider(&server)
+        .send_message(
+            vec![common::user_message("Weather in Hanoi?"), call, result],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    assert_eq!(
+        body["contents"],
+        serde_json::json!([
+            { "role": "user", "parts": [{ "text": "Weather in Hanoi?" }] },
+            { "role": "user", "parts": [{ "text": "31°C and sunny" }] },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn tools_are_rejected_instead_of_dropped() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(common::load("gemini/text").response())
+        .expect(0)
+        .mount(&server)
+        .await;
+    let options = SendOptions::default().with_tools(vec![common::weather_tool()]);
+    let messages = vec![common::user_message("What's the weather in Hanoi?")];
+
+    let sent = provider(&server).send_message(messages.clone(), &options).await;
+    let streamed = provider(&server).stream_message(messages, &options).await;
+
+    assert!(matches!(sent, Err(AegisError::Unsupported(_))));
+    assert!(matches!(streamed, Err(AegisError::Unsupported(_))));
+}
+
+#[tokio::test]
+async fn rate_limited_status_maps_to_rate_limit_error() {
+    let server = common::serve(ENDPOINT, "gemini/rate_limited").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::RateLimitExceeded { .. })));
+}
+
+#[tokio::test]
+async fn invalid_key_maps_to_invalid_api_key() {
+    let server = common::serve(ENDPOINT, "gemini/unauthorized").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn safety_block_maps_to_content_filtered() {
+    let server = common::serve(ENDPOINT, "gemini/safety").await;
+
+    let error = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap_err();
+
+    let AegisError::ContentFiltered {
+        partial, metadata, ..
+    } = &error
+    else {
+        panic!("expected ContentFiltered, got {:?}", error);
+    };
+    assert_eq!(partial, "");
+    let usage = metadata.as_ref().and_then(|m| m.usage.as_ref()).unwrap();
+    assert_eq!(usage.prompt_tokens, 11);
+}
+
+#[tokio::test]
+async fn stream_yields_text_deltas_and_final_usage() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(STREAM_ENDPOINT))
+        .and(query_param("alt", "sse"))
+        .respond_with(common::load("gemini/stream_text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let chunks: Vec<_> = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    let text: String = chunks.iter().map(|c| c.content.to_string()).collect();
+    assert_eq!(text, "The capital of Vietnam is Hanoi.");
+    assert!(chunks[0].metadata.is_none());
+    let metadata = chunks.last().and_then(|c| c.metadata.as_ref()).unwrap();
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    let usage = metadata.usage.as_ref().unwrap();
+    assert_eq!(usage.prompt_tokens, 9);
+    assert_eq!(usage.completion_tokens, 8);
+}
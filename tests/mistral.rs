@@ -0,0 +1,163 @@
+mod common;
+
+use aegis::{
+    error::AegisError,
+    models::{FinishReason, Role, ToolChoice},
+    options::{MistralOptions, SendOptions},
+    providers::{mistral::MistralProvider, Provider},
+};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer,
+};
+
+const ENDPOINT: &str = "/v1/chat/completions";
+
+fn provider(server: &MockServer) -> MistralProvider {
+    MistralProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_response_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("authorization", "Bearer test-key"))
+        .and(body_partial_json(serde_json::json!({ "model": "mistral-large-latest" })))
+        .respond_with(common::load("mistral/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(message.role, Role::Assistant));
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["The capital of Vietnam is Hanoi."]
+    );
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("mistral"));
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 12);
+    assert_eq!(usage.completion_tokens, 9);
+    assert_eq!(usage.total_tokens, 21);
+}
+
+#[tokio::test]
+async fn safe_prompt_is_sent_when_set() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({ "safe_prompt": true })))
+        .respond_with(common::load("mistral/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new().with_mistral(MistralOptions::new().with_safe_prompt(true));
+    provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn model_and_generation_params_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "model": "mistral-small-latest",
+            "temperature": 0.0,
+            "max_tokens": 256,
+            "top_p": 0.9,
+            "stop": ["END"]
+        })))
+        .respond_with(common::load("mistral/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_model("mistral-small-latest")
+        .with_temperature(0.0)
+        .with_max_tokens(256)
+        .with_top_p(0.9)
+        .with_stop_sequences(vec!["END".to_string()]);
+    provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn specific_tool_choice_uses_the_openai_shape() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "tool_choice": { "type": "function", "function": { "name": "get_weather" } }
+        })))
+        .respond_with(common::load("mistral/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_tools(vec![common::weather_tool()])
+        .with_tool_choice(ToolChoice::Specific("get_weather".to_string()));
+    provider(&server)
+        .send_message(vec![common::user_message("Weather in Hanoi?")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn unauthorized_status_maps_to_invalid_api_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .respond_with(wiremock::ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn stream_yields_content_deltas_and_final_usage() {
+    let server = common::serve(ENDPOINT, "mistral/stream_text").await;
+
+    let chunks: Vec<_> = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    let text: String = chunks.iter().map(|c| c.content.to_string()).collect();
+    assert_eq!(text, "The capital of Vietnam is Hanoi.");
+    let metadata = chunks.last().and_then(|c| c.metadata.as_ref()).unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("mistral"));
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    let usage = metadata.usage.as_ref().unwrap();
+    assert_eq!(usage.prompt_tokens, 12);
+    assert_eq!(usage.completion_tokens, 9);
+}
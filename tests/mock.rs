@@ -0,0 +1,129 @@
+mod common;
+
+use std::time::Duration;
+
+use aegis::{
+    config::AegisConfig,
+    error::AegisError,
+    models::{FinishReason, ProviderType},
+    providers::mock::MockProvider,
+    stream::{record_stream, StreamAccumulator, StreamRecording},
+    Aegis,
+};
+use futures::{stream, StreamExt};
+
+fn mock() -> ProviderType {
+    ProviderType::Custom("mock".to_string())
+}
+
+#[tokio::test]
+async fn echoes_the_last_user_message_without_a_script() {
+    let aegis = Aegis::new(AegisConfig::new().with_mock(MockProvider::new()));
+
+    let message = aegis
+        .send_message(mock(), vec![common::user_message("Xin chào")])
+        .await
+        .unwrap();
+
+    assert_eq!(common::text_parts(&message), vec!["Xin chào"]);
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("mock"));
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+}
+
+#[tokio::test]
+async fn plays_the_script_in_order_then_echoes() {
+    let provider = MockProvider::new()
+        .with_reply("Hanoi.")
+        .with_error(|| AegisError::RateLimitExceeded { retry_after: None })
+        .with_reply("Still Hanoi.");
+    let aegis = Aegis::new(AegisConfig::new().with_mock(provider));
+    let ask = || aegis.send_message(mock(), vec![common::user_message("Capital?")]);
+
+    assert_eq!(common::text_parts(&ask().await.unwrap()), vec!["Hanoi."]);
+    assert!(matches!(
+        ask().await,
+        Err(AegisError::RateLimitExceeded { .. })
+    ));
+    assert_eq!(common::text_parts(&ask().await.unwrap()), vec!["Still Hanoi."]);
+    assert_eq!(common::text_parts(&ask().await.unwrap()), vec!["Capital?"]);
+}
+
+#[tokio::test]
+async fn streams_the_reply_word_by_word() {
+    let provider = MockProvider::new()
+        .with_name("demo")
+        .with_reply("The capital of Vietnam is Hanoi.");
+    let aegis = Aegis::new(AegisConfig::new().with_mock(provider));
+
+    let deltas: Vec<_> = aegis
+        .stream_message(
+            ProviderType::Custom("demo".to_string()),
+            vec![common::user_message("Capital?")],
+        )
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    assert_eq!(deltas.len(), 7);
+    let mut accumulator = StreamAccumulator::new();
+    for delta in &deltas {
+        accumulator.push(delta.as_ref().unwrap());
+    }
+    assert_eq!(accumulator.text(), "The capital of Vietnam is Hanoi.");
+    let metadata = accumulator.into_message().metadata.unwrap();
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+}
+
+#[tokio::test(start_paused = true)]
+async fn replies_after_the_configured_latency() {
+    let provider = MockProvider::new().with_latency(Duration::from_millis(250));
+    let aegis = Aegis::new(AegisConfig::new().with_mock(provider));
+    let started = tokio::time::Instant::now();
+
+    aegis
+        .send_message(mock(), vec![common::user_message("Hello")])
+        .await
+        .unwrap();
+
+    assert_eq!(started.elapsed(), Duration::from_millis(250));
+}
+
+#[tokio::test]
+async fn recorded_stream_replays_identically() {
+    let live = Aegis::new(
+        AegisConfig::new().with_mock(MockProvider::new().with_reply("Hanoi is the capital.")),
+    );
+    let stream = live
+        .stream_message(mock(), vec![common::user_message("Capital?")])
+        .await
+        .unwrap();
+    let idle = stream::once(async { Err(AegisError::StreamIdle(Duration::from_millis(1500))) });
+    let (stream, recorder) = record_stream(Box::pin(stream.chain(idle)));
+    let original: Vec<_> = stream.collect().await;
+
+    let fixture = serde_json::to_string(&recorder.recording()).unwrap();
+    let recording: StreamRecording = serde_json::from_str(&fixture).unwrap();
+    let replayed = Aegis::new(
+        AegisConfig::new().with_mock(MockProvider::new().with_recording(recording)),
+    );
+    let replay: Vec<_> = replayed
+        .stream_message(mock(), vec![common::user_message("Anything")])
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    let as_json = |items: &[Result<_, AegisError>]| {
+        items
+            .iter()
+            .map(|item| serde_json::to_value(item.as_ref().unwrap()).unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(original.len(), 6);
+    assert_eq!(replay.len(), 6);
+    assert_eq!(as_json(&replay[..5]), as_json(&original[..5]));
+    let idle = Duration::from_millis(1500);
+    assert!(matches!(replay[5], Err(AegisError::StreamIdle(d)) if d == idle));
+}
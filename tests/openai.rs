@@ -0,0 +1,701 @@
+mod common;
+
+use aegis::{
+    batch::{BatchId, BatchRequest, BatchStatus},
+    error::AegisError,
+    models::{ContentPart, FinishReason, Role},
+    options::{OpenAIOptions, SendOptions},
+    providers::{openai::OpenAIProvider, Provider},
+    stream::StreamAccumulator,
+};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, body_string_contains, header, header_regex, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const ENDPOINT: &str = "/v1/chat/completions";
+
+fn provider(server: &MockServer) -> OpenAIProvider {
+    OpenAIProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_response_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("authorization", "Bearer test-key"))
+        .respond_with(common::load("openai/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(message.role, Role::Assistant));
+    assert_eq!(common::text_parts(&message), vec!["The capital of Vietnam is Hanoi."]);
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("openai"));
+    assert_eq!(metadata.system_fingerprint.as_deref(), Some("fp_3bc1b5746c"));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 15);
+    assert_eq!(usage.completion_tokens, 8);
+    assert_eq!(usage.total_tokens, 23);
+}
+
+#[tokio::test]
+async fn requested_model_is_sent_and_reported() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({ "model": "gpt-4o" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": "Hanoi." } }]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::new().with_model("gpt-4o"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(message.metadata.unwrap().model.as_deref(), Some("gpt-4o"));
+}
+
+#[tokio::test]
+async fn echoed_model_wins_over_requested_alias() {
+    let server = common::serve(ENDPOINT, "openai/resolved_model").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::new().with_model("gpt-4o"),
+        )
+        .await
+        .unwrap();
+
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.model.as_deref(), Some("gpt-4o-2024-08-06"));
+}
+
+#[tokio::test]
+async fn deprecation_warnings_are_captured_from_headers_and_body() {
+    let server = common::serve(ENDPOINT, "openai/deprecated_model").await;
+
+    let message = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        message.metadata.unwrap().warnings,
+        [
+            "openai-deprecation: gpt-4-0314 will be retired on 2024-06-13; migrate to gpt-4o",
+            "This model version is deprecated. Migrate before June 13, 2024.",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rate_limit_headers_are_exposed() {
+    let server = common::serve(ENDPOINT, "openai/text").await;
+    let provider = provider(&server);
+    assert!(provider.last_rate_limit_status().is_none());
+
+    provider
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap();
+
+    let status = provider.last_rate_limit_status().unwrap();
+    assert_eq!(status.requests_limit, Some(500));
+    assert_eq!(status.requests_remaining, Some(499));
+    assert_eq!(status.tokens_remaining, Some(29977));
+    assert_eq!(status.tokens_reset.as_deref(), Some("46ms"));
+}
+
+#[tokio::test]
+async fn prediction_is_sent_and_its_usage_reported() {
+    let current = "fn capital() -> &'static str {\n    \"Saigon\"\n}\n";
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "prediction": { "type": "content", "content": current }
+        })))
+        .respond_with(common::load("openai/prediction").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new().with_openai(OpenAIOptions::new().with_prediction(current));
+    let response = provider(&server)
+        .send_message(vec![common::user_message("Make it return Hanoi.")], &options)
+        .await
+        .unwrap();
+
+    let usage = response.metadata.unwrap().usage.unwrap();
+    assert_eq!(usage.accepted_prediction_tokens, Some(14));
+    assert_eq!(usage.rejected_prediction_tokens, Some(2));
+}
+
+#[tokio::test]
+async fn multiple_choices_are_returned_in_order() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({ "n": 3 })))
+        .respond_with(common::load("openai/multiple_choices").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let completions = provider(&server)
+        .send_message_n(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            3,
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let texts: Vec<_> = completions
+        .choices
+        .iter()
+        .map(|choice| common::text_parts(choice).concat())
+        .collect();
+    assert_eq!(
+        texts,
+        ["Hanoi.", "The capital of Vietnam is Hanoi, in the north of", "It's Hanoi."]
+    );
+    let reasons: Vec<_> = completions
+        .choices
+        .iter()
+        .map(|choice| choice.metadata.as_ref().unwrap().finish_reason.clone())
+        .collect();
+    assert_eq!(
+        reasons,
+        [
+            Some(FinishReason::Stop),
+            Some(FinishReason::MaxTokens),
+            Some(FinishReason::Stop)
+        ]
+    );
+    assert_eq!(completions.usage.prompt_tokens, 15);
+    assert_eq!(completions.usage.completion_tokens, 22);
+    assert_eq!(completions.usage.total_tokens, 37);
+}
+
+#[tokio::test]
+async fn serialized_tool_calls_parse_single_call() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "parallel_tool_calls": false,
+            "tools": [{ "type": "function", "function": { "name": "get_weather" } }]
+        })))
+        .respond_with(common::load("openai/tool_call").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_tools(vec![common::weather_tool()])
+        .with_parallel_tool_calls(false);
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What's the weather in Hanoi?")],
+            &options,
+        )
+        .await
+        .unwrap();
+
+    assert!(common::text_parts(&message).is_empty());
+    let calls = common::tool_calls(&message);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "call_abc123");
+    assert_eq!(calls[0].name, "get_weather");
+    assert_eq!(
+        calls[0].arguments,
+        serde_json::json!({ "location": "Hanoi, Vietnam" })
+    );
+}
+
+#[tokio::test]
+async fn image_output_round_trips_with_text() {
+    let server = common::serve(ENDPOINT, "openai/image_output").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("Draw a red lantern")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(common::text_parts(&message), vec!["Here is a red lantern:"]);
+    let images = common::image_parts(&message);
+    assert_eq!(images.len(), 1);
+    assert!(images[0].starts_with("data:image/png;base64,iVBORw0KGgo"));
+}
+
+#[tokio::test]
+async fn url_citations_are_attached_to_text() {
+    let server = common::serve(ENDPOINT, "openai/citations").await;
+
+    let message = provider(&server)
+        .send_message(vec![common::user_message("Capital of Vietnam?")], &SendOptions::default())
+        .await
+        .unwrap();
+
+    match &message.content.parts[..] {
+        [ContentPart::Text { annotations, .. }] => {
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(
+                annotations[0].url.as_deref(),
+                Some("https://en.wikipedia.org/wiki/Hanoi")
+            );
+            assert_eq!(annotations[0].title.as_deref(), Some("Hanoi - Wikipedia"));
+            assert_eq!(annotations[0].start_index, Some(32));
+            assert_eq!(annotations[0].end_index, Some(86));
+        }
+        parts => panic!("expected one text part, got {:?}", parts),
+    }
+}
+
+#[tokio::test]
+async fn url_citations_are_attached_to_text_parts() {
+    let server = MockServer::start().await;
+    let mut fixture = common::load("openai/citations");
+    let message = &mut fixture.body["choices"][0]["message"];
+    let text = message["content"].take();
+    message["content"] = serde_json::json!([{ "type": "text", "text": text }]);
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .respond_with(fixture.response())
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(vec![common::user_message("Capital of Vietnam?")], &SendOptions::default())
+        .await
+        .unwrap();
+
+    match &message.content.parts[..] {
+        [ContentPart::Text { text, annotations, .. }] => {
+            assert!(text.starts_with("Hanoi is the capital of Vietnam"));
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(
+                annotations[0].url.as_deref(),
+                Some("https://en.wikipedia.org/wiki/Hanoi")
+            );
+        }
+        parts => panic!("expected one text part, got {:?}", parts),
+    }
+}
+
+#[tokio::test]
+async fn streamed_url_citations_are_collected() {
+    let server = common::serve(ENDPOINT, "openai/stream_citations").await;
+
+    let mut stream = provider(&server)
+        .stream_message(vec![common::user_message("Capital of Vietnam?")], &SendOptions::default())
+        .await
+        .unwrap();
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    let message = accumulator.into_message();
+    match &message.content.parts[..] {
+        [ContentPart::Text { text, annotations, .. }] => {
+            assert_eq!(
+                text,
+                "Hanoi is the capital of Vietnam ([wikipedia.org](https://en.wikipedia.org/wiki/Hanoi))."
+            );
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(
+                annotations[0].url.as_deref(),
+                Some("https://en.wikipedia.org/wiki/Hanoi")
+            );
+            assert_eq!(annotations[0].start_index, Some(32));
+            assert_eq!(annotations[0].end_index, Some(86));
+        }
+        parts => panic!("expected one text part, got {:?}", parts),
+    }
+}
+
+#[tokio::test]
+async fn schema_drift_falls_back_to_text() {
+    let server = common::serve(ENDPOINT, "openai/schema_drift").await;
+
+    let message = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["The capital of Vietnam is Hanoi."]
+    );
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.model.as_deref(), Some("gpt-4-turbo-preview"));
+    assert!(metadata.usage.is_none());
+}
+
+#[tokio::test]
+async fn unrecoverable_body_reports_path_and_snippet() {
+    let server = common::serve(ENDPOINT, "openai/unrecognized").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    match result {
+        Err(AegisError::ResponseParseError { path, snippet, .. }) => {
+            assert_eq!(path, "choices");
+            assert!(snippet.contains("\"choices\":\"unavailable\""));
+        }
+        other => panic!("expected ResponseParseError, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn vision_request_returns_text_description() {
+    let server = common::serve(ENDPOINT, "openai/vision").await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::vision_message(
+                "Describe this image.",
+                "https://example.com/lantern.png",
+            )],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["A red lantern hanging over a street in Hoi An."]
+    );
+}
+
+#[tokio::test]
+async fn rate_limited_status_maps_to_rate_limit_error() {
+    let server = common::serve(ENDPOINT, "openai/rate_limited").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::RateLimitExceeded { .. })));
+}
+
+#[tokio::test]
+async fn unauthorized_status_maps_to_invalid_api_key() {
+    let server = common::serve(ENDPOINT, "openai/unauthorized").await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn empty_choices_are_a_retryable_empty_response() {
+    let server = common::serve(ENDPOINT, "openai/empty_choices").await;
+
+    let error = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, AegisError::EmptyResponse));
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn content_filter_reports_the_triggered_categories() {
+    let server = common::serve(ENDPOINT, "openai/content_filter").await;
+
+    let error = provider(&server)
+        .send_message(vec![common::user_message("Describe the siege.")], &SendOptions::default())
+        .await
+        .unwrap_err();
+
+    let AegisError::ContentFiltered {
+        categories,
+        partial,
+        metadata,
+    } = &error
+    else {
+        panic!("expected ContentFiltered, got {:?}", error);
+    };
+    assert_eq!(categories, &["violence"]);
+    assert_eq!(partial, "The siege ended when");
+    let usage = metadata.as_ref().and_then(|m| m.usage.as_ref()).unwrap();
+    assert_eq!(usage.prompt_tokens, 21);
+    assert_eq!(usage.completion_tokens, 4);
+    assert!(!error.is_retryable());
+}
+
+#[tokio::test]
+async fn stream_replays_recorded_events() {
+    let server = common::serve(ENDPOINT, "openai/stream_text").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut deltas = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        deltas.push(chunk.unwrap().content.to_string());
+    }
+
+    // Two texts, then the finish reason on its own
+    assert_eq!(deltas, ["The capital of Vietnam", " is Hanoi.", ""]);
+    assert_eq!(deltas.concat(), "The capital of Vietnam is Hanoi.");
+}
+
+#[tokio::test]
+async fn keepalives_between_chunks_are_ignored() {
+    let server = common::serve(ENDPOINT, "openai/stream_keepalive").await;
+
+    let stream = provider(&server)
+        .stream_message(vec![common::user_message("Capital?")], &SendOptions::default())
+        .await
+        .unwrap();
+    let deltas: Vec<_> = stream.collect().await;
+
+    // The same deltas as without the keepalives: two texts and the finish
+    assert_eq!(deltas.len(), 3);
+    let text: String = deltas.iter().map(|d| d.as_ref().unwrap().content.to_string()).collect();
+    assert_eq!(text, "The capital of Vietnam is Hanoi.");
+}
+
+#[tokio::test]
+async fn streamed_reasoning_is_kept_apart_from_the_answer() {
+    let server = common::serve(ENDPOINT, "openai/stream_reasoning").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut accumulator = StreamAccumulator::default();
+    while let Some(chunk) = stream.next().await {
+        accumulator.push(&chunk.unwrap());
+    }
+    let message = accumulator.into_message();
+
+    assert_eq!(message.content.to_string(), "The capital of Vietnam is Hanoi.");
+    let reasoning: Vec<&str> = message
+        .content
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            ContentPart::Reasoning { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(reasoning, ["The user asks for Vietnam's capital. That is Hanoi."]);
+}
+
+#[tokio::test]
+async fn raw_stream_yields_the_undecoded_body() {
+    let server = common::serve(ENDPOINT, "openai/stream_text").await;
+
+    let mut stream = provider(&server)
+        .stream_raw(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        bytes.extend_from_slice(&chunk.unwrap());
+    }
+
+    let expected = common::load("openai/stream_text").body;
+    assert_eq!(String::from_utf8(bytes).unwrap(), expected.as_str().unwrap());
+}
+
+#[tokio::test]
+async fn streamed_tool_call_fragments_are_assembled() {
+    let server = common::serve(ENDPOINT, "openai/stream_tool_call").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What's the weather in Hanoi?")],
+            &SendOptions::new().with_tools(vec![common::weather_tool()]),
+        )
+        .await
+        .unwrap();
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    let message = accumulator.into_message();
+    assert_eq!(common::text_parts(&message), vec!["Let me check."]);
+    let calls = common::tool_calls(&message);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "call_Vx3kQ9");
+    assert_eq!(calls[0].name, "get_weather");
+    assert_eq!(calls[0].arguments, serde_json::json!({ "location": "Hanoi" }));
+    assert_eq!(
+        message.metadata.unwrap().finish_reason,
+        Some(FinishReason::ToolCalls)
+    );
+}
+
+#[tokio::test]
+async fn truncated_tool_call_ends_the_stream_with_an_error() {
+    let server = common::serve(ENDPOINT, "openai/stream_tool_call_truncated").await;
+
+    let stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What's the weather in Hanoi?")],
+            &SendOptions::new().with_tools(vec![common::weather_tool()]),
+        )
+        .await
+        .unwrap();
+    let deltas: Vec<_> = stream.collect().await;
+
+    assert!(deltas[..deltas.len() - 1].iter().all(|delta| {
+        delta.as_ref().is_ok_and(|m| common::tool_calls(m).is_empty())
+    }));
+    match deltas.last() {
+        Some(Err(AegisError::IncompleteToolCall {
+            id,
+            name,
+            partial_arguments,
+        })) => {
+            assert_eq!(id, "call_Vx3kQ9");
+            assert_eq!(name, "get_weather");
+            assert_eq!(partial_arguments, "{\"location\": \"Ha");
+        }
+        other => panic!("expected an incomplete tool call, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn stream_rejects_error_status() {
+    let server = common::serve(ENDPOINT, "openai/unauthorized").await;
+
+    let result = provider(&server)
+        .stream_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn batch_submit_uploads_jsonl_then_creates_the_batch() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/files"))
+        .and(header_regex("content-type", "^multipart/form-data; boundary="))
+        .and(body_string_contains("name=\"purpose\"\r\n\r\nbatch"))
+        .and(body_string_contains("\"custom_id\":\"vietnam\""))
+        .and(body_string_contains("\"url\":\"/v1/chat/completions\""))
+        .respond_with(common::load("openai/file_uploaded").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/batches"))
+        .and(body_partial_json(serde_json::json!({
+            "input_file_id": "file-Bq2n7Xk4",
+            "endpoint": "/v1/chat/completions",
+            "completion_window": "24h"
+        })))
+        .respond_with(common::load("openai/batch_created").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let id = provider(&server)
+        .batch_submit(vec![BatchRequest::new(
+            "vietnam",
+            vec![common::user_message("What is the capital of Vietnam?")],
+        )])
+        .await
+        .unwrap();
+
+    assert_eq!(id, BatchId("batch_67a1c9e2f0".to_string()));
+}
+
+#[tokio::test]
+async fn batch_with_repeated_custom_ids_is_rejected_before_upload() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(common::load("openai/file_uploaded").response())
+        .expect(0)
+        .mount(&server)
+        .await;
+    let request = |id| BatchRequest::new(id, vec![common::user_message("Capital of Vietnam?")]);
+
+    let result = provider(&server)
+        .batch_submit(vec![request("vietnam"), request("laos"), request("vietnam")])
+        .await;
+
+    assert!(matches!(result, Err(AegisError::APIError(message)) if message.contains("vietnam")));
+}
+
+#[tokio::test]
+async fn batch_results_collect_successes_and_failures() {
+    let server = MockServer::start().await;
+    for (endpoint, fixture) in [
+        ("/v1/batches/batch_67a1c9e2f0", "openai/batch_completed"),
+        ("/v1/files/file-out9Tz/content", "openai/batch_output"),
+        ("/v1/files/file-err3Lm/content", "openai/batch_errors"),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(common::load(fixture).response())
+            .mount(&server)
+            .await;
+    }
+    let provider = provider(&server);
+    let id = BatchId("batch_67a1c9e2f0".to_string());
+
+    assert_eq!(provider.batch_poll(&id).await.unwrap(), BatchStatus::Completed);
+    let results = provider.batch_results(&id).await.unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].custom_id, "vietnam");
+    let message = results[0].result.as_ref().unwrap();
+    assert_eq!(message.content.to_string(), "Hanoi.");
+    assert_eq!(
+        message.metadata.as_ref().unwrap().model.as_deref(),
+        Some("gpt-4o-mini-2024-07-18")
+    );
+    assert_eq!(results[1].custom_id, "atlantis");
+    assert!(matches!(results[1].result, Err(AegisError::APIError(_))));
+}
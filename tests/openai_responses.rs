@@ -0,0 +1,180 @@
+mod common;
+
+use aegis::{
+    options::{OpenAIOptions, SendOptions},
+    providers::{openai_responses::OpenAIResponsesProvider, Provider},
+    stream::StreamAccumulator,
+};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer,
+};
+
+const ENDPOINT: &str = "/v1/responses";
+
+fn provider(server: &MockServer) -> OpenAIResponsesProvider {
+    OpenAIResponsesProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_output_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("authorization", "Bearer test-key"))
+        .and(body_partial_json(serde_json::json!({
+            "input": [{
+                "type": "message",
+                "role": "user",
+                "content": [{ "type": "input_text", "text": "What is the capital of Vietnam?" }]
+            }]
+        })))
+        .respond_with(common::load("openai_responses/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["The capital of Vietnam is Hanoi."]
+    );
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.model.as_deref(), Some("gpt-4o-2024-08-06"));
+    assert_eq!(
+        metadata.response_id.as_deref(),
+        Some("resp_67ccd2bed1ec8190b14f964abc0542670bb6a6b452d3795b")
+    );
+    assert_eq!(metadata.usage.unwrap().total_tokens, 22);
+}
+
+#[tokio::test]
+async fn previous_response_id_is_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "previous_response_id": "resp_previous"
+        })))
+        .respond_with(common::load("openai_responses/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_openai(OpenAIOptions::new().with_previous_response_id("resp_previous".to_string()));
+    provider(&server)
+        .send_message(vec![common::user_message("And of Laos?")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn model_and_generation_params_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "model": "gpt-4o-mini",
+            "temperature": 0.0,
+            "max_output_tokens": 256,
+            "top_p": 0.9
+        })))
+        .respond_with(common::load("openai_responses/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_model("gpt-4o-mini")
+        .with_temperature(0.0)
+        .with_max_tokens(256)
+        .with_top_p(0.9);
+    provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn requested_model_is_reported_when_the_response_omits_it() {
+    let server = MockServer::start().await;
+    let mut fixture = common::load("openai_responses/text");
+    fixture.body.as_object_mut().unwrap().remove("model");
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .respond_with(fixture.response())
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new().with_model("gpt-4o-mini");
+    let message = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+
+    assert_eq!(message.metadata.unwrap().model.as_deref(), Some("gpt-4o-mini"));
+}
+
+#[tokio::test]
+async fn function_call_output_converts_to_tool_call() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "tools": [{ "type": "function", "name": "get_weather" }]
+        })))
+        .respond_with(common::load("openai_responses/function_call").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new().with_tools(vec![common::weather_tool()]);
+    let message = provider(&server)
+        .send_message(vec![common::user_message("Weather in Hanoi?")], &options)
+        .await
+        .unwrap();
+
+    let calls = common::tool_calls(&message);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "call_unLAR8MvFNptuiZK6K6HCy5k");
+    assert_eq!(calls[0].name, "get_weather");
+    assert_eq!(
+        calls[0].arguments,
+        serde_json::json!({ "location": "Hanoi" })
+    );
+}
+
+#[tokio::test]
+async fn stream_yields_text_deltas_and_final_metadata() {
+    let server = common::serve(ENDPOINT, "openai_responses/stream_text").await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    assert_eq!(accumulator.text(), "The capital of Vietnam is Hanoi.");
+    let metadata = accumulator.metadata().unwrap();
+    assert_eq!(
+        metadata.response_id.as_deref(),
+        Some("resp_67c9fdcecf488190bdd9a0409de3a1ec07b8b0ad4e5eb654")
+    );
+    assert_eq!(metadata.usage.as_ref().unwrap().completion_tokens, 8);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use aegis::{
+    error::AegisError,
+    models::{FinishReason, Role},
+    options::SendOptions,
+    providers::{xai::XaiProvider, Provider},
+    stream::StreamAccumulator,
+};
+use futures::StreamExt;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer,
+};
+
+const ENDPOINT: &str = "/v1/chat/completions";
+
+fn provider(server: &MockServer) -> XaiProvider {
+    XaiProvider::new("test-key".to_string()).with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn text_response_converts_to_assistant_message() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(header("authorization", "Bearer test-key"))
+        .and(body_partial_json(serde_json::json!({ "model": "grok-2-latest" })))
+        .respond_with(common::load("xai/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let message = provider(&server)
+        .send_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(message.role, Role::Assistant));
+    assert_eq!(
+        common::text_parts(&message),
+        vec!["The capital of Vietnam is Hanoi."]
+    );
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("xai"));
+    assert_eq!(metadata.model.as_deref(), Some("grok-2-1212"));
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 15);
+    assert_eq!(usage.completion_tokens, 8);
+    assert_eq!(usage.total_tokens, 23);
+}
+
+#[tokio::test]
+async fn model_and_generation_params_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "model": "grok-2-mini",
+            "temperature": 0.0,
+            "max_tokens": 256,
+            "top_p": 0.9,
+            "stop": ["END"]
+        })))
+        .respond_with(common::load("xai/text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let options = SendOptions::new()
+        .with_model("grok-2-mini")
+        .with_temperature(0.0)
+        .with_max_tokens(256)
+        .with_top_p(0.9)
+        .with_stop_sequences(vec!["END".to_string()]);
+    provider(&server)
+        .send_message(vec![common::user_message("Hello")], &options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn unauthorized_status_maps_to_invalid_api_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .respond_with(wiremock::ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    let result = provider(&server)
+        .send_message(vec![common::user_message("Hello")], &SendOptions::default())
+        .await;
+
+    assert!(matches!(result, Err(AegisError::InvalidAPIKey)));
+}
+
+#[tokio::test]
+async fn stream_requests_and_reports_usage() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(ENDPOINT))
+        .and(body_partial_json(serde_json::json!({
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        })))
+        .respond_with(common::load("xai/stream_text").response())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut stream = provider(&server)
+        .stream_message(
+            vec![common::user_message("What is the capital of Vietnam?")],
+            &SendOptions::default(),
+        )
+        .await
+        .unwrap();
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(delta) = stream.next().await {
+        accumulator.push(&delta.unwrap());
+    }
+
+    let message = accumulator.into_message();
+    assert_eq!(message.content.to_string(), "The capital of Vietnam is Hanoi.");
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.provider.as_deref(), Some("xai"));
+    assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+    let usage = metadata.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 15);
+    assert_eq!(usage.completion_tokens, 8);
+}